@@ -0,0 +1,211 @@
+use quick_xml::events::{BytesStart, Event};
+use url::Url;
+
+use super::ContentTransform;
+
+/// Attribute put on block elements, holding their anchor.
+const ANCHOR_ATTR: &str = "data-og-id";
+
+/// Fragment of URLs holding a `TextPosition`, before the anchor.
+const POSITION_FRAGMENT_PREFIX: &str = "og-pos=";
+
+pub(super) fn is_block(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"address"
+            | b"article"
+            | b"aside"
+            | b"blockquote"
+            | b"dd"
+            | b"details"
+            | b"div"
+            | b"dl"
+            | b"dt"
+            | b"figcaption"
+            | b"figure"
+            | b"footer"
+            | b"h1"
+            | b"h2"
+            | b"h3"
+            | b"h4"
+            | b"h5"
+            | b"h6"
+            | b"header"
+            | b"hr"
+            | b"li"
+            | b"main"
+            | b"nav"
+            | b"ol"
+            | b"p"
+            | b"pre"
+            | b"section"
+            | b"summary"
+            | b"table"
+            | b"td"
+            | b"th"
+            | b"tr"
+            | b"ul"
+    )
+}
+
+/// Gives block elements in `<body>` a `data-og-id` with their path from `<body>`, e.g.,
+/// `2.0` for the first child of the third child, counting all elements. The same document
+/// always gets the same anchors, whatever the settings, if this pass runs first.
+#[derive(Default)]
+pub struct BlockAnchors {
+    /// Number of child elements seen so far, for `<body>` and each open element in it.
+    counts: Vec<usize>,
+    /// Indexes of the open elements in `<body>`.
+    path: Vec<usize>,
+    /// Counts to start from, last first, for `<body>` and the elements opened first.
+    resume: Vec<usize>,
+}
+
+impl BlockAnchors {
+    /// Anchors a part of a split document as in the whole document, `path` being that of
+    /// the first element of the part in the whole, see `DocumentSplit`.
+    pub fn resuming(mut path: Vec<usize>) -> Self {
+        path.reverse();
+        Self {
+            resume: path,
+            ..Default::default()
+        }
+    }
+
+    /// Starts counting the child elements of the element just opened.
+    fn open(&mut self) {
+        self.counts.push(self.resume.pop().unwrap_or_default());
+    }
+
+    /// Counts `e` as a child of the current element, and returns it with an anchor if it
+    /// is a block. `path` then ends with its index.
+    fn enter(&mut self, e: &BytesStart<'_>) -> Option<BytesStart<'static>> {
+        let count = self.counts.last_mut()?;
+        self.path.push(*count);
+        *count += 1;
+        if !is_block(e.local_name().as_ref()) {
+            return None;
+        }
+
+        let anchor = self
+            .path
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        let mut e = e.to_owned();
+        e.push_attribute((ANCHOR_ATTR, anchor.as_str()));
+        Some(e)
+    }
+}
+
+impl ContentTransform for BlockAnchors {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) if self.counts.is_empty() => {
+                if e.local_name().as_ref() == b"body" {
+                    self.open();
+                }
+                out.push(event);
+            }
+            Event::Start(ref e) => {
+                let anchored = self.enter(e);
+                self.open();
+                out.push(anchored.map_or(event, Event::Start));
+            }
+            Event::Empty(ref e) => {
+                let anchored = self.enter(e);
+                self.path.pop();
+                out.push(anchored.map_or(event, Event::Empty));
+            }
+            Event::End(_) => {
+                self.counts.pop();
+                self.path.pop();
+                out.push(event);
+            }
+            event => out.push(event),
+        }
+    }
+}
+
+/// A place in the text of a document that stays the same whatever the styles: a number of
+/// chars into the text of the block element with an anchor.
+///
+/// Chars are counted as in JavaScript strings, without soft hyphens.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextPosition {
+    pub url: Url,
+    /// `data-og-id` of the block element.
+    pub anchor: String,
+    pub offset: usize,
+}
+
+impl TextPosition {
+    /// The URL of the document with the position in the fragment, e.g., `#og-pos=2.0:35`.
+    /// The front-end makes them, this is for tests.
+    #[cfg(test)]
+    pub fn to_url(&self) -> Url {
+        let mut url = self.url.clone();
+        url.set_fragment(Some(&format!(
+            "{POSITION_FRAGMENT_PREFIX}{}:{}",
+            self.anchor, self.offset
+        )));
+        url
+    }
+
+    /// Reads a URL made by `to_url`.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        let position = url.fragment()?.strip_prefix(POSITION_FRAGMENT_PREFIX)?;
+        let (anchor, offset) = position.split_once(':')?;
+        let is_path = anchor
+            .split('.')
+            .all(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()));
+        if !is_path {
+            return None;
+        }
+
+        let mut doc_url = url.clone();
+        doc_url.set_fragment(None);
+        Some(Self {
+            url: doc_url,
+            anchor: String::from(anchor),
+            offset: offset.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_block_anchors() {
+        let mut pipeline = Pipeline::with(BlockAnchors::default());
+        let input = "<html><head><title>t</title></head><body>\
+                     <h1>T</h1><section><p>a <b>b</b></p><hr/><p>c</p></section></body></html>";
+        let expected = "<html><head><title>t</title></head><body>\
+                        <h1 data-og-id=\"0\">T</h1><section data-og-id=\"1\">\
+                        <p data-og-id=\"1.0\">a <b>b</b></p><hr data-og-id=\"1.1\"/>\
+                        <p data-og-id=\"1.2\">c</p></section></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_text_position_url() {
+        let position = TextPosition {
+            url: Url::parse("epub:/OEBPS/ch1.xhtml").unwrap(),
+            anchor: String::from("1.2"),
+            offset: 35,
+        };
+        let url = position.to_url();
+        assert_eq!("epub:/OEBPS/ch1.xhtml#og-pos=1.2:35", url.as_str());
+        assert_eq!(Some(position), TextPosition::from_url(&url));
+
+        let url = Url::parse("epub:/OEBPS/ch1.xhtml#og-pos=1..2:35").unwrap();
+        assert_eq!(None, TextPosition::from_url(&url));
+        let url = Url::parse("epub:/OEBPS/ch1.xhtml#note").unwrap();
+        assert_eq!(None, TextPosition::from_url(&url));
+    }
+}
@@ -0,0 +1,318 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use quick_xml::{
+    escape::partial_escape,
+    events::{BytesStart, BytesText, Event},
+};
+
+use super::{ContentTransform, hyphen::element_lang};
+use crate::prefs::ChineseScript;
+
+/// An OpenCC dictionary: text to what it converts to.
+pub(super) struct Dictionary {
+    /// Space separated values, the first being the default.
+    entries: HashMap<String, String>,
+    /// Length of the longest key, in chars.
+    max_len: usize,
+}
+
+impl Dictionary {
+    /// `sources` are in the OpenCC text format: per line, a key, a tab and space separated
+    /// values. With `reversed`, each value converts to its key.
+    pub(super) fn parse(sources: &[&str], reversed: bool) -> Self {
+        let mut entries = HashMap::new();
+        for line in sources.iter().flat_map(|source| source.lines()) {
+            let Some((key, values)) = line.split_once('\t') else {
+                continue;
+            };
+            if reversed {
+                for value in values.split(' ') {
+                    entries
+                        .entry(String::from(value))
+                        .or_insert_with(|| String::from(key));
+                }
+            } else {
+                entries.insert(String::from(key), String::from(values));
+            }
+        }
+        let max_len = entries.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+        Self { entries, max_len }
+    }
+
+    /// The longest key in `text` from char `start` to at most char `end`, as the char where
+    /// it ends and its values. `offsets` are where the chars of `text` start, then its length.
+    pub(super) fn longest_match(
+        &self,
+        text: &str,
+        offsets: &[usize],
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, &str)> {
+        (start + 1..=end.min(start + self.max_len))
+            .rev()
+            .find_map(|end| {
+                let value = self.entries.get(&text[offsets[start]..offsets[end]])?;
+                Some((end, value.as_str()))
+            })
+    }
+
+    /// Converts `text`, taking the longest key at each position.
+    fn convert(&self, text: &str) -> String {
+        let offsets = char_offsets(text);
+        let chars = offsets.len() - 1;
+
+        let mut output = String::with_capacity(text.len());
+        let mut start = 0;
+        while start < chars {
+            match self.longest_match(text, &offsets, start, chars) {
+                Some((end, values)) => {
+                    output.push_str(values.split(' ').next().unwrap_or_default());
+                    start = end;
+                }
+                None => {
+                    output.push_str(&text[offsets[start]..offsets[start + 1]]);
+                    start += 1;
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Where the chars of `text` start, then its length.
+pub(super) fn char_offsets(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain([text.len()])
+        .collect()
+}
+
+static ST: LazyLock<Dictionary> = LazyLock::new(|| {
+    Dictionary::parse(
+        &[
+            include_str!("opencc/STPhrases.txt"),
+            include_str!("opencc/STCharacters.txt"),
+        ],
+        false,
+    )
+});
+static TS: LazyLock<Dictionary> = LazyLock::new(|| {
+    Dictionary::parse(
+        &[
+            include_str!("opencc/TSPhrases.txt"),
+            include_str!("opencc/TSCharacters.txt"),
+        ],
+        false,
+    )
+});
+static TW: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("opencc/TWVariants.txt")], false));
+static TW_REV: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("opencc/TWVariants.txt")], true));
+static HK: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("opencc/HKVariants.txt")], false));
+static HK_REV: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("opencc/HKVariants.txt")], true));
+
+/// Script of Chinese text by its language tag, e.g., `zh-Hant` or `zh-TW`, or `None` if
+/// the text is not Chinese. Plain `zh` is taken as Simplified.
+fn script_of(lang: &str) -> Option<ChineseScript> {
+    let lang = lang.to_ascii_lowercase();
+    let mut subtags = lang.split('-');
+    if subtags.next() != Some("zh") {
+        return None;
+    }
+    let subtags: Vec<_> = subtags.collect();
+    let has = |subtag: &str| subtags.contains(&subtag);
+    Some(if has("tw") {
+        ChineseScript::Taiwan
+    } else if has("hk") || has("mo") {
+        ChineseScript::HongKong
+    } else if has("hant") {
+        ChineseScript::Traditional
+    } else {
+        ChineseScript::Simplified
+    })
+}
+
+/// Language tag of text in `script`.
+fn lang_of(script: ChineseScript) -> &'static str {
+    match script {
+        ChineseScript::Simplified => "zh-Hans",
+        ChineseScript::Traditional => "zh-Hant",
+        ChineseScript::Taiwan => "zh-TW",
+        ChineseScript::HongKong => "zh-HK",
+    }
+}
+
+/// Dictionaries to apply in turn to convert text from `from` to `to`.
+fn conversion(from: ChineseScript, to: ChineseScript) -> Vec<&'static Dictionary> {
+    use ChineseScript::*;
+
+    let mut steps: Vec<&'static Dictionary> = Vec::new();
+    match (from, to) {
+        _ if from == to => return steps,
+        (_, Simplified) => return vec![&*TS],
+        (Simplified, _) => steps.push(&ST),
+        (Taiwan, _) => steps.push(&TW_REV),
+        (HongKong, _) => steps.push(&HK_REV),
+        (Traditional, _) => {}
+    }
+    match to {
+        Taiwan => steps.push(&TW),
+        HongKong => steps.push(&HK),
+        _ => {}
+    }
+    steps
+}
+
+/// Elements whose text is not converted, with their descendants.
+fn skips(name: &[u8]) -> bool {
+    matches!(name, b"script" | b"style")
+}
+
+struct Element {
+    /// `None` if the text is not Chinese.
+    script: Option<ChineseScript>,
+    skipped: bool,
+}
+
+/// Converts Chinese text to `target`, by the language of the element or the book, and
+/// updates `lang` and `xml:lang` to match.
+pub struct ChineseConversion {
+    target: ChineseScript,
+    book_lang: Option<String>,
+    stack: Vec<Element>,
+}
+
+impl ChineseConversion {
+    pub fn new(target: ChineseScript, book_lang: Option<String>) -> Self {
+        Self {
+            target,
+            book_lang,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns what is known of `e`, and a copy of `e` with its language updated if needed.
+    fn enter(&self, e: &BytesStart<'_>) -> (Element, Option<BytesStart<'static>>) {
+        let name = e.local_name();
+        let parent = self.stack.last();
+        let lang = element_lang(e);
+        let element = Element {
+            script: match (&lang, parent) {
+                (Some(lang), _) => script_of(lang),
+                (None, Some(parent)) => parent.script,
+                (None, None) => self.book_lang.as_deref().and_then(script_of),
+            },
+            skipped: parent.is_some_and(|p| p.skipped) || skips(name.as_ref()),
+        };
+
+        let target_lang = lang_of(self.target);
+        let is_root = parent.is_none() && name.as_ref() == b"html";
+        let updated = match lang {
+            Some(lang) if script_of(&lang).is_some() && lang != target_lang => {
+                let mut updated = e.to_owned();
+                updated.clear_attributes();
+                for attr in e.attributes().flatten() {
+                    match attr.key.as_ref() {
+                        b"lang" | b"xml:lang"
+                            if script_of(&String::from_utf8_lossy(&attr.value)).is_some() =>
+                        {
+                            updated.push_attribute((attr.key.as_ref(), target_lang.as_bytes()));
+                        }
+                        _ => updated.push_attribute(attr),
+                    }
+                }
+                Some(updated.into_owned())
+            }
+            // the reader takes the language of the book otherwise
+            None if is_root && element.script.is_some() => {
+                let mut updated = e.to_owned();
+                updated.push_attribute(("lang", target_lang));
+                updated.push_attribute(("xml:lang", target_lang));
+                Some(updated)
+            }
+            _ => None,
+        };
+        (element, updated)
+    }
+}
+
+impl ContentTransform for ChineseConversion {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) => {
+                let (element, updated) = self.enter(e);
+                self.stack.push(element);
+                out.push(updated.map_or(event, Event::Start));
+            }
+            Event::Empty(ref e) => {
+                let (_, updated) = self.enter(e);
+                out.push(updated.map_or(event, Event::Empty));
+            }
+            Event::End(_) => {
+                self.stack.pop();
+                out.push(event);
+            }
+            Event::Text(ref e) => {
+                let steps = match self.stack.last() {
+                    Some(Element {
+                        script: Some(script),
+                        skipped: false,
+                    }) => conversion(*script, self.target),
+                    _ => Vec::new(),
+                };
+                match e.unescape() {
+                    Ok(text) if !steps.is_empty() => {
+                        let text = steps
+                            .iter()
+                            .fold(text.into_owned(), |text, dict| dict.convert(&text));
+                        let text = partial_escape(&text).into_owned();
+                        out.push(Event::Text(BytesText::from_escaped(text)));
+                    }
+                    _ => out.push(event),
+                }
+            }
+            event => out.push(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_convert() {
+        let convert = |from, to, text| {
+            conversion(from, to)
+                .iter()
+                .fold(String::from(text), |text, dict| dict.convert(&text))
+        };
+        use ChineseScript::*;
+        assert_eq!("頭髮發展", convert(Simplified, Traditional, "头发发展"));
+        assert_eq!("爲衆人說", convert(Simplified, Traditional, "为众人说"));
+        assert_eq!("為眾人說", convert(Simplified, Taiwan, "为众人说"));
+        assert_eq!("為眾人説", convert(Simplified, HongKong, "为众人说"));
+        assert_eq!("为众人说", convert(Taiwan, Simplified, "為眾人說"));
+        assert_eq!(
+            "乾隆年间干净",
+            convert(Traditional, Simplified, "乾隆年間乾淨")
+        );
+        assert_eq!("為眾人説", convert(Taiwan, HongKong, "為眾人說"));
+    }
+
+    #[test]
+    fn test_chinese_conversion() {
+        let pass = ChineseConversion::new(ChineseScript::Simplified, Some(String::from("zh-TW")));
+        let mut pipeline = Pipeline::with(pass);
+        let input = "<html><head><title>書</title></head><body>\
+                     <p>頭髮</p><p lang=\"ja\">學校</p><p xml:lang=\"zh-Hant\">國語</p></body></html>";
+        let expected = "<html lang=\"zh-Hans\" xml:lang=\"zh-Hans\"><head><title>书</title></head><body>\
+                        <p>头发</p><p lang=\"ja\">學校</p><p xml:lang=\"zh-Hans\">国语</p></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,403 @@
+//! Color adaptation: remaps publisher colors into the palette of the reader's theme.
+
+use cssparser::{
+    Parser, ParserInput, Token,
+    color::{parse_hash_color, parse_named_color},
+};
+use quick_xml::events::{BytesStart, Event, attributes::Attribute};
+
+use super::{ContentTransform, CssContext, CssPosition};
+use crate::prefs::Theme;
+
+/// A color in sRGB. Every component is in `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rgba {
+    r: f32,
+    g: f32,
+    b: f32,
+    a: f32,
+}
+
+impl Rgba {
+    fn from_u8(r: u8, g: u8, b: u8, a: f32) -> Self {
+        Self {
+            r: r as f32 / 255.0,
+            g: g as f32 / 255.0,
+            b: b as f32 / 255.0,
+            a,
+        }
+    }
+
+    /// Hue in degrees, saturation and lightness.
+    fn to_hsl(self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == self.r {
+            60.0 * ((self.g - self.b) / d).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / d + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / d + 4.0)
+        };
+        (h, s, l)
+    }
+
+    fn from_hsl(h: f32, s: f32, l: f32, a: f32) -> Self {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        Self {
+            r: r + m,
+            g: g + m,
+            b: b + m,
+            a,
+        }
+    }
+
+    fn mix(self, other: Self, t: f32) -> Self {
+        Self {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a,
+        }
+    }
+
+    fn to_css(self) -> String {
+        let u8_of = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let (r, g, b) = (u8_of(self.r), u8_of(self.g), u8_of(self.b));
+        if self.a >= 1.0 {
+            format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+            format!("rgba({r}, {g}, {b}, {:.2})", self.a.clamp(0.0, 1.0))
+        }
+    }
+}
+
+/// Parse a `<color>` such as `#fff`, `white` or `rgb(255 255 255)`.
+/// Keywords like `currentColor` are not colors to remap and give `None`.
+fn parse_color(css: &str) -> Option<Rgba> {
+    let mut input = ParserInput::new(css);
+    let mut parser = Parser::new(&mut input);
+    let color = match parser.next().ok()? {
+        Token::Hash(value) | Token::IDHash(value) => parse_hash(value),
+        Token::Ident(ident) => parse_named(ident),
+        Token::Function(name) => {
+            let name = name.to_ascii_lowercase();
+            parser
+                .parse_nested_block(|arguments| {
+                    let start = arguments.position();
+                    while arguments.next().is_ok() {}
+                    let css = arguments.slice_from(start);
+                    Ok::<_, cssparser::ParseError<'_, ()>>(parse_color_function(&name, css))
+                })
+                .ok()?
+        }
+        _ => None,
+    };
+    parser.expect_exhausted().ok()?;
+    color
+}
+
+fn parse_hash(value: &str) -> Option<Rgba> {
+    let (r, g, b, a) = parse_hash_color(value.as_bytes()).ok()?;
+    Some(Rgba::from_u8(r, g, b, a))
+}
+
+fn parse_named(ident: &str) -> Option<Rgba> {
+    let (r, g, b) = parse_named_color(&ident.to_ascii_lowercase()).ok()?;
+    Some(Rgba::from_u8(r, g, b, 1.0))
+}
+
+/// Parse the arguments of `rgb()`, `rgba()`, `hsl()` or `hsla()`,
+/// in either the legacy (comma) or the modern (space) syntax.
+fn parse_color_function(name: &str, arguments: &str) -> Option<Rgba> {
+    enum Component {
+        Number(f32),
+        Percentage(f32),
+        Degrees(f32),
+    }
+
+    let mut input = ParserInput::new(arguments);
+    let mut parser = Parser::new(&mut input);
+    let mut components = Vec::with_capacity(4);
+    while let Ok(token) = parser.next() {
+        let component = match token {
+            Token::Comma | Token::Delim('/') => continue,
+            Token::Number { value, .. } => Component::Number(*value),
+            Token::Percentage { unit_value, .. } => Component::Percentage(*unit_value),
+            Token::Dimension { value, unit, .. } => match unit.to_ascii_lowercase().as_str() {
+                "deg" => Component::Degrees(*value),
+                "grad" => Component::Degrees(*value * 0.9),
+                "rad" => Component::Degrees(value.to_degrees()),
+                "turn" => Component::Degrees(*value * 360.0),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        components.push(component);
+    }
+
+    let alpha = match components.get(3) {
+        None => 1.0,
+        Some(Component::Number(value) | Component::Percentage(value)) => value.clamp(0.0, 1.0),
+        Some(Component::Degrees(_)) => return None,
+    };
+    if components.len() < 3 || components.len() > 4 {
+        return None;
+    }
+
+    match name {
+        "rgb" | "rgba" => {
+            let mut rgb = [0.0; 3];
+            for (i, component) in components.iter().take(3).enumerate() {
+                rgb[i] = match component {
+                    Component::Number(value) => value / 255.0,
+                    Component::Percentage(value) => *value,
+                    Component::Degrees(_) => return None,
+                }
+                .clamp(0.0, 1.0);
+            }
+            Some(Rgba {
+                r: rgb[0],
+                g: rgb[1],
+                b: rgb[2],
+                a: alpha,
+            })
+        }
+        "hsl" | "hsla" => {
+            let h = match components[0] {
+                Component::Number(value) | Component::Degrees(value) => value,
+                Component::Percentage(_) => return None,
+            };
+            let mut sl = [0.0; 2];
+            for (i, component) in components[1..3].iter().enumerate() {
+                sl[i] = match component {
+                    Component::Number(value) => value / 100.0,
+                    Component::Percentage(value) => *value,
+                    Component::Degrees(_) => return None,
+                }
+                .clamp(0.0, 1.0);
+            }
+            Some(Rgba::from_hsl(h, sl[0], sl[1], alpha))
+        }
+        _ => None,
+    }
+}
+
+/// Whether values of the CSS property can contain colors to remap.
+fn is_color_property(property: &str) -> bool {
+    const PREFIXES: [&str; 7] = [
+        "background",
+        "border",
+        "outline",
+        "column-rule",
+        "text-decoration",
+        "text-emphasis",
+        "scrollbar",
+    ];
+    property.ends_with("color")
+        || PREFIXES.iter().any(|prefix| property.starts_with(prefix))
+        || matches!(property, "fill" | "stroke" | "box-shadow" | "text-shadow")
+}
+
+/// Whether the XHTML or SVG attribute holds a color.
+fn is_color_attribute(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"fill"
+            | b"stroke"
+            | b"stop-color"
+            | b"flood-color"
+            | b"lighting-color"
+            | b"color"
+            | b"bgcolor"
+            | b"text"
+            | b"link"
+            | b"vlink"
+            | b"alink"
+    )
+}
+
+/// Colors that a theme uses in place of white paper and black ink.
+struct Palette {
+    background: Rgba,
+    foreground: Rgba,
+}
+
+impl Palette {
+    fn of(theme: Theme) -> Self {
+        match theme {
+            Theme::Sepia => Self {
+                background: Rgba::from_u8(0xf4, 0xec, 0xd8, 1.0),
+                foreground: Rgba::from_u8(0x5b, 0x46, 0x36, 1.0),
+            },
+            Theme::Dark => Self {
+                background: Rgba::from_u8(0x1e, 0x1e, 0x1e, 1.0),
+                foreground: Rgba::from_u8(0xd8, 0xd8, 0xd8, 1.0),
+            },
+        }
+    }
+
+    /// Map a color designed for a white page.
+    ///
+    /// Lightness is mapped linearly so that white becomes the background and black
+    /// becomes the foreground. This keeps the order of lightness, and thus contrast,
+    /// between colors. Grays take the tint of the palette; other colors keep their hue
+    /// and saturation.
+    fn remap(&self, color: Rgba) -> Rgba {
+        const GRAY_SATURATION: f32 = 0.1;
+
+        let (h, s, l) = color.to_hsl();
+        if s < GRAY_SATURATION {
+            let gray = self.foreground.mix(self.background, l);
+            return Rgba { a: color.a, ..gray };
+        }
+        let (_, _, lf) = self.foreground.to_hsl();
+        let (_, _, lb) = self.background.to_hsl();
+        Rgba::from_hsl(h, s, lf + (lb - lf) * l, color.a)
+    }
+
+    fn remap_css(&self, css: &str) -> Option<String> {
+        parse_color(css).map(|color| self.remap(color).to_css())
+    }
+}
+
+/// Remaps colors in CSS and in color attributes to the palette of a theme.
+pub struct Colors {
+    palette: Palette,
+}
+
+impl Colors {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            palette: Palette::of(theme),
+        }
+    }
+
+    fn remap_attributes(&self, e: &BytesStart<'static>) -> Option<BytesStart<'static>> {
+        let mut changed = false;
+        let mut start = e.to_owned();
+        start.clear_attributes();
+        for attr in e.attributes().flatten() {
+            if is_color_attribute(attr.key.local_name().as_ref())
+                && let Ok(value) = std::str::from_utf8(&attr.value)
+                && let Some(value) = self.palette.remap_css(value)
+            {
+                start.push_attribute(Attribute::from((attr.key.as_ref(), value.as_bytes())));
+                changed = true;
+                continue;
+            }
+            start.push_attribute(attr);
+        }
+        changed.then_some(start)
+    }
+}
+
+impl ContentTransform for Colors {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if ctx.position != CssPosition::Value || !is_color_property(ctx.property) {
+            return None;
+        }
+        let color = match token {
+            Token::Hash(value) | Token::IDHash(value) => parse_hash(value),
+            Token::Ident(ident) => parse_named(ident),
+            _ => None,
+        };
+        color.map(|color| self.palette.remap(color).to_css())
+    }
+
+    fn css_function(
+        &mut self,
+        name: &str,
+        arguments: &str,
+        ctx: &CssContext<'_>,
+    ) -> Option<String> {
+        if ctx.position != CssPosition::Value || !is_color_property(ctx.property) {
+            return None;
+        }
+        parse_color_function(name, arguments).map(|color| self.palette.remap(color).to_css())
+    }
+
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        let event = match event {
+            Event::Start(e) => Event::Start(self.remap_attributes(&e).unwrap_or(e)),
+            Event::Empty(e) => Event::Empty(self.remap_attributes(&e).unwrap_or(e)),
+            event => event,
+        };
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Context, CssBlock, Pipeline, alter_xhtml, settings_with};
+    use crate::prefs;
+
+    #[test]
+    fn test_parse_color() {
+        let white = Some(Rgba::from_u8(255, 255, 255, 1.0));
+        assert_eq!(white, parse_color("#fff"));
+        assert_eq!(white, parse_color("White"));
+        assert_eq!(white, parse_color("rgb(255, 255, 255)"));
+        assert_eq!(white, parse_color("rgb(100% 100% 100%)"));
+        assert_eq!(white, parse_color("hsl(0 0% 100%)"));
+        assert_eq!(Some(0.5), parse_color("rgba(0, 0, 0, 0.5)").map(|c| c.a));
+        assert_eq!(None, parse_color("currentColor"));
+        assert_eq!(None, parse_color("none"));
+    }
+
+    #[test]
+    fn test_remap_dark() {
+        let palette = Palette::of(Theme::Dark);
+        assert_eq!(Some(String::from("#1e1e1e")), palette.remap_css("white"));
+        assert_eq!(Some(String::from("#d8d8d8")), palette.remap_css("#000"));
+
+        // a dark blue link becomes a light blue
+        let blue = palette.remap(parse_color("#00008b").unwrap());
+        let (h, _, l) = blue.to_hsl();
+        assert!((h - 240.0).abs() < 1.0);
+        assert!(l > 0.5);
+    }
+
+    #[test]
+    fn test_colors_pass() {
+        let settings = settings_with(prefs::THEME, serde_json::json!("dark"));
+        let mut pipeline = Pipeline::new(&settings, &Context::default());
+
+        let input = "#fff { color: #000; background: linear-gradient(white, rgb(0 0 0)); }";
+        let expected = "#fff { color: #d8d8d8; background: linear-gradient(#1e1e1e, #d8d8d8); }";
+        assert_eq!(
+            expected,
+            pipeline.alter_css_str(input, CssBlock::Rules).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_colors_svg() {
+        let settings = settings_with(prefs::THEME, serde_json::json!("dark"));
+        let mut pipeline = Pipeline::new(&settings, &Context::default());
+
+        // remapped once, not remapped back by the presentation attributes
+        let input = r##"<svg xmlns="http://www.w3.org/2000/svg"><text font-size="12pt" fill="#000">A</text><rect stroke="white" style="fill: black"/></svg>"##;
+        let expected = r##"<svg xmlns="http://www.w3.org/2000/svg"><text font-size="1.00rem" fill="#d8d8d8">A</text><rect stroke="#1e1e1e" style="fill: #d8d8d8"/></svg>"##;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
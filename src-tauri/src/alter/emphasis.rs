@@ -0,0 +1,225 @@
+use quick_xml::{
+    escape::partial_escape,
+    events::{BytesEnd, BytesStart, BytesText, Event},
+};
+
+use super::{ContentTransform, hyphen::element_lang};
+use crate::prefs::EmphasisStrength;
+
+/// Class of the `<b>` put around the start of words.
+const FIX_CLASS: &str = "og-fix";
+
+/// Share of the letters of each word made bold.
+fn fraction(strength: EmphasisStrength) -> f32 {
+    match strength {
+        EmphasisStrength::Light => 0.3,
+        EmphasisStrength::Medium => 0.45,
+        EmphasisStrength::Strong => 0.6,
+    }
+}
+
+/// Chinese, Japanese and Korean, written without spaces between words, or in syllable
+/// blocks too short to split.
+fn is_cjk_lang(lang: &str) -> bool {
+    let primary = lang.split('-').next().unwrap_or_default();
+    ["zh", "ja", "ko"]
+        .iter()
+        .any(|cjk| primary.eq_ignore_ascii_case(cjk))
+}
+
+/// Han, kana, Hangul, and their punctuation and full width forms.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{2E80}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF00}'..='\u{FFEF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Letters of words, and soft hyphens inserted by `Hyphenation`, which are not counted.
+fn is_word_char(c: char) -> bool {
+    c == '\u{AD}' || (c.is_alphabetic() && !is_cjk(c))
+}
+
+/// Splits `text` into pieces, with whether each is the start of a word to emphasize: the
+/// given fraction of its letters, at least one.
+fn split(text: &str, fraction: f32) -> Vec<(&str, bool)> {
+    // byte ranges, unemphasized ones merged
+    let mut ranges: Vec<(usize, usize, bool)> = Vec::new();
+    let mut push = |start: usize, end: usize, fixed: bool| match ranges.last_mut() {
+        _ if start == end => {}
+        Some((_, last_end, false)) if !fixed => *last_end = end,
+        _ => ranges.push((start, end, fixed)),
+    };
+
+    let mut gap_start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if !is_word_char(c) {
+            chars.next();
+            continue;
+        }
+        let mut word = Vec::new();
+        while let Some(&(i, c)) = chars.peek().filter(|(_, c)| is_word_char(*c)) {
+            word.push((i, c));
+            chars.next();
+        }
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+
+        let letters = word.iter().filter(|(_, c)| *c != '\u{AD}').count();
+        if letters == 0 {
+            continue;
+        }
+        let fixed = ((letters as f32 * fraction).ceil() as usize).max(1);
+        let fix_end = word
+            .iter()
+            .filter(|(_, c)| *c != '\u{AD}')
+            .nth(fixed - 1)
+            .map_or(end, |&(i, c)| i + c.len_utf8());
+
+        push(gap_start, start, false);
+        push(start, fix_end, true);
+        push(fix_end, end, false);
+        gap_start = end;
+    }
+    push(gap_start, text.len(), false);
+    ranges
+        .into_iter()
+        .map(|(start, end, fixed)| (&text[start..end], fixed))
+        .collect()
+}
+
+/// Elements whose text is not emphasized, with their descendants.
+fn skips(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"head"
+            | b"h1"
+            | b"h2"
+            | b"h3"
+            | b"h4"
+            | b"h5"
+            | b"h6"
+            | b"pre"
+            | b"code"
+            | b"kbd"
+            | b"samp"
+            | b"ruby"
+            | b"rt"
+            | b"rp"
+            | b"script"
+            | b"style"
+            | b"svg"
+            | b"math"
+    )
+}
+
+struct Element {
+    cjk: bool,
+    skipped: bool,
+}
+
+/// Makes the start of each word bold, for the eyes to fix on, by the language of the
+/// element or the book. Text in CJK languages and scripts is left as is.
+pub struct ReadingEmphasis {
+    fraction: f32,
+    book_cjk: bool,
+    stack: Vec<Element>,
+}
+
+impl ReadingEmphasis {
+    pub fn new(strength: EmphasisStrength, book_lang: Option<String>) -> Self {
+        Self {
+            fraction: fraction(strength),
+            book_cjk: book_lang.as_deref().is_some_and(is_cjk_lang),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl ContentTransform for ReadingEmphasis {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) => {
+                let parent = self.stack.last();
+                let element = Element {
+                    cjk: match element_lang(e) {
+                        Some(lang) => is_cjk_lang(&lang),
+                        None => parent.map_or(self.book_cjk, |p| p.cjk),
+                    },
+                    skipped: parent.is_some_and(|p| p.skipped) || skips(e.local_name().as_ref()),
+                };
+                self.stack.push(element);
+            }
+            Event::End(_) => {
+                self.stack.pop();
+            }
+            Event::Text(ref e) => {
+                let emphasized = self
+                    .stack
+                    .last()
+                    .is_some_and(|element| !element.cjk && !element.skipped);
+                if emphasized && let Ok(text) = e.unescape() {
+                    let pieces = split(&text, self.fraction);
+                    if pieces.iter().any(|(_, fixed)| *fixed) {
+                        for (piece, fixed) in pieces {
+                            let piece = partial_escape(piece).into_owned();
+                            let piece = Event::Text(BytesText::from_escaped(piece));
+                            if fixed {
+                                let b =
+                                    BytesStart::new("b").with_attributes([("class", FIX_CLASS)]);
+                                out.push(Event::Start(b));
+                                out.push(piece);
+                                out.push(Event::End(BytesEnd::new("b")));
+                            } else {
+                                out.push(piece);
+                            }
+                        }
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            vec![
+                ("Read", true),
+                ("ing, ", false),
+                ("i", true),
+                ("t ", false),
+                ("wo\u{AD}r", true),
+                ("ks 日本 ", false),
+                ("Ét", true),
+                ("é.", false)
+            ],
+            split("Reading, it wo\u{AD}rks 日本 Été.", 0.45)
+        );
+    }
+
+    #[test]
+    fn test_reading_emphasis() {
+        let pass = ReadingEmphasis::new(EmphasisStrength::Medium, Some(String::from("en")));
+        let mut pipeline = Pipeline::with(pass);
+        let input = "<html><head><title>Book</title></head><body><h1>Title</h1>\
+                     <p>Fun &amp; <code>x</code></p><p lang=\"ja\">Tokyo</p></body></html>";
+        let expected = "<html><head><title>Book</title></head><body><h1>Title</h1>\
+                        <p><b class=\"og-fix\">Fu</b>n &amp; <code>x</code></p><p lang=\"ja\">Tokyo</p></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
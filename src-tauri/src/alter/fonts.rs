@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::rc::Rc;
+
+use cssparser::{ParseError, Parser, ParserInput, ToCss, Token};
+use url::Url;
+
+use super::{
+    ContentTransform, CssContext, CssPosition, Pipeline, alter_css, alter_xhtml, remote_url,
+};
+use crate::errors::AnyErr;
+
+/// Keywords that `font-family` takes in any property, not families.
+fn is_css_wide_keyword(value: &str) -> bool {
+    ["inherit", "initial", "unset", "revert", "revert-layer"]
+        .iter()
+        .any(|keyword| value.eq_ignore_ascii_case(keyword))
+}
+
+fn is_font_face(prelude: &str) -> bool {
+    prelude.eq_ignore_ascii_case("@font-face")
+}
+
+/// Descriptors of an `@font-face` rule.
+#[derive(Default)]
+struct FontFace {
+    family: String,
+    weight: Option<String>,
+    style: Option<String>,
+    /// URLs in `src`, as written.
+    sources: Vec<String>,
+}
+
+impl FontFace {
+    /// Parses the block of an `@font-face` rule.
+    fn parse(block: &str) -> Self {
+        let mut face = Self::default();
+        let mut input = ParserInput::new(block);
+        let mut parser = Parser::new(&mut input);
+        let mut descriptor = None::<String>;
+        let mut value = String::new();
+        while let Ok(token) = parser.next() {
+            match token {
+                Token::Ident(name) if descriptor.is_none() => {
+                    descriptor = Some(name.to_ascii_lowercase());
+                    value.clear();
+                }
+                Token::Semicolon => {
+                    face.set(descriptor.take().as_deref(), value.trim());
+                }
+                Token::Colon => {}
+                Token::UnquotedUrl(url) if descriptor.as_deref() == Some("src") => {
+                    face.sources.push(url.to_string());
+                }
+                Token::Function(name)
+                    if name.eq_ignore_ascii_case("url") && descriptor.as_deref() == Some("src") =>
+                {
+                    let url =
+                        parser.parse_nested_block(|nested| -> Result<_, ParseError<'_, ()>> {
+                            Ok(nested.expect_string()?.to_string())
+                        });
+                    if let Ok(url) = url {
+                        face.sources.push(url);
+                    }
+                }
+                Token::Ident(name) | Token::QuotedString(name) => {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(name);
+                }
+                token => {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(&token.to_css_string());
+                }
+            }
+        }
+        face.set(descriptor.as_deref(), value.trim());
+        face
+    }
+
+    fn set(&mut self, descriptor: Option<&str>, value: &str) {
+        let value = String::from(value);
+        match descriptor {
+            Some("font-family") => self.family = value,
+            Some("font-weight") => self.weight = Some(value),
+            Some("font-style") => self.style = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Resolves a URL in a stylesheet at `base`, to the remote URL if served by the proxy.
+fn resolve(base: &Url, url: &str) -> Option<Url> {
+    let url = base.join(url).ok()?;
+    Some(remote_url(&url).unwrap_or(url))
+}
+
+/// A font embedded in a book, or loaded from the web, by an `@font-face` rule.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct EmbeddedFont {
+    pub family: String,
+    pub weight: Option<String>,
+    pub style: Option<String>,
+    /// The first source, which identifies the font.
+    pub url: Url,
+    /// Size of the font file in bytes, if in the book.
+    pub size: Option<u64>,
+    /// Whether the font is disabled for the book, see `prefs::DISABLED_FONTS`.
+    pub disabled: bool,
+}
+
+/// Collects the families of `font-family` declarations, as `FontFamily` sees them, by
+/// lowercase name, and the fonts of `@font-face` rules.
+struct CollectFonts {
+    /// URL of the stylesheet or document.
+    base: Url,
+    families: Rc<RefCell<BTreeMap<String, String>>>,
+    embedded: Rc<RefCell<Vec<EmbeddedFont>>>,
+}
+
+impl ContentTransform for CollectFonts {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if ctx.position != CssPosition::Value || ctx.nested || ctx.property != "font-family" {
+            return None;
+        }
+        if let Token::Ident(value) | Token::QuotedString(value) = token
+            && !is_css_wide_keyword(value)
+        {
+            self.families
+                .borrow_mut()
+                .entry(value.to_lowercase())
+                .or_insert_with(|| value.to_string());
+        }
+        None
+    }
+
+    fn keep_css_rule(&mut self, prelude: &str, block: Option<&str>) -> bool {
+        let Some(block) = block.filter(|_| is_font_face(prelude)) else {
+            return true;
+        };
+        let face = FontFace::parse(block);
+        let Some(url) = face
+            .sources
+            .first()
+            .and_then(|url| resolve(&self.base, url))
+        else {
+            return true;
+        };
+        let mut embedded = self.embedded.borrow_mut();
+        if !embedded.iter().any(|font| font.url == url) {
+            embedded.push(EmbeddedFont {
+                family: face.family,
+                weight: face.weight,
+                style: face.style,
+                url,
+                size: None,
+                disabled: false,
+            });
+        }
+        true
+    }
+}
+
+/// The fonts that the stylesheets and documents of a book use and embed, as the publisher
+/// writes them.
+#[derive(Default)]
+pub struct PublisherFonts {
+    families: Rc<RefCell<BTreeMap<String, String>>>,
+    embedded: Rc<RefCell<Vec<EmbeddedFont>>>,
+}
+
+impl PublisherFonts {
+    fn pipeline(&self, base: &Url) -> Pipeline {
+        let pass = CollectFonts {
+            base: base.clone(),
+            families: Rc::clone(&self.families),
+            embedded: Rc::clone(&self.embedded),
+        };
+        Pipeline {
+            passes: vec![Box::new(pass)],
+            user_css: String::new(),
+        }
+    }
+
+    /// Adds the fonts of the stylesheet at `url`.
+    pub fn add_css<R: Read>(&mut self, reader: R, url: &Url) -> Result<(), AnyErr> {
+        alter_css(reader, &mut self.pipeline(url)).map(|_| ())
+    }
+
+    /// Adds the fonts in the `<style>`s and `style` attributes of the document at `url`.
+    pub fn add_xhtml<R: Read>(&mut self, reader: R, url: &Url) -> Result<(), AnyErr> {
+        alter_xhtml(reader, &mut self.pipeline(url)).map(|_| ())
+    }
+
+    /// Families sorted by lowercase name, each spelled as first found.
+    pub fn families(&self) -> Vec<String> {
+        self.families.borrow().values().cloned().collect()
+    }
+
+    /// Fonts of `@font-face` rules in the order found, one per source.
+    pub fn embedded(&self) -> Vec<EmbeddedFont> {
+        self.embedded.borrow().clone()
+    }
+}
+
+/// Drops the `@font-face` rules of disabled fonts, so that text falls back to the next
+/// family.
+pub struct DisabledFonts {
+    /// URL of the stylesheet or document.
+    base: Url,
+    disabled: BTreeSet<Url>,
+}
+
+impl DisabledFonts {
+    pub fn new(base: Url, disabled: BTreeSet<Url>) -> Self {
+        Self { base, disabled }
+    }
+}
+
+impl ContentTransform for DisabledFonts {
+    fn keep_css_rule(&mut self, prelude: &str, block: Option<&str>) -> bool {
+        let Some(block) = block.filter(|_| is_font_face(prelude)) else {
+            return true;
+        };
+        !FontFace::parse(block)
+            .sources
+            .iter()
+            .filter_map(|url| resolve(&self.base, url))
+            .any(|url| self.disabled.contains(&url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publisher_fonts() {
+        let url = Url::parse("epub:/OEBPS/css/style.css").unwrap();
+        let mut fonts = PublisherFonts::default();
+        let css = r#"@font-face { font-family: "Minion Pro"; font-weight: bold; src: url("../fonts/minion-bold.otf") format("opentype"), local(Minion); }
+            body { font-family: "Minion Pro", Georgia, serif; } h1 { font-family: inherit; }"#;
+        fonts.add_css(css.as_bytes(), &url).unwrap();
+        let xhtml = r#"<html><head><style>p { font-family: georgia; }</style></head><body><p style="font-family: 'Hiragino Mincho ProN'">A</p></body></html>"#;
+        fonts.add_xhtml(xhtml.as_bytes(), &url).unwrap();
+        assert_eq!(
+            vec!["Georgia", "Hiragino Mincho ProN", "Minion Pro", "serif"],
+            fonts.families()
+        );
+        let font_url = Url::parse("epub:/OEBPS/fonts/minion-bold.otf").unwrap();
+        assert_eq!(
+            vec![EmbeddedFont {
+                family: String::from("Minion Pro"),
+                weight: Some(String::from("bold")),
+                style: None,
+                url: font_url.clone(),
+                size: None,
+                disabled: false,
+            }],
+            fonts.embedded()
+        );
+
+        let pass = DisabledFonts::new(url, BTreeSet::from([font_url]));
+        let mut pipeline = Pipeline::with(pass);
+        let output = alter_css(css.as_bytes(), &mut pipeline).unwrap();
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .trim_start()
+                .starts_with("body {")
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use super::{ContentTransform, CssContext, CssPosition};
+
+/// Yields the chars of `s` outside parentheses, brackets and strings, with their indices.
+/// Opening parentheses and brackets are yielded.
+fn top_level_chars(s: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut depth = 0_usize;
+    let mut quote = None;
+    let mut escaped = false;
+    s.char_indices().filter(move |&(_, c)| {
+        if escaped {
+            escaped = false;
+            return false;
+        }
+        match (quote, c) {
+            (_, '\\') => {
+                escaped = true;
+                false
+            }
+            (Some(q), c) => {
+                if c == q {
+                    quote = None;
+                }
+                false
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                false
+            }
+            (None, '(' | '[') => {
+                depth += 1;
+                depth == 1
+            }
+            (None, ')' | ']') => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            (None, _) => depth == 0,
+        }
+    })
+}
+
+/// Rewrites a compound selector that matches the root element, e.g., `html.ja` or `:root`,
+/// to match the shadow host. Returns `None` if it doesn't match the root element.
+fn scope_compound(compound: &str) -> Option<String> {
+    // where simple selectors after the type selector start
+    let mut starts = Vec::new();
+    let mut last_colon = None;
+    for (i, c) in top_level_chars(compound) {
+        let double_colon = c == ':' && last_colon.is_some_and(|last| last + 1 == i);
+        if matches!(c, '.' | '#' | '[' | ':') && !double_colon {
+            starts.push(i);
+        }
+        last_colon = (c == ':').then_some(i);
+    }
+    starts.push(compound.len());
+
+    let type_selector = &compound[..starts[0]];
+    let simple_selectors = starts.windows(2).map(|w| &compound[w[0]..w[1]]);
+    let is_root = type_selector.eq_ignore_ascii_case("html")
+        || simple_selectors
+            .clone()
+            .any(|s| s.eq_ignore_ascii_case(":root"));
+    if !is_root || !matches!(type_selector, "" | "*") && !type_selector.eq_ignore_ascii_case("html")
+    {
+        return None;
+    }
+
+    let mut conditions = String::new();
+    let mut pseudo_elements = String::new();
+    for s in simple_selectors.filter(|s| !s.eq_ignore_ascii_case(":root")) {
+        if s.starts_with("::") {
+            pseudo_elements.push_str(s);
+        } else {
+            conditions.push_str(s);
+        }
+    }
+    Some(if conditions.is_empty() {
+        format!(":host{pseudo_elements}")
+    } else {
+        format!(":host({conditions}){pseudo_elements}")
+    })
+}
+
+/// Rewrites the first compound selector of a complex selector, because only it can
+/// match the root element.
+fn scope_selector(selector: &str) -> Option<String> {
+    let rest = selector.trim_start();
+    let lead = &selector[..selector.len() - rest.len()];
+    let end = top_level_chars(rest)
+        .find(|&(_, c)| c.is_whitespace() || matches!(c, '>' | '+' | '~'))
+        .map_or(rest.len(), |(i, _)| i);
+    let compound = scope_compound(&rest[..end])?;
+    Some(format!("{lead}{compound}{}", &rest[end..]))
+}
+
+/// Returns `None` if no selector in `list` matches the root element.
+fn scope_selector_list(list: &str) -> Option<String> {
+    let mut output = String::new();
+    let mut changed = false;
+    let mut last = 0;
+    let commas = top_level_chars(list).filter(|&(_, c)| c == ',');
+    for i in commas.map(|(i, _)| i).chain([list.len()]) {
+        let selector = &list[last..i];
+        match scope_selector(selector) {
+            Some(selector) => {
+                output.push_str(&selector);
+                changed = true;
+            }
+            None => output.push_str(selector),
+        }
+        if i < list.len() {
+            output.push(',');
+        }
+        last = i + 1;
+    }
+    changed.then_some(output)
+}
+
+/// Points selectors of the root element (`html`, `:root`) to `:host`.
+///
+/// The reader mounts the `<body>` of a content document in a shadow root, so there is no
+/// `<html>` to match, and the host is what stands for it. Selectors on `body` are left
+/// alone because the element is kept.
+pub struct HostSelectors;
+
+impl ContentTransform for HostSelectors {
+    fn css_function(
+        &mut self,
+        name: &str,
+        arguments: &str,
+        ctx: &CssContext<'_>,
+    ) -> Option<String> {
+        // `@supports selector(...)`
+        if ctx.position != CssPosition::Prelude || name != "selector" {
+            return None;
+        }
+        scope_selector_list(arguments).map(|arguments| format!("selector({arguments})"))
+    }
+
+    fn css_prelude(&mut self, prelude: &str) -> Option<String> {
+        if prelude.starts_with('@') {
+            // group rules are `@media`, `@supports`, etc., whose content is seen separately
+            return None;
+        }
+        scope_selector_list(prelude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alter::{Context, CssBlock, Pipeline};
+    use crate::prefs::Settings;
+
+    fn alter_css_str(css: &str) -> String {
+        Pipeline::new(&Settings::default(), &Context::default())
+            .alter_css_str(css, CssBlock::Rules)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_host_selectors() {
+        assert_eq!(
+            ":host, :host { --a: 1; }",
+            alter_css_str("html, :root { --a: 1; }")
+        );
+        assert_eq!(
+            ":host > body p, :host(.ja:lang(ja)) {}",
+            alter_css_str("HTML > body p, html.ja:lang(ja) {}")
+        );
+        assert_eq!(
+            "@media screen { :host::before {} div html, body {} }",
+            alter_css_str("@media screen { :root::before {} div html, body {} }")
+        );
+        assert_eq!(
+            "@supports selector(:host p) { p[title=\"html\"] {} }",
+            alter_css_str("@supports selector(html p) { p[title=\"html\"] {} }")
+        );
+    }
+}
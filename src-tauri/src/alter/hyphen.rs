@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+};
+
+use quick_xml::{
+    escape::partial_escape,
+    events::{BytesStart, BytesText, Event},
+};
+
+use super::ContentTransform;
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Knuth-Liang hyphenation patterns of one language, as in the `.pat.txt` and `.hyp.txt`
+/// files of the tex-hyphen project.
+pub struct Patterns {
+    /// Letters of a pattern, to its priorities between and around the letters.
+    patterns: HashMap<String, Vec<u8>>,
+    /// Lowercase word, to where it breaks.
+    exceptions: HashMap<String, Vec<usize>>,
+    /// Length of the longest pattern, in chars.
+    max_len: usize,
+    left_min: usize,
+    right_min: usize,
+}
+
+impl Patterns {
+    /// `patterns` and `exceptions` are whitespace separated, e.g., `hy3ph` and `ta-ble`.
+    pub fn parse(patterns: &str, exceptions: &str, left_min: usize, right_min: usize) -> Self {
+        let mut parsed = HashMap::new();
+        let mut max_len = 0;
+        for pattern in patterns.split_whitespace() {
+            let mut letters = String::new();
+            let mut priorities = vec![0];
+            for c in pattern.chars() {
+                match c.to_digit(10) {
+                    Some(d) => *priorities.last_mut().unwrap() = d as u8,
+                    None => {
+                        letters.push(c);
+                        priorities.push(0);
+                    }
+                }
+            }
+            max_len = max_len.max(priorities.len() - 1);
+            parsed.insert(letters, priorities);
+        }
+
+        let exceptions = exceptions
+            .split_whitespace()
+            .map(|word| {
+                let mut breaks = Vec::new();
+                let mut letters = String::new();
+                for c in word.chars() {
+                    if c == '-' {
+                        breaks.push(letters.chars().count());
+                    } else {
+                        letters.push(c);
+                    }
+                }
+                (letters.to_lowercase(), breaks)
+            })
+            .collect();
+
+        Self {
+            patterns: parsed,
+            exceptions,
+            max_len,
+            left_min,
+            right_min,
+        }
+    }
+
+    /// Reads the patterns in files named after `name`, e.g., `de-1996`, from the first of
+    /// `dirs` that has them.
+    fn read(dirs: &[PathBuf], name: &str) -> Option<Self> {
+        dirs.iter().find_map(|dir| {
+            let text = std::fs::read_to_string(dir.join(format!("hyph-{name}.pat.txt"))).ok()?;
+            let exceptions = std::fs::read_to_string(dir.join(format!("hyph-{name}.hyp.txt")))
+                .unwrap_or_default();
+            let (left_min, right_min) = hyphen_mins(name);
+            log::debug!(
+                "hyphenation patterns for {name} loaded from {}",
+                dir.display()
+            );
+            Some(Patterns::parse(&text, &exceptions, left_min, right_min))
+        })
+    }
+
+    /// Where `word` can break, as numbers of chars before the breaks.
+    pub fn breaks(&self, word: &str) -> Vec<usize> {
+        let lower = word.to_lowercase();
+        let len = lower.chars().count();
+        if len != word.chars().count() || len < self.left_min + self.right_min {
+            return Vec::new();
+        }
+        if let Some(breaks) = self.exceptions.get(&lower) {
+            return breaks.clone();
+        }
+
+        let dotted = format!(".{lower}.");
+        let offsets: Vec<usize> = dotted
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([dotted.len()])
+            .collect();
+        let chars = offsets.len() - 1;
+
+        // priorities[i] is before the ith char of `dotted`
+        let mut priorities = vec![0_u8; chars + 1];
+        for start in 0..chars {
+            for end in start + 1..=chars.min(start + self.max_len) {
+                let Some(pattern) = self.patterns.get(&dotted[offsets[start]..offsets[end]]) else {
+                    continue;
+                };
+                for (i, p) in pattern.iter().enumerate() {
+                    let at = &mut priorities[start + i];
+                    *at = (*at).max(*p);
+                }
+            }
+        }
+
+        (self.left_min..=len - self.right_min)
+            .filter(|before| priorities[before + 1] % 2 == 1)
+            .collect()
+    }
+
+    /// Puts soft hyphens in the words of `text`.
+    fn hyphenate(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut word = String::new();
+        let flush = |word: &mut String, output: &mut String| {
+            let mut breaks = self.breaks(word).into_iter().peekable();
+            for (i, c) in word.chars().enumerate() {
+                if breaks.next_if_eq(&i).is_some() {
+                    output.push(SOFT_HYPHEN);
+                }
+                output.push(c);
+            }
+            word.clear();
+        };
+        for c in text.chars() {
+            if c.is_alphabetic() {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut output);
+                output.push(c);
+            }
+        }
+        flush(&mut word, &mut output);
+        output
+    }
+}
+
+/// Directories with pattern files, e.g., `hyph-de-1996.pat.txt`. The first has priority.
+static PATTERN_DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Patterns read so far, by file name stem. `None` if there is no file.
+static LOADED: LazyLock<Mutex<HashMap<String, Option<Arc<Patterns>>>>> =
+    LazyLock::new(Default::default);
+
+/// Sets where to look for hyphenation patterns. Only the first call has effect.
+pub fn set_pattern_dirs(dirs: Vec<PathBuf>) {
+    let _ = PATTERN_DIRS.set(dirs);
+}
+
+/// Names of pattern files for a language tag, as tex-hyphen names them, best first.
+fn pattern_names(lang: &str) -> Vec<String> {
+    let lang = lang.to_ascii_lowercase();
+    let primary = lang.split('-').next().unwrap_or_default();
+    let mut names = vec![lang.clone()];
+    // where tex-hyphen has no file for the bare language
+    match primary {
+        "en" => names.push(String::from("en-us")),
+        "de" => names.push(String::from("de-1996")),
+        "el" => names.push(String::from("el-monoton")),
+        "mn" => names.push(String::from("mn-cyrl")),
+        _ => {}
+    }
+    names.insert(1, String::from(primary));
+    names.dedup();
+    names
+}
+
+/// Minimum letters before and after a break, by TeX conventions.
+fn hyphen_mins(lang: &str) -> (usize, usize) {
+    match lang.split('-').next().unwrap_or_default() {
+        "en" => (2, 3),
+        _ => (2, 2),
+    }
+}
+
+fn load_patterns(name: &str) -> Option<Arc<Patterns>> {
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some(patterns) = loaded.get(name) {
+        return patterns.clone();
+    }
+
+    let dirs = PATTERN_DIRS.get().map(Vec::as_slice).unwrap_or_default();
+    let patterns = Patterns::read(dirs, name).map(Arc::new);
+    loaded.insert(String::from(name), patterns.clone());
+    patterns
+}
+
+/// Elements whose text is not hyphenated, with their descendants.
+fn skips(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"head"
+            | b"pre"
+            | b"code"
+            | b"kbd"
+            | b"samp"
+            | b"rt"
+            | b"rp"
+            | b"script"
+            | b"style"
+            | b"svg"
+            | b"math"
+    )
+}
+
+pub(super) fn element_lang(e: &BytesStart<'_>) -> Option<String> {
+    let attr = e
+        .try_get_attribute("xml:lang")
+        .ok()
+        .flatten()
+        .or_else(|| e.try_get_attribute("lang").ok().flatten())?;
+    String::from_utf8(attr.value.into_owned()).ok()
+}
+
+struct Element {
+    lang: Option<String>,
+    skipped: bool,
+}
+
+/// Inserts soft hyphens into text, by the language of the element or the book.
+pub struct Hyphenation {
+    book_lang: Option<String>,
+    stack: Vec<Element>,
+    /// Patterns by language tag.
+    patterns: HashMap<String, Option<Arc<Patterns>>>,
+}
+
+impl Hyphenation {
+    pub fn new(book_lang: Option<String>) -> Self {
+        Self {
+            book_lang,
+            stack: Vec::new(),
+            patterns: HashMap::new(),
+        }
+    }
+
+    fn patterns(&mut self, lang: &str) -> Option<Arc<Patterns>> {
+        self.patterns
+            .entry(String::from(lang))
+            .or_insert_with(|| {
+                pattern_names(lang)
+                    .iter()
+                    .find_map(|name| load_patterns(name))
+            })
+            .clone()
+    }
+}
+
+impl ContentTransform for Hyphenation {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) => {
+                let parent = self.stack.last();
+                let element = Element {
+                    lang: element_lang(e).or_else(|| parent.and_then(|p| p.lang.clone())),
+                    skipped: parent.is_some_and(|p| p.skipped) || skips(e.local_name().as_ref()),
+                };
+                self.stack.push(element);
+            }
+            Event::End(_) => {
+                self.stack.pop();
+            }
+            Event::Text(ref e) => {
+                let Some(element) = self.stack.last().filter(|element| !element.skipped) else {
+                    out.push(event);
+                    return;
+                };
+                let lang = element.lang.clone().or_else(|| self.book_lang.clone());
+                let patterns = lang.and_then(|lang| self.patterns(&lang));
+                if let Some(patterns) = patterns
+                    && let Ok(text) = e.unescape()
+                {
+                    let text = patterns.hyphenate(&text);
+                    let text = partial_escape(&text).into_owned();
+                    out.push(Event::Text(BytesText::from_escaped(text)));
+                    return;
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    // from Liang's thesis
+    const PATTERNS: &str = "1na 1tio 2io n2at o2n hen5at hy3ph he2n hena4";
+
+    #[test]
+    fn test_breaks() {
+        let patterns = Patterns::parse(PATTERNS, "ta-ble", 2, 3);
+        assert_eq!(vec![2, 6], patterns.breaks("hyphenation"));
+        assert_eq!(vec![2, 6], patterns.breaks("Hyphenation"));
+        assert_eq!(vec![2], patterns.breaks("table"));
+        assert!(patterns.breaks("hyp").is_empty());
+    }
+
+    #[test]
+    fn test_bundled_patterns() {
+        let dirs = [PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/hyphenation")];
+        let breaks = |lang: &str, word: &str| {
+            let patterns = pattern_names(lang)
+                .iter()
+                .find_map(|name| Patterns::read(&dirs, name));
+            patterns.unwrap().breaks(word)
+        };
+        assert_eq!(vec![2, 6], breaks("en", "hyphenation"));
+        assert_eq!(vec![3, 6, 10], breaks("de-DE", "Silbentrennung"));
+        assert_eq!(vec![5, 7, 10], breaks("nl", "woordafbreking"));
+    }
+
+    #[test]
+    fn test_hyphenation_pass() {
+        let mut pass = Hyphenation::new(Some(String::from("en")));
+        let patterns = Arc::new(Patterns::parse(PATTERNS, "", 2, 3));
+        pass.patterns.insert(String::from("en"), Some(patterns));
+        pass.patterns.insert(String::from("de"), None);
+        let mut pipeline = Pipeline::with(pass);
+
+        let input = "<html><head><title>hyphenation</title></head><body>\
+                     <p>hyphenation <code>hyphenation</code></p>\
+                     <p xml:lang=\"de\">hyphenation</p></body></html>";
+        let expected = "<html><head><title>hyphenation</title></head><body>\
+                        <p>hy\u{AD}phen\u{AD}ation <code>hyphenation</code></p>\
+                        <p xml:lang=\"de\">hyphenation</p></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,124 @@
+use cssparser::Token;
+use quick_xml::events::{BytesStart, Event};
+
+use super::{ContentTransform, CssContext, CssPosition};
+
+/// Standard name of a property that reading systems or WebKit only knew with a prefix,
+/// or under another name.
+fn standard_property(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_lowercase();
+    if name == "adobe-hyphenate" {
+        return Some("hyphens");
+    }
+    let unprefixed = name
+        .strip_prefix("-epub-")
+        .or_else(|| name.strip_prefix("-webkit-"))?;
+    Some(match unprefixed {
+        "writing-mode" => "writing-mode",
+        "text-orientation" => "text-orientation",
+        "text-combine" | "text-combine-horizontal" | "text-combine-upright" => {
+            "text-combine-upright"
+        }
+        "text-emphasis" => "text-emphasis",
+        "text-emphasis-color" => "text-emphasis-color",
+        "text-emphasis-position" => "text-emphasis-position",
+        "text-emphasis-style" => "text-emphasis-style",
+        "text-underline-position" => "text-underline-position",
+        "text-transform" => "text-transform",
+        "hyphens" => "hyphens",
+        "line-break" => "line-break",
+        "word-break" => "word-break",
+        "ruby-position" => "ruby-position",
+        _ => return None,
+    })
+}
+
+/// Standard keyword for an old or prefixed one. `property` is the standard name.
+fn standard_value(property: &str, value: &str) -> Option<&'static str> {
+    let value = value.to_ascii_lowercase();
+    Some(match (property, value.as_str()) {
+        ("writing-mode", "tb-rl" | "tb") => "vertical-rl",
+        ("writing-mode", "tb-lr") => "vertical-lr",
+        ("writing-mode", "lr-tb" | "lr" | "rl-tb" | "rl") => "horizontal-tb",
+        ("text-orientation", "vertical-right") => "mixed",
+        ("text-orientation", "rotate-right" | "sideways-right") => "sideways",
+        ("text-combine-upright", "horizontal") => "all",
+        ("text-transform", "-epub-fullwidth") => "full-width",
+        ("text-transform", "-epub-fullsize-kana") => "full-size-kana",
+        // `adobe-hyphenate`
+        ("hyphens", "explicit") => "manual",
+        // running headers and footers of Adobe Digital Editions
+        ("display", "oeb-page-head" | "oeb-page-foot") => "none",
+        _ => return None,
+    })
+}
+
+/// Whether `e` links to an Adobe page template (`.xpgt`).
+fn is_page_template_link(e: &BytesStart<'_>) -> bool {
+    if e.local_name().as_ref() != b"link" {
+        return false;
+    }
+    let attr_value = |name| match e.try_get_attribute(name) {
+        Ok(Some(attr)) => attr.value.to_ascii_lowercase(),
+        _ => Vec::new(),
+    };
+    attr_value("type") == b"application/vnd.adobe-page-template+xml"
+        || attr_value("href").ends_with(b".xpgt")
+}
+
+/// Translates `-epub-`, `-webkit-` and Adobe properties to standard ones, and hides Adobe
+/// page templates.
+#[derive(Default)]
+pub struct LegacyCss {
+    /// Inside a `<link>` being dropped.
+    in_template_link: bool,
+}
+
+impl ContentTransform for LegacyCss {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        let Token::Ident(ident) = token else {
+            return None;
+        };
+        match ctx.position {
+            CssPosition::Property => standard_property(ident).map(String::from),
+            CssPosition::Value if !ctx.nested => {
+                standard_value(ctx.property, ident).map(String::from)
+            }
+            _ => None,
+        }
+    }
+
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Empty(ref e) if is_page_template_link(e) => {}
+            Event::Start(ref e) if is_page_template_link(e) => self.in_template_link = true,
+            Event::End(_) if self.in_template_link => self.in_template_link = false,
+            Event::PI(ref e) if e.windows(5).any(|w| w.eq_ignore_ascii_case(b".xpgt")) => {}
+            event => out.push(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alter::{Context, CssBlock, Pipeline, alter_xhtml};
+    use crate::prefs::Settings;
+
+    #[test]
+    fn test_legacy_css() {
+        let mut pipeline = Pipeline::new(&Settings::default(), &Context::default());
+        let input = "p { -epub-writing-mode: tb-rl; -webkit-text-combine: horizontal; \
+                     adobe-hyphenate: explicit; }\n.h { display: oeb-page-head; }";
+        let expected = "p { writing-mode: vertical-rl; text-combine-upright: all; \
+                        hyphens: manual; }\n.h { display: none; }";
+        assert_eq!(
+            expected,
+            pipeline.alter_css_str(input, CssBlock::Rules).unwrap()
+        );
+
+        let input = r#"<head><link rel="stylesheet" type="application/vnd.adobe-page-template+xml" href="page-template.xpgt"/><link rel="stylesheet" href="a.css"/></head>"#;
+        let expected = r#"<head><link rel="stylesheet" href="a.css"/></head>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use quick_xml::events::{BytesStart, Event};
+use url::Url;
+
+use super::ContentTransform;
+
+/// Attribute put on anchors the reader shouldn't navigate to as pages.
+const LINK_ATTR: &str = "data-og-link";
+
+/// Schemes opened by other applications.
+const EXTERNAL_SCHEMES: &[&str] = &["http", "https", "mailto", "tel", "ftp"];
+
+/// Tags anchors to external sites with `data-og-link="external"`, and anchors to resources
+/// off the spine with `"image"` or `"resource"`.
+pub struct Links {
+    doc_url: Option<Url>,
+    /// Media types of resources off the spine.
+    off_spine: HashMap<Url, String>,
+}
+
+impl Links {
+    pub fn new(doc_url: Option<Url>, off_spine: HashMap<Url, String>) -> Self {
+        Self { doc_url, off_spine }
+    }
+
+    fn kind(&self, e: &BytesStart<'_>) -> Option<&'static str> {
+        if e.local_name().as_ref() != b"a" {
+            return None;
+        }
+        let href = e.try_get_attribute("href").ok()??;
+        let href = String::from_utf8_lossy(&href.value);
+        let mut url = match &self.doc_url {
+            Some(doc_url) => doc_url.join(href.trim()).ok()?,
+            None => Url::parse(href.trim()).ok()?,
+        };
+        if EXTERNAL_SCHEMES.contains(&url.scheme()) {
+            return Some("external");
+        }
+        url.set_fragment(None);
+        let media_type = self.off_spine.get(&url)?;
+        if media_type.starts_with("image/") {
+            Some("image")
+        } else {
+            Some("resource")
+        }
+    }
+}
+
+impl ContentTransform for Links {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        let tagged = match &event {
+            Event::Start(e) | Event::Empty(e) => self.kind(e).map(|kind| {
+                let mut e = e.to_owned();
+                e.push_attribute((LINK_ATTR, kind));
+                e
+            }),
+            _ => None,
+        };
+        out.push(match (event, tagged) {
+            (Event::Start(_), Some(e)) => Event::Start(e),
+            (Event::Empty(_), Some(e)) => Event::Empty(e),
+            (event, _) => event,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_links() {
+        let doc_url = Url::parse("epub:/OEBPS/text/ch1.xhtml").unwrap();
+        let mut off_spine = HashMap::new();
+        off_spine.insert(
+            Url::parse("epub:/OEBPS/images/map.png").unwrap(),
+            String::from("image/png"),
+        );
+        off_spine.insert(
+            Url::parse("epub:/OEBPS/text/extra.xhtml").unwrap(),
+            String::from("application/xhtml+xml"),
+        );
+        let mut pipeline = Pipeline::with(Links::new(Some(doc_url), off_spine));
+
+        let input = r##"<p><a href="https://example.com/">a</a><a href="MAILTO:x@example.com">b</a><a href="../images/map.png">c</a><a href="extra.xhtml#s1">d</a><a href="ch2.xhtml">e</a><a href="#f">f</a></p>"##;
+        let expected = r##"<p><a href="https://example.com/" data-og-link="external">a</a><a href="MAILTO:x@example.com" data-og-link="external">b</a><a href="../images/map.png" data-og-link="image">c</a><a href="extra.xhtml#s1" data-og-link="resource">d</a><a href="ch2.xhtml">e</a><a href="#f">f</a></p>"##;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,1123 @@
+mod anchors;
+mod chinese;
+mod color;
+mod emphasis;
+mod fonts;
+mod host;
+mod hyphen;
+mod legacy;
+mod links;
+mod notes;
+mod publisher;
+mod readings;
+mod remote;
+mod replace;
+mod ruby;
+mod script;
+mod split;
+mod stacks;
+mod stitch;
+mod vertical;
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, BufReader, Read};
+use std::rc::Rc;
+
+use arrayvec::ArrayString;
+use cssparser::{ParseError, Parser, ParserInput, ToCss, Token};
+use quick_xml::{
+    Decoder, Reader, Writer,
+    escape::partial_escape,
+    events::{BytesEnd, BytesStart, BytesText, Event},
+};
+use url::Url;
+
+use crate::errors::AnyErr;
+use crate::prefs::{
+    self, ChineseScript, EmphasisStrength, FontPrefer, PublisherStyles, RubyMode, ScriptPolicy,
+    Settings, VerticalLayout,
+};
+
+pub use anchors::TextPosition;
+pub use fonts::{EmbeddedFont, PublisherFonts};
+pub use hyphen::set_pattern_dirs;
+pub use notes::extract_note;
+pub use remote::{allowed_hosts, is_allowed, remote_url};
+pub use replace::{RuleMatches, preview_rules};
+pub use split::{DocumentSplit, part_index, part_of, part_url};
+pub use stitch::{HeadStyles, continuous_document};
+pub use vertical::is_vertical_css;
+
+fn abs_length_in_rem(value: f32, unit: &str) -> Option<f32> {
+    const BASE_FONT_SIZE: f32 = 16.0;
+    match unit {
+        "px" => Some(value / BASE_FONT_SIZE),
+        "cm" => Some(value * 96.0 / 2.54 / BASE_FONT_SIZE), // 1cm = 96px / 2.54
+        "mm" => Some(value * 96.0 / 2.54 / 10.0 / BASE_FONT_SIZE), // 1mm = 1cm / 10
+        "Q" => Some(value * 96.0 / 25.4 / 40.0 / BASE_FONT_SIZE), // 1Q = 1cm / 40
+        "in" => Some(value * 96.0 / BASE_FONT_SIZE),        // 1in = 96px
+        "pc" => Some(value * 96.0 / 6.0 / BASE_FONT_SIZE),  // 1pc = 1in / 6
+        "pt" => Some(value * 96.0 / 72.0 / BASE_FONT_SIZE), // 1pt = 1in / 72
+        _ => None,
+    }
+}
+
+fn sml_in_rem(ident: &str) -> Option<f32> {
+    match ident {
+        "xx-small" => Some(0.60),
+        "x-small" => Some(0.75),
+        "small" => Some(0.89),
+        "medium" => Some(1.00),
+        "large" => Some(1.20),
+        "x-large" => Some(1.50),
+        "xx-large" => Some(2.00),
+        "xxx-large" => Some(3.00),
+        _ => None,
+    }
+}
+
+enum LineHeightValue {
+    Normal,
+    Number(f32),
+    Length(f32, ArrayString<8>),
+    Percentage(f32),
+}
+
+fn regulated_line_height(value: LineHeightValue) -> String {
+    const SCALE_VAR: &str = "var(--og-line-height-scale)";
+    match value {
+        LineHeightValue::Normal => format!("calc({SCALE_VAR} * 1.25)"),
+        LineHeightValue::Number(value) => format!("calc({SCALE_VAR} * {value:.2})"),
+        LineHeightValue::Length(value, unit) => {
+            // TODO: what about the other units?
+            if unit.eq_ignore_ascii_case("em") {
+                format!("calc({SCALE_VAR} * {value:.2})")
+            } else {
+                format!("{value:.2}{unit}")
+            }
+        }
+        LineHeightValue::Percentage(value) => {
+            // Assume that the auther intended to use a unitless number
+            format!("calc({SCALE_VAR} * {value:.2})")
+        }
+    }
+}
+
+/// Identifies a `ContentTransform`, as written in settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pass {
+    /// Give block elements anchors that don't depend on styles. Runs first to see the
+    /// document as published.
+    BlockAnchors,
+    /// Strip scripts, unless the document is scripted and set to run in a sandbox.
+    Scripts,
+    /// Block resources on the web, or fetch them through the proxy if allowed.
+    RemoteResources,
+    /// Normalize or ignore publisher styles, as set for the book.
+    PublisherStyles,
+    /// Drop the `@font-face` rules of the embedded fonts disabled for the book.
+    DisabledFonts,
+    /// Point selectors of the root element to the shadow host the reader mounts content in.
+    HostSelectors,
+    /// Lay out vertical writing modes horizontally, if set for the book.
+    Horizontal,
+    /// Translate `-epub-`, `-webkit-` and Adobe properties, and hide Adobe page templates.
+    LegacyCss,
+    /// Scale `line-height` with `--og-line-height-scale`.
+    LineHeight,
+    /// Let users substitute fonts through `--og-font-*` custom properties.
+    FontFamily,
+    /// Give the languages of the document the font stacks set for them.
+    FontStacks,
+    /// Convert absolute lengths and font size keywords to `rem`.
+    Units,
+    /// Remap colors to the palette of the theme, if one is chosen.
+    Colors,
+    /// Apply the find and replace rules of the user to text.
+    ReplaceRules,
+    /// Convert Chinese text between Simplified and Traditional, if a script is chosen.
+    ChineseConversion,
+    /// Add readings as ruby over Han characters, if set.
+    RubyReadings,
+    /// Hide ruby annotations or show them inline, as set.
+    Ruby,
+    /// Insert soft hyphens by the language of the text, if turned on.
+    Hyphenation,
+    /// Make the start of each word bold, at the strength set for the book.
+    ReadingEmphasis,
+    /// Tag note references, for the reader to show notes in pop-ups.
+    Notes,
+    /// Tag links to external sites and to resources off the spine.
+    Links,
+    /// Prefix IDs, if the document is stitched into the continuous one. Runs last, after
+    /// passes that look for IDs.
+    NamespaceIds,
+}
+
+impl Pass {
+    /// Passes that run, in order, unless others are set.
+    pub const DEFAULT: &[Pass] = &[
+        Pass::BlockAnchors,
+        Pass::Scripts,
+        Pass::RemoteResources,
+        Pass::PublisherStyles,
+        Pass::DisabledFonts,
+        Pass::HostSelectors,
+        Pass::Horizontal,
+        Pass::LegacyCss,
+        Pass::LineHeight,
+        Pass::FontFamily,
+        Pass::FontStacks,
+        Pass::Units,
+        Pass::Colors,
+        Pass::ReplaceRules,
+        Pass::ChineseConversion,
+        Pass::RubyReadings,
+        Pass::Ruby,
+        Pass::Hyphenation,
+        Pass::ReadingEmphasis,
+        Pass::Notes,
+        Pass::Links,
+        Pass::NamespaceIds,
+    ];
+
+    /// Passes that keep content from running code or reaching the web. They run even if
+    /// left out.
+    const MANDATORY: &[Pass] = &[Pass::Scripts, Pass::RemoteResources];
+
+    /// Passes to run, in the order set in `prefs::TRANSFORMS`, each once, with mandatory
+    /// ones left out added last, so that no other pass comes after them.
+    fn enabled(settings: &Settings) -> Vec<Pass> {
+        let set: Vec<Pass> = settings
+            .get(prefs::TRANSFORMS)
+            .unwrap_or_else(|| Pass::DEFAULT.to_vec());
+        let mut passes = Vec::new();
+        for pass in set.into_iter().chain(Pass::MANDATORY.iter().copied()) {
+            if !passes.contains(&pass) {
+                passes.push(pass);
+            }
+        }
+        passes
+    }
+
+    /// Returns `None` if the pass has nothing to do with the given settings.
+    fn make(self, settings: &Settings, context: &Context) -> Option<Box<dyn ContentTransform>> {
+        match self {
+            Pass::BlockAnchors => Some(Box::new(anchors::BlockAnchors::resuming(
+                context.part_path.clone(),
+            ))),
+            Pass::Scripts => match settings.get(prefs::SCRIPTS) {
+                Some(ScriptPolicy::Sandbox) if context.scripted => None,
+                _ => Some(Box::<script::StripScripts>::default()),
+            },
+            Pass::RemoteResources => Some(Box::new(remote::RemoteResources::new(
+                context.doc_url.clone(),
+                remote::allowed_hosts(settings),
+                context.blocked_remote.clone(),
+            ))),
+            Pass::PublisherStyles => match settings.get(prefs::PUBLISHER_STYLES) {
+                None | Some(PublisherStyles::Keep) => None,
+                Some(level) => Some(Box::new(publisher::PublisherStyles::new(level))),
+            },
+            Pass::DisabledFonts => {
+                let disabled: BTreeSet<Url> = settings
+                    .get::<Vec<Url>>(prefs::DISABLED_FONTS)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect();
+                match &context.doc_url {
+                    Some(base) if !disabled.is_empty() => {
+                        Some(Box::new(fonts::DisabledFonts::new(base.clone(), disabled)))
+                    }
+                    _ => None,
+                }
+            }
+            Pass::HostSelectors if context.standalone => None,
+            Pass::HostSelectors => Some(Box::new(host::HostSelectors)),
+            Pass::Horizontal => match settings.get(prefs::VERTICAL_LAYOUT) {
+                Some(VerticalLayout::Horizontal) => Some(Box::new(vertical::Horizontal)),
+                _ => None,
+            },
+            Pass::LegacyCss => Some(Box::<legacy::LegacyCss>::default()),
+            Pass::LineHeight | Pass::FontFamily | Pass::FontStacks if context.standalone => None,
+            Pass::LineHeight => Some(Box::new(LineHeight)),
+            Pass::FontFamily => Some(Box::new(FontFamily)),
+            Pass::FontStacks => {
+                let stacks = settings.get_merged(prefs::FONT_STACKS);
+                (!stacks.is_empty()).then(|| {
+                    Box::new(stacks::FontStacks::new(
+                        stacks,
+                        settings.get::<FontPrefer>(prefs::FONT_PREFER),
+                        context.book_lang.clone(),
+                    )) as Box<dyn ContentTransform>
+                })
+            }
+            Pass::Units => Some(Box::new(Units)),
+            Pass::Colors => settings
+                .get(prefs::THEME)
+                .map(|theme| Box::new(color::Colors::new(theme)) as Box<dyn ContentTransform>),
+            Pass::ReplaceRules => {
+                let rules: Vec<prefs::ReplaceRule> = settings.get_joined(prefs::REPLACE_RULES);
+                (!rules.is_empty()).then(|| {
+                    Box::new(replace::ReplaceText::new(&rules, Default::default()))
+                        as Box<dyn ContentTransform>
+                })
+            }
+            Pass::ChineseConversion => {
+                settings
+                    .get::<ChineseScript>(prefs::CHINESE_SCRIPT)
+                    .map(|target| {
+                        Box::new(chinese::ChineseConversion::new(
+                            target,
+                            context.book_lang.clone(),
+                        )) as Box<dyn ContentTransform>
+                    })
+            }
+            Pass::RubyReadings => match settings.get(prefs::RUBY_READINGS) {
+                Some(true) => Some(Box::new(readings::RubyReadings::new(
+                    context.book_lang.clone(),
+                ))),
+                _ => None,
+            },
+            Pass::Ruby => match settings.get(prefs::RUBY) {
+                None | Some(RubyMode::Show) => None,
+                Some(mode) => Some(Box::new(ruby::Ruby::new(mode))),
+            },
+            Pass::Hyphenation => match settings.get(prefs::HYPHENATION) {
+                Some(true) => Some(Box::new(hyphen::Hyphenation::new(
+                    context.book_lang.clone(),
+                ))),
+                _ => None,
+            },
+            Pass::ReadingEmphasis => settings
+                .get::<EmphasisStrength>(prefs::READING_EMPHASIS)
+                .map(|strength| {
+                    Box::new(emphasis::ReadingEmphasis::new(
+                        strength,
+                        context.book_lang.clone(),
+                    )) as Box<dyn ContentTransform>
+                }),
+            Pass::Notes => Some(Box::<notes::Notes>::default()),
+            Pass::Links => Some(Box::new(links::Links::new(
+                context.doc_url.clone(),
+                context.off_spine.clone(),
+            ))),
+            Pass::NamespaceIds => context.id_prefix.clone().map(|prefix| {
+                Box::new(stitch::NamespaceIds::new(prefix)) as Box<dyn ContentTransform>
+            }),
+        }
+    }
+}
+
+/// Where a CSS token is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CssPosition {
+    /// In a selector or an at-rule prelude.
+    Prelude,
+    /// The property name of a declaration.
+    Property,
+    /// The value of a declaration.
+    Value,
+}
+
+/// What a `ContentTransform` is told about a CSS token.
+pub struct CssContext<'a> {
+    pub position: CssPosition,
+    /// Lowercase property name if `position` is `Value`, otherwise empty.
+    pub property: &'a str,
+    /// Lowercase name of the at-rule, e.g., `import`, if the token is in its prelude,
+    /// otherwise empty.
+    pub at_rule: &'a str,
+    /// Whether the token is inside a function, parentheses or square brackets.
+    pub nested: bool,
+}
+
+/// A rewriting step applied to the content served to the reader.
+///
+/// Each hook defaults to leaving the content unchanged.
+pub trait ContentTransform {
+    /// Returns the replacement of a CSS token, or `None` to let the next pass decide.
+    /// The first pass returning `Some` wins.
+    fn css_token(&mut self, _token: &Token<'_>, _ctx: &CssContext<'_>) -> Option<String> {
+        None
+    }
+
+    /// Returns the replacement of a function call, or `None` to let the next pass decide.
+    /// `arguments` is what is between the parentheses, already transformed.
+    fn css_function(
+        &mut self,
+        _name: &str,
+        _arguments: &str,
+        _ctx: &CssContext<'_>,
+    ) -> Option<String> {
+        None
+    }
+
+    /// Returns the replacement of the prelude of a rule with a block, e.g., a selector list
+    /// or `@media print`. Unlike the other CSS hooks, each pass sees the output of the last.
+    fn css_prelude(&mut self, _prelude: &str) -> Option<String> {
+        None
+    }
+
+    /// Whether to keep a rule, given its prelude (e.g., `p.note` or `@import url(a.css)`)
+    /// and the content of its block if any, both already transformed.
+    /// Every pass sees every rule; the rule is dropped if any pass returns `false`.
+    fn keep_css_rule(&mut self, _prelude: &str, _block: Option<&str>) -> bool {
+        true
+    }
+
+    /// Pushes what replaces `event` to `out`. Events pushed are seen by the next pass.
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        out.push(event);
+    }
+
+    /// Returns CSS to put in a `<style>` at the end of the `<head>`, once the whole XHTML
+    /// document has been seen. It is not transformed by the passes.
+    fn head_css(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Scales `line-height` with `--og-line-height-scale`.
+struct LineHeight;
+
+impl ContentTransform for LineHeight {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if ctx.position != CssPosition::Value || ctx.nested || ctx.property != "line-height" {
+            return None;
+        }
+        let value = match token {
+            // left to `Units`
+            Token::Dimension { int_value, .. } if int_value.is_some_and(|i| i == 0) => {
+                return None;
+            }
+            Token::Ident(ident) if ident.eq_ignore_ascii_case("normal") => LineHeightValue::Normal,
+            Token::Percentage { unit_value, .. } => LineHeightValue::Percentage(*unit_value),
+            Token::Number { value, .. } => LineHeightValue::Number(*value),
+            Token::Dimension { value, unit, .. } => {
+                LineHeightValue::Length(*value, ArrayString::from(unit).unwrap_or_default())
+            }
+            _ => return None,
+        };
+        Some(regulated_line_height(value))
+    }
+}
+
+/// Puts a `--og-font-*` custom property before each font family.
+struct FontFamily;
+
+impl ContentTransform for FontFamily {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if ctx.position != CssPosition::Value || ctx.nested || ctx.property != "font-family" {
+            return None;
+        }
+        match token {
+            Token::Ident(value) | Token::QuotedString(value) => Some(format!(
+                "{}, {}",
+                font_custom_property_ref(value),
+                token.to_css_string()
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Converts absolute lengths and font size keywords to `rem`.
+struct Units;
+
+impl ContentTransform for Units {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        match token {
+            Token::Dimension { int_value, .. } if int_value.is_some_and(|i| i == 0) => {
+                Some(String::from("0"))
+            }
+            Token::Dimension { value, unit, .. } => {
+                abs_length_in_rem(*value, unit).map(|rem| format!("{rem:.2}rem"))
+            }
+            Token::Ident(ident) if ctx.position == CssPosition::Value => {
+                sml_in_rem(ident).map(|rem| format!("{rem:.2}rem"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name of the custom property that substitutes the font family `name`.
+fn font_custom_property(name: &str) -> String {
+    let mut out = String::from("--og-font-");
+    for b in name.to_lowercase().as_bytes() {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
+
+fn font_custom_property_ref(name: &str) -> String {
+    format!("var({})", font_custom_property(name))
+}
+
+/// Attributes of SVG elements that set CSS properties, rewritten as declarations. Color
+/// attributes are left to `color::Colors`, which remaps them in place.
+const PRESENTATION_ATTRIBUTES: &[&str] =
+    &["font-family", "font-size", "letter-spacing", "word-spacing"];
+
+/// Attribute put on SVG elements whose presentation attributes move to generated rules.
+const PRESENTATION_ATTR: &str = "data-og-presentation";
+
+/// Rules of presentation attributes whose values need custom properties, which attributes
+/// can't use. `:where()` keeps them as weak as the attributes against other rules.
+#[derive(Default)]
+struct PresentationRules {
+    count: usize,
+    css: String,
+}
+
+/// Kind of the CSS block being transformed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CssBlock {
+    /// A stylesheet, or the block of a conditional group rule such as `@media`.
+    Rules,
+    /// The block of a style rule, or a `style` attribute.
+    Declarations,
+}
+
+/// At-rules whose block contains rules rather than declarations.
+fn is_group_at_rule(name: &str) -> bool {
+    matches!(
+        name,
+        "media" | "supports" | "document" | "-moz-document" | "layer" | "container" | "scope"
+    )
+}
+
+/// The URL that the `arguments` of a `url()` function quote, if they do.
+fn quoted_url(arguments: &str) -> Option<&str> {
+    let value = arguments.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+}
+
+/// What passes know about the book being served, besides settings.
+#[derive(Default)]
+pub struct Context {
+    /// Language of the book, from `dc:language`.
+    pub book_lang: Option<String>,
+    /// URL of the resource being served.
+    pub doc_url: Option<Url>,
+    /// Path from `<body>` of the first element of the part served, if the document is
+    /// split, see `DocumentSplit`.
+    pub part_path: Vec<usize>,
+    /// Prefix of IDs if the document is stitched into the continuous one, see `id_prefix`.
+    pub id_prefix: Option<String>,
+    /// Media types of the resources in the manifest but not in the spine.
+    pub off_spine: HashMap<Url, String>,
+    /// Whether the resource is marked `scripted` in the manifest.
+    pub scripted: bool,
+    /// Whether the document is shown on its own, e.g., an SVG image, out of reach of the
+    /// custom properties and the shadow host of the reader.
+    pub standalone: bool,
+    /// Where passes put the remote resources they block.
+    pub blocked_remote: Rc<RefCell<BTreeSet<Url>>>,
+    /// The global and per-book stylesheets of the user, to apply after the styles of the
+    /// document. Left as written, even if publisher styles are ignored.
+    pub user_css: String,
+}
+
+impl Context {
+    /// Sets the document up to be stitched into the continuous one, as the one at `index`
+    /// in the spine. It shares the frame of the reader, so it never keeps its scripts.
+    pub fn stitch(&mut self, index: usize) {
+        self.id_prefix = Some(stitch::id_prefix(index));
+        self.scripted = false;
+    }
+}
+
+/// The transforms to run, in order, on one served resource.
+pub struct Pipeline {
+    passes: Vec<Box<dyn ContentTransform>>,
+    /// Put in a `<style>` at the end of the `<head>` of XHTML documents, as is.
+    user_css: String,
+}
+
+impl Pipeline {
+    pub fn new(settings: &Settings, context: &Context) -> Self {
+        Self::of_passes(Pass::enabled(settings), settings, context)
+    }
+
+    /// A pipeline running only `passes`, in the given order.
+    fn of_passes<I>(passes: I, settings: &Settings, context: &Context) -> Self
+    where
+        I: IntoIterator<Item = Pass>,
+    {
+        Self {
+            passes: passes
+                .into_iter()
+                .filter_map(|pass| pass.make(settings, context))
+                .collect(),
+            user_css: context.user_css.clone(),
+        }
+    }
+
+    /// A pipeline running only `pass`, with no user styles.
+    #[cfg(test)]
+    fn with(pass: impl ContentTransform + 'static) -> Self {
+        Self {
+            passes: vec![Box::new(pass)],
+            user_css: String::new(),
+        }
+    }
+
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> String {
+        self.passes
+            .iter_mut()
+            .find_map(|pass| pass.css_token(token, ctx))
+            .unwrap_or_else(|| token.to_css_string())
+    }
+
+    fn transform_css<'i>(
+        &mut self,
+        parser: &mut Parser<'i, '_>,
+        output: &mut String,
+        block: CssBlock,
+    ) -> Result<(), ParseError<'i, ()>> {
+        let start = match block {
+            CssBlock::Rules => CssPosition::Prelude,
+            CssBlock::Declarations => CssPosition::Property,
+        };
+        let mut position = start;
+        let mut property = String::new();
+        let mut at_rule = None::<String>;
+        let mut rule_start = output.len();
+
+        while let Ok(token) = parser.next_including_whitespace() {
+            let ctx = CssContext {
+                position,
+                property: &property,
+                at_rule: at_rule.as_deref().unwrap_or_default(),
+                nested: false,
+            };
+            let s = self.css_token(token, &ctx);
+            if !matches!(token, Token::Function(_)) {
+                output.push_str(&s);
+            }
+
+            match token {
+                Token::Semicolon => {
+                    position = start;
+                    property.clear();
+                    at_rule = None;
+                    if block == CssBlock::Rules {
+                        self.end_css_rule(output, rule_start, None);
+                        rule_start = output.len();
+                    }
+                }
+                Token::AtKeyword(name) if position == CssPosition::Prelude => {
+                    at_rule = Some(name.to_ascii_lowercase());
+                }
+                Token::Ident(_) if position == CssPosition::Property => {
+                    property = s.trim().to_ascii_lowercase();
+                }
+                Token::Colon if position == CssPosition::Property => {
+                    position = CssPosition::Value;
+                }
+                Token::CurlyBracketBlock => {
+                    let inner = match at_rule.take() {
+                        Some(name) if is_group_at_rule(&name) => CssBlock::Rules,
+                        _ => CssBlock::Declarations,
+                    };
+                    if block == CssBlock::Rules {
+                        self.rewrite_css_prelude(output, rule_start);
+                    }
+                    let block_start = output.len() - 1;
+                    parser.parse_nested_block(|parser_nested| {
+                        self.transform_css(parser_nested, output, inner)
+                    })?;
+                    output.push('}');
+                    position = start;
+                    property.clear();
+                    if block == CssBlock::Rules {
+                        self.end_css_rule(output, rule_start, Some(block_start));
+                        rule_start = output.len();
+                    }
+                }
+                Token::Function(name) => {
+                    let name = name.to_ascii_lowercase();
+                    let ctx = CssContext {
+                        position,
+                        property: &property,
+                        at_rule: at_rule.as_deref().unwrap_or_default(),
+                        nested: false,
+                    };
+                    self.transform_css_function(parser, output, &name, &s, &ctx)?;
+                }
+                Token::ParenthesisBlock | Token::SquareBracketBlock => {
+                    let close = match token {
+                        Token::SquareBracketBlock => ']',
+                        _ => ')',
+                    };
+                    let ctx = CssContext {
+                        position,
+                        property: &property,
+                        at_rule: at_rule.as_deref().unwrap_or_default(),
+                        nested: false,
+                    };
+                    parser.parse_nested_block(|parser_nested| {
+                        self.transform_css_nested(parser_nested, output, &ctx)
+                    })?;
+                    output.push(close);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets passes rewrite the prelude written to `output` since `rule_start`.
+    /// `output` ends with the `{` of the rule.
+    fn rewrite_css_prelude(&mut self, output: &mut String, rule_start: usize) {
+        let end = output.len() - 1;
+        let untrimmed = &output[rule_start..end];
+        let start = rule_start + (untrimmed.len() - untrimmed.trim_start().len());
+        let end = rule_start + untrimmed.trim_end().len();
+
+        let mut prelude = None::<String>;
+        for pass in self.passes.iter_mut() {
+            let current = prelude.as_deref().unwrap_or(&output[start..end]);
+            if let Some(replacement) = pass.css_prelude(current) {
+                prelude = Some(replacement);
+            }
+        }
+        if let Some(prelude) = prelude {
+            output.replace_range(start..end, &prelude);
+        }
+    }
+
+    /// Drops the rule written to `output` since `rule_start` unless all passes keep it.
+    /// `block_start` is where its `{` is, if the rule has a block.
+    fn end_css_rule(&mut self, output: &mut String, rule_start: usize, block_start: Option<usize>) {
+        let (prelude, block) = match block_start {
+            Some(i) => (
+                &output[rule_start..i],
+                Some(&output[i + 1..output.len() - 1]),
+            ),
+            None => (&output[rule_start..output.len() - 1], None),
+        };
+        let prelude = prelude.trim();
+        let mut keep = true;
+        for pass in self.passes.iter_mut() {
+            keep &= pass.keep_css_rule(prelude, block);
+        }
+        if !keep {
+            output.truncate(rule_start);
+        }
+    }
+
+    /// Transforms the inside of a function, parentheses or square brackets, in `outer`.
+    fn transform_css_nested<'i>(
+        &mut self,
+        parser: &mut Parser<'i, '_>,
+        output: &mut String,
+        outer: &CssContext<'_>,
+    ) -> Result<(), ParseError<'i, ()>> {
+        while let Ok(token) = parser.next_including_whitespace() {
+            let ctx = CssContext {
+                nested: true,
+                ..*outer
+            };
+            let s = self.css_token(token, &ctx);
+
+            let close = match token {
+                Token::Function(name) => {
+                    let name = name.to_ascii_lowercase();
+                    self.transform_css_function(parser, output, &name, &s, &ctx)?;
+                    continue;
+                }
+                Token::ParenthesisBlock => Some(')'),
+                Token::SquareBracketBlock => Some(']'),
+                Token::CurlyBracketBlock => Some('}'),
+                _ => None,
+            };
+            output.push_str(&s);
+            if let Some(close) = close {
+                parser.parse_nested_block(|parser_nested| {
+                    self.transform_css_nested(parser_nested, output, &ctx)
+                })?;
+                output.push(close);
+            }
+        }
+        Ok(())
+    }
+
+    /// Transforms the arguments of a function, then lets passes replace the whole function.
+    /// `opening` is the function name and the opening parenthesis.
+    fn transform_css_function<'i>(
+        &mut self,
+        parser: &mut Parser<'i, '_>,
+        output: &mut String,
+        name: &str,
+        opening: &str,
+        ctx: &CssContext<'_>,
+    ) -> Result<(), ParseError<'i, ()>> {
+        let mut arguments = String::new();
+        parser.parse_nested_block(|parser_nested| {
+            self.transform_css_nested(parser_nested, &mut arguments, ctx)
+        })?;
+
+        let replacement = self
+            .passes
+            .iter_mut()
+            .find_map(|pass| pass.css_function(name, &arguments, ctx));
+        match replacement {
+            Some(replacement) => output.push_str(&replacement),
+            None => {
+                output.push_str(opening);
+                output.push_str(&arguments);
+                output.push(')');
+            }
+        }
+        Ok(())
+    }
+
+    fn alter_css_str(&mut self, css: &str, block: CssBlock) -> Result<String, AnyErr> {
+        let mut output = String::new();
+
+        let mut input = ParserInput::new(css);
+        let mut parser = Parser::new(&mut input);
+
+        self.transform_css(&mut parser, &mut output, block)
+            .map_err(|_| AnyErr::EpubContent)?;
+
+        Ok(output)
+    }
+
+    /// Runs `event` through the XHTML hooks of all passes.
+    fn xhtml_events(&mut self, event: Event<'static>) -> Vec<Event<'static>> {
+        let mut events = vec![event];
+        for pass in self.passes.iter_mut() {
+            let mut out = Vec::with_capacity(events.len());
+            for event in events {
+                pass.xhtml_event(event, &mut out);
+            }
+            events = out;
+        }
+        events
+    }
+
+    /// Returns the value of the presentation attribute `name` as transformed, or `None` if
+    /// unchanged or no longer a value of `name`.
+    fn alter_presentation_attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        let declaration = format!("{name}: {value}");
+        let css = self
+            .alter_css_str(&declaration, CssBlock::Declarations)
+            .ok()?;
+        if css == declaration {
+            return None;
+        }
+        let value = css.strip_prefix(name)?.trim_start().strip_prefix(':')?;
+        Some(String::from(value.trim()))
+    }
+
+    /// Returns `e` with its `style` attribute, and its presentation attributes if in SVG,
+    /// transformed, or `None` if they don't change.
+    fn alter_attributes(
+        &mut self,
+        e: &BytesStart<'_>,
+        in_svg: bool,
+        decoder: Decoder,
+        rules: &mut PresentationRules,
+    ) -> Result<Option<BytesStart<'static>>, quick_xml::Error> {
+        let mut start = e.to_owned();
+        start.clear_attributes();
+        let mut changed = false;
+        let mut moved = String::new();
+        for attr in e.attributes().flatten() {
+            let key = attr.key.as_ref();
+            if key.eq_ignore_ascii_case(b"style") {
+                let css = attr.decode_and_unescape_value(decoder)?;
+                if let Ok(css) = self.alter_css_str(&css, CssBlock::Declarations) {
+                    start.push_attribute(("style", css.as_str()));
+                    changed = true;
+                    continue;
+                }
+            } else if let Some(name) = PRESENTATION_ATTRIBUTES
+                .iter()
+                .find(|name| in_svg && name.as_bytes() == key)
+            {
+                let value = attr.decode_and_unescape_value(decoder)?;
+                if let Some(value) = self.alter_presentation_attribute(name, &value) {
+                    if value.contains("var(") {
+                        moved.push_str(&format!("{name}: {value}; "));
+                    } else {
+                        start.push_attribute((*name, value.as_str()));
+                    }
+                    changed = true;
+                    continue;
+                }
+            }
+            start.push_attribute(attr);
+        }
+        if !moved.is_empty() {
+            rules.count += 1;
+            let id = rules.count.to_string();
+            start.push_attribute((PRESENTATION_ATTR, id.as_str()));
+            rules.css += &format!(":where([{PRESENTATION_ATTR}=\"{id}\"]) {{ {moved}}}\n");
+        }
+        Ok(changed.then(|| start.into_owned()))
+    }
+
+    /// Returns the `<style>` to put at the end of the `<head>`, or an empty string if there
+    /// are no styles to add.
+    fn head_style(&mut self) -> String {
+        let mut css: String = self
+            .passes
+            .iter_mut()
+            .filter_map(|pass| pass.head_css())
+            .collect();
+        css += &self.user_css;
+        if css.is_empty() {
+            return css;
+        }
+        format!("<style>{}</style>", partial_escape(&css))
+    }
+
+    /// Transforms an XHTML or SVG document. In SVG, presentation attributes are transformed
+    /// like declarations, and those that move to rules are put in a `<style>` at the end of
+    /// the outermost `<svg>`. The CSS of `ContentTransform::head_css` and user styles are
+    /// put in the last `<style>` of the `<head>`.
+    fn transform_xhtml<R: BufRead>(&mut self, reader: R) -> Result<Vec<u8>, quick_xml::Error> {
+        let mut reader = Reader::from_reader(reader);
+        reader.config_mut().trim_text(false);
+        let decoder = reader.decoder();
+
+        let mut buffer = Vec::new();
+        let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+
+        let mut is_css = false;
+        // elements open in the outermost `<svg>`, including it
+        let mut svg_depth = 0;
+        let mut rules = PresentationRules::default();
+        // where the `</head>` is in the output
+        let mut head_end = None;
+        loop {
+            let evt = reader.read_event_into(&mut buffer)?;
+            if let Event::Eof = evt {
+                // done
+                let mut output = writer.into_inner().into_inner();
+                if let Some(head_end) = head_end {
+                    let style = self.head_style();
+                    output.splice(head_end..head_end, style.into_bytes());
+                }
+                return Ok(output);
+            }
+            let evt = evt.into_owned();
+            buffer.clear();
+
+            for evt in self.xhtml_events(evt) {
+                let mut replace = None;
+                match evt {
+                    Event::Start(ref e) if e.local_name().as_ref() == b"style" => {
+                        is_css = true;
+                    }
+                    Event::Text(ref e) if is_css => {
+                        let css = e.unescape()?;
+                        let css = self
+                            .alter_css_str(&css, CssBlock::Rules)
+                            .unwrap_or_else(|_| String::from(css));
+                        replace = Some(Event::Text(BytesText::from_escaped(css)));
+                    }
+                    Event::End(_) if is_css => {
+                        is_css = false;
+                    }
+                    Event::End(ref e) if e.local_name().as_ref() == b"head" => {
+                        head_end = Some(writer.get_ref().get_ref().len());
+                    }
+
+                    Event::Start(ref e) | Event::Empty(ref e) => {
+                        let is_start = matches!(evt, Event::Start(_));
+                        let in_svg = svg_depth > 0 || e.local_name().as_ref() == b"svg";
+                        if let Some(e) = self.alter_attributes(e, in_svg, decoder, &mut rules)? {
+                            replace = Some(if is_start {
+                                Event::Start(e)
+                            } else {
+                                Event::Empty(e)
+                            });
+                        }
+                        if in_svg && is_start {
+                            svg_depth += 1;
+                        }
+                    }
+                    Event::End(_) if svg_depth > 0 => {
+                        svg_depth -= 1;
+                        if svg_depth == 0 && !rules.css.is_empty() {
+                            let css = std::mem::take(&mut rules.css);
+                            let _ = writer.write_event(Event::Start(BytesStart::new("style")));
+                            let _ = writer.write_event(Event::Text(BytesText::from_escaped(
+                                partial_escape(&css),
+                            )));
+                            let _ = writer.write_event(Event::End(BytesEnd::new("style")));
+                        }
+                    }
+                    _ => {}
+                }
+                let _ = writer.write_event(replace.unwrap_or(evt));
+            }
+        }
+    }
+}
+
+/// Settings with `key` alone set to `value`, per book, for tests of the passes it drives.
+#[cfg(test)]
+fn settings_with(key: &str, value: serde_json::Value) -> Settings {
+    let mut filewise = serde_json::Map::new();
+    filewise.insert(key.into(), value);
+    Settings::new(filewise, serde_json::Map::new())
+}
+
+pub fn alter_css<R: Read>(mut reader: R, pipeline: &mut Pipeline) -> Result<Vec<u8>, AnyErr> {
+    let mut css = String::new();
+    reader.read_to_string(&mut css)?;
+    pipeline.alter_css_str(&css, CssBlock::Rules).map(Vec::from)
+}
+
+pub fn alter_xhtml<R: Read>(reader: R, pipeline: &mut Pipeline) -> Result<Vec<u8>, AnyErr> {
+    pipeline
+        .transform_xhtml(BufReader::new(reader))
+        .map_err(|_| AnyErr::EpubContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alter_css_str(css: &str) -> Result<String, AnyErr> {
+        Pipeline::new(&Settings::default(), &Context::default()).alter_css_str(css, CssBlock::Rules)
+    }
+
+    fn alter_xhtml_default(reader: &[u8]) -> Result<Vec<u8>, AnyErr> {
+        alter_xhtml(
+            reader,
+            &mut Pipeline::new(&Settings::default(), &Context::default()),
+        )
+    }
+
+    #[test]
+    fn test_alter_css_font_size() {
+        let input =
+            "body { font-size: 16px; margin: 32px; } p { padding: 8px; } a { font-size: medium; }";
+        let expected = "body { font-size: 1.00rem; margin: 2.00rem; } p { padding: 0.50rem; } a { font-size: 1.00rem; }";
+        assert_eq!(expected, alter_css_str(input).unwrap());
+    }
+
+    #[test]
+    fn test_alter_css_nesting() {
+        let input = "body { color: green; p { color: red; a { color: blue } } }";
+        let expected = "body { color: green; p { color: red; a { color: blue } } }";
+        assert_eq!(expected, alter_css_str(input).unwrap());
+    }
+
+    #[test]
+    fn test_alter_css_font_substitute() {
+        let input = r#":host {
+            font-family: "Noto Serif",
+                serif;
+        }
+        head {}"#;
+        let expected = r#":host {
+            font-family: var(--og-font-6e6f746f207365726966), "Noto Serif",
+                var(--og-font-7365726966), serif;
+        }
+        head {}"#;
+        assert_eq!(expected, alter_css_str(input).unwrap());
+    }
+
+    #[test]
+    fn test_alter_xhtml_style() {
+        let input = r#"<html><head>
+            <style>
+                p {
+                    color: blue;
+                    line-height: 1;
+                }
+            </style>
+        </head></html>"#;
+        let expected = r#"<html><head>
+            <style>
+                p {
+                    color: blue;
+                    line-height: calc(var(--og-line-height-scale) * 1.00);
+                }
+            </style>
+        </head></html>"#;
+        let reader = input.as_bytes();
+        assert_eq!(Vec::from(expected), alter_xhtml_default(reader).unwrap());
+    }
+
+    #[test]
+    fn test_alter_xhtml_style_inline() {
+        let input = "<html><body style=\"line-height:1\"></body></html>";
+        let expected = "<html><body style=\"line-height:calc(var(--og-line-height-scale) * 1.00)\"></body></html>";
+        let reader = input.as_bytes();
+        assert_eq!(Vec::from(expected), alter_xhtml_default(reader).unwrap());
+    }
+
+    #[test]
+    fn test_alter_svg_presentation() {
+        let input = r#"<svg xmlns="http://www.w3.org/2000/svg"><style>text { font-size: 12pt; }</style><text font-family="Georgia" font-size="24px" fill="red">A</text><rect style="stroke-width: 1px" stroke="blue"/></svg>"#;
+        let expected = r#"<svg xmlns="http://www.w3.org/2000/svg"><style>text { font-size: 1.00rem; }</style><text font-size="1.50rem" fill="red" data-og-presentation="1">A</text><rect style="stroke-width: 0.06rem" stroke="blue"/><style>:where([data-og-presentation="1"]) { font-family: var(--og-font-67656f72676961), Georgia; }
+</style></svg>"#;
+        let output = alter_xhtml_default(input.as_bytes()).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_alter_svg_standalone() {
+        let context = Context {
+            standalone: true,
+            ..Default::default()
+        };
+        let mut pipeline = Pipeline::new(&Settings::default(), &context);
+        let input = r#"<svg xmlns="http://www.w3.org/2000/svg"><text font-family="Georgia" font-size="12pt">A</text></svg>"#;
+        let expected = r#"<svg xmlns="http://www.w3.org/2000/svg"><text font-family="Georgia" font-size="1.00rem">A</text></svg>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_alter_xhtml_user_css() {
+        let context = Context {
+            user_css: String::from("p { line-height: 2; }\na > b {}\n"),
+            ..Default::default()
+        };
+        let mut pipeline = Pipeline::new(&Settings::default(), &context);
+        let input = "<html><head><style>p { color: red; }</style></head></html>";
+        let expected = "<html><head><style>p { color: red; }</style><style>p { line-height: 2; }\na &gt; b {}\n</style></head></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_transforms() {
+        let settings = settings_with(
+            prefs::TRANSFORMS,
+            serde_json::json!(["colors", "scripts", "line-height", "colors"]),
+        );
+        assert_eq!(
+            vec![
+                Pass::Colors,
+                Pass::Scripts,
+                Pass::LineHeight,
+                Pass::RemoteResources
+            ],
+            Pass::enabled(&settings)
+        );
+        assert_eq!(Pass::DEFAULT, Pass::enabled(&Settings::default()));
+
+        let mut pipeline = Pipeline::new(&settings, &Context::default());
+        let input = r#"<body><script>alert(1)</script><p style="margin: 12pt">a</p></body>"#;
+        let expected = r#"<body><p style="margin: 12pt">a</p></body>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
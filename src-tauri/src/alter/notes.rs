@@ -0,0 +1,246 @@
+use std::io;
+
+use quick_xml::{
+    Reader, Writer,
+    events::{BytesEnd, BytesStart, Event, attributes::Attribute},
+};
+
+use super::{
+    ContentTransform,
+    script::{is_dropped, without_scripts},
+};
+use crate::errors::AnyErr;
+
+/// Attribute put on note references, for the reader to show notes in a pop-up.
+const NOTEREF_ATTR: &str = "data-og-noteref";
+
+/// Whether a space separated attribute of `e`, e.g., `epub:type`, has any of `values`.
+fn has_token(e: &BytesStart<'_>, name: &str, values: &[&str]) -> bool {
+    match e.try_get_attribute(name) {
+        Ok(Some(attr)) => String::from_utf8_lossy(&attr.value)
+            .split_ascii_whitespace()
+            .any(|token| values.iter().any(|v| token.eq_ignore_ascii_case(v))),
+        _ => false,
+    }
+}
+
+fn is_semantic_noteref(e: &BytesStart<'_>) -> bool {
+    has_token(e, "epub:type", &["noteref"]) || has_token(e, "role", &["doc-noteref"])
+}
+
+fn is_semantic_note(e: &BytesStart<'_>) -> bool {
+    has_token(e, "epub:type", &["footnote", "endnote", "rearnote", "note"])
+        || has_token(e, "role", &["doc-footnote", "doc-endnote"])
+}
+
+fn has_fragment_href(e: &BytesStart<'_>) -> bool {
+    matches!(e.try_get_attribute("href"), Ok(Some(attr)) if attr.value.contains(&b'#'))
+}
+
+/// Elements too small to stand for a note by themselves.
+fn is_inline(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"a" | b"span" | b"sup" | b"sub" | b"b" | b"i" | b"em" | b"strong" | b"small" | b"cite"
+    )
+}
+
+/// Elements dropped from notes, with their content.
+fn is_unsafe(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"script" | b"style" | b"iframe" | b"object" | b"embed" | b"form" | b"link" | b"meta"
+    )
+}
+
+/// Tags anchors referring to notes, by `epub:type="noteref"`, `role="doc-noteref"`,
+/// or as a fallback, by being in a `<sup>` and pointing to a fragment.
+#[derive(Default)]
+pub struct Notes {
+    /// Whether each open element is a `<sup>`.
+    in_sup: Vec<bool>,
+}
+
+impl ContentTransform for Notes {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        let is_noteref = |e: &BytesStart<'_>, in_sup: bool| {
+            e.local_name().as_ref() == b"a"
+                && (is_semantic_noteref(e) || (in_sup && has_fragment_href(e)))
+        };
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let in_sup = self.in_sup.last().copied().unwrap_or_default();
+                let tagged = is_noteref(e, in_sup).then(|| {
+                    let mut e = e.to_owned();
+                    e.push_attribute((NOTEREF_ATTR, ""));
+                    e
+                });
+                let event = match (event, tagged) {
+                    (Event::Start(_), Some(e)) => Event::Start(e),
+                    (Event::Empty(_), Some(e)) => Event::Empty(e),
+                    (event, _) => event,
+                };
+                if let Event::Start(ref e) = event {
+                    self.in_sup.push(e.local_name().as_ref() == b"sup");
+                }
+                out.push(event);
+            }
+            Event::End(_) => {
+                self.in_sup.pop();
+                out.push(event);
+            }
+            event => out.push(event),
+        }
+    }
+}
+
+/// Finds the note with `id` in `xhtml`, and returns it in a `<div>` declaring the namespaces
+/// of the document, or `None` if there is no such element.
+///
+/// The note is the nearest element marked as a note containing `id`, or else the element
+/// with `id` itself, or the nearest block around it if it is inline, e.g., `<a id="fn1">`.
+pub fn extract_note(xhtml: &[u8], id: &str) -> Result<Option<String>, AnyErr> {
+    let mut reader = Reader::from_reader(xhtml);
+    reader.config_mut().trim_text(false);
+
+    let mut events = Vec::new();
+    // index of the element each event is in, or closes
+    let mut parents = Vec::new();
+    let mut stack = Vec::<usize>::new();
+    loop {
+        let event = reader.read_event().map_err(|_| AnyErr::EpubContent)?;
+        if let Event::Eof = event {
+            break;
+        }
+        // an end belongs to the element it closes
+        parents.push(match event {
+            Event::End(_) => stack.pop(),
+            _ => stack.last().copied(),
+        });
+        if let Event::Start(_) = event {
+            stack.push(events.len());
+        }
+        events.push(event.into_owned());
+    }
+
+    let element = |i: usize| match &events[i] {
+        Event::Start(e) | Event::Empty(e) => Some(e),
+        _ => None,
+    };
+    let Some(target) = (0..events.len()).find(|&i| {
+        element(i).is_some_and(|e| {
+            matches!(e.try_get_attribute("id"), Ok(Some(attr)) if *attr.value == *id.as_bytes())
+        })
+    }) else {
+        return Ok(None);
+    };
+    let ancestors: Vec<usize> = std::iter::successors(Some(target), |&i| parents[i]).collect();
+    let start = ancestors
+        .iter()
+        .find(|&&i| element(i).is_some_and(is_semantic_note))
+        .or_else(|| {
+            ancestors.iter().find(|&&i| {
+                element(i).is_some_and(|e| !is_inline(e.local_name().as_ref()))
+                    && (i != target || matches!(events[i], Event::Start(_)))
+            })
+        });
+    let Some(&start) = start else {
+        return Ok(None);
+    };
+    let end = match events[start] {
+        Event::Start(_) => (start + 1..events.len())
+            .find(|&i| matches!(events[i], Event::End(_)) && parents[i] == Some(start)),
+        _ => Some(start),
+    };
+    let Some(end) = end else {
+        return Ok(None);
+    };
+
+    // namespaces declared around the note
+    let mut wrapper = BytesStart::new("div");
+    wrapper.push_attribute(("xmlns", "http://www.w3.org/1999/xhtml"));
+    for &i in ancestors.iter().filter(|&&i| i < start) {
+        if let Some(e) = element(i) {
+            for attr in e.attributes().flatten() {
+                let key = attr.key.as_ref();
+                if key.starts_with(b"xmlns:") {
+                    wrapper.push_attribute(Attribute::from((key, attr.value.as_ref())));
+                }
+            }
+        }
+    }
+
+    let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+    let _ = writer.write_event(Event::Start(wrapper));
+    let mut skipping = 0;
+    for event in events.drain(start..=end) {
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        let event = match event {
+            Event::Start(e) if is_unsafe(e.local_name().as_ref()) || is_dropped(&e) => {
+                skipping = 1;
+                continue;
+            }
+            Event::Empty(e) if is_unsafe(e.local_name().as_ref()) || is_dropped(&e) => continue,
+            Event::Start(e) => Event::Start(without_scripts(&e).unwrap_or(e)),
+            Event::Empty(e) => Event::Empty(without_scripts(&e).unwrap_or(e)),
+            event => event,
+        };
+        let _ = writer.write_event(event);
+    }
+    let _ = writer.write_event(Event::End(BytesEnd::new("div")));
+    String::from_utf8(writer.into_inner().into_inner())
+        .map(Some)
+        .map_err(|_| AnyErr::EpubContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_notes_tagging() {
+        let mut pipeline = Pipeline::with(Notes::default());
+        let input = r##"<p>a<a epub:type="noteref" href="n.xhtml#n1">1</a> b<sup><a href="#n2">2</a></sup> <a href="#c">c</a></p>"##;
+        let expected = r##"<p>a<a epub:type="noteref" href="n.xhtml#n1" data-og-noteref="">1</a> b<sup><a href="#n2" data-og-noteref="">2</a></sup> <a href="#c">c</a></p>"##;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_extract_note() {
+        let input = r##"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<aside epub:type="footnote" id="n1"><p>One <b onclick="x()">bold</b><script>x()</script></p></aside>
+<p><a id="n2" href="#r2">2</a> Two</p>
+<ol><li id="n3">Three <a href="javascript:x()">x</a></li></ol>
+</body></html>"##;
+        let xhtml = input.as_bytes();
+        let wrap = |s: &str| {
+            format!(
+                r#"<div xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">{s}</div>"#
+            )
+        };
+        assert_eq!(
+            Some(wrap(
+                r#"<aside epub:type="footnote" id="n1"><p>One <b>bold</b></p></aside>"#
+            )),
+            extract_note(xhtml, "n1").unwrap()
+        );
+        assert_eq!(
+            Some(wrap(r##"<p><a id="n2" href="#r2">2</a> Two</p>"##)),
+            extract_note(xhtml, "n2").unwrap()
+        );
+        assert_eq!(
+            Some(wrap(r#"<li id="n3">Three <a>x</a></li>"#)),
+            extract_note(xhtml, "n3").unwrap()
+        );
+        assert_eq!(None, extract_note(xhtml, "n4").unwrap());
+    }
+}
@@ -0,0 +1,197 @@
+use cssparser::Token;
+use quick_xml::events::{BytesStart, Event};
+
+use super::{ContentTransform, CssContext, CssPosition, abs_length_in_rem};
+use crate::prefs::PublisherStyles as Level;
+
+/// Presentational attributes of (X)HTML, which are styles in disguise.
+fn is_presentational_attribute(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"align"
+            | b"bgcolor"
+            | b"background"
+            | b"color"
+            | b"face"
+            | b"text"
+            | b"link"
+            | b"vlink"
+            | b"alink"
+    )
+}
+
+/// Elements that only carry styles. Their content is kept.
+fn is_presentational_element(name: &[u8]) -> bool {
+    matches!(name, b"font" | b"center" | b"basefont")
+}
+
+fn is_stylesheet_link(e: &BytesStart<'_>) -> bool {
+    e.local_name().as_ref() == b"link"
+        && matches!(
+            e.try_get_attribute("rel"),
+            Ok(Some(rel)) if std::str::from_utf8(&rel.value).is_ok_and(|rel| {
+                rel.split_ascii_whitespace()
+                    .any(|t| t.eq_ignore_ascii_case("stylesheet"))
+            })
+        )
+}
+
+/// Tones down or removes publisher styles.
+pub struct PublisherStyles {
+    level: Level,
+    /// Depth inside an element being dropped, or 0.
+    skipping: usize,
+}
+
+impl PublisherStyles {
+    pub fn new(level: Level) -> Self {
+        Self { level, skipping: 0 }
+    }
+
+    fn drops_attribute(&self, name: &[u8]) -> bool {
+        match self.level {
+            Level::Keep => false,
+            Level::Normalize => name == b"face",
+            Level::Ignore => name == b"style" || is_presentational_attribute(name),
+        }
+    }
+
+    fn strip_attributes(&self, e: &BytesStart<'static>) -> Option<BytesStart<'static>> {
+        let mut changed = false;
+        let mut start = e.to_owned();
+        start.clear_attributes();
+        for attr in e.attributes().flatten() {
+            let name = attr.key.as_ref().to_ascii_lowercase();
+            if self.drops_attribute(&name)
+                || (name == b"align" && attr.value.eq_ignore_ascii_case(b"justify"))
+            {
+                changed = true;
+                continue;
+            }
+            start.push_attribute(attr);
+        }
+        changed.then_some(start)
+    }
+}
+
+impl ContentTransform for PublisherStyles {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if self.level != Level::Normalize || ctx.nested {
+            return None;
+        }
+        match (ctx.position, token) {
+            // Renamed so that it has no effect, and the font passes leave it alone
+            (CssPosition::Property, Token::Ident(name))
+                if name.eq_ignore_ascii_case("font-family") =>
+            {
+                Some(String::from("--og-publisher-font-family"))
+            }
+            (CssPosition::Value, Token::Ident(value))
+                if matches!(ctx.property, "text-align" | "text-align-last")
+                    && value.eq_ignore_ascii_case("justify") =>
+            {
+                Some(String::from("start"))
+            }
+            (CssPosition::Value, Token::Dimension { value, unit, .. })
+                if abs_length_in_rem(*value, unit).is_some() =>
+            {
+                match ctx.property {
+                    "width" | "min-width" | "inline-size" | "min-inline-size" => {
+                        Some(String::from("auto"))
+                    }
+                    "max-width" | "max-inline-size" => Some(String::from("none")),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn keep_css_rule(&mut self, _prelude: &str, _block: Option<&str>) -> bool {
+        self.level != Level::Ignore
+    }
+
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        if self.skipping > 0 {
+            match event {
+                Event::Start(_) => self.skipping += 1,
+                Event::End(_) => self.skipping -= 1,
+                _ => {}
+            }
+            return;
+        }
+
+        let event = match event {
+            Event::Start(e) | Event::Empty(e)
+                if self.level == Level::Ignore
+                    && is_presentational_element(e.local_name().as_ref()) =>
+            {
+                return;
+            }
+            Event::End(e)
+                if self.level == Level::Ignore
+                    && is_presentational_element(e.local_name().as_ref()) =>
+            {
+                return;
+            }
+            Event::Start(e)
+                if self.level == Level::Ignore
+                    && (e.local_name().as_ref() == b"style" || is_stylesheet_link(&e)) =>
+            {
+                self.skipping = 1;
+                return;
+            }
+            Event::Empty(e) if self.level == Level::Ignore && is_stylesheet_link(&e) => return,
+            Event::PI(e) if self.level == Level::Ignore && e.target() == b"xml-stylesheet" => {
+                return;
+            }
+            Event::Start(e) => Event::Start(self.strip_attributes(&e).unwrap_or(e)),
+            Event::Empty(e) => Event::Empty(self.strip_attributes(&e).unwrap_or(e)),
+            event => event,
+        };
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alter::{Context, CssBlock, Pipeline, alter_xhtml, settings_with};
+    use crate::prefs;
+
+    fn pipeline(level: &str) -> Pipeline {
+        let settings = settings_with(prefs::PUBLISHER_STYLES, serde_json::json!(level));
+        Pipeline::new(&settings, &Context::default())
+    }
+
+    #[test]
+    fn test_normalize() {
+        let input = "p { text-align: justify; width: 300px; max-width: 20em; font-family: Foo; }";
+        let expected = "p { text-align: start; width: auto; max-width: 20em; --og-publisher-font-family: Foo; }";
+        assert_eq!(
+            expected,
+            pipeline("normalize")
+                .alter_css_str(input, CssBlock::Rules)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ignore() {
+        let mut pipeline = pipeline("ignore");
+        assert_eq!(
+            "",
+            pipeline
+                .alter_css_str(
+                    "@import url(a.css);\np { color: red }\n@media print { p { x: y } }",
+                    CssBlock::Rules
+                )
+                .unwrap()
+                .trim()
+        );
+
+        let input = r#"<html><head><link rel="stylesheet" href="a.css"/><style>p { color: red }</style></head><body><p style="color: red" align="center" id="a"><font face="Foo">Hi</font></p></body></html>"#;
+        let expected = r#"<html><head></head><body><p id="a" data-og-id="0">Hi</p></body></html>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::LazyLock;
+
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+
+use super::{
+    ContentTransform,
+    chinese::{Dictionary, char_offsets},
+    hyphen::element_lang,
+};
+
+/// Attribute put on generated `<rt>`, for the reader to leave them out of selections.
+const GENERATED_ATTR: &str = "data-og-ruby";
+
+static PINYIN: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("readings/pinyin.txt")], false));
+static KANA: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("readings/kana.txt")], false));
+static KANJI: LazyLock<Dictionary> =
+    LazyLock::new(|| Dictionary::parse(&[include_str!("readings/kanji.txt")], false));
+
+/// Han characters, and the Japanese iteration mark.
+fn is_han(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+            | '々'
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Language {
+    Chinese,
+    Japanese,
+}
+
+fn language_of(lang: &str) -> Option<Language> {
+    let primary = lang.split('-').next()?;
+    if primary.eq_ignore_ascii_case("zh") {
+        Some(Language::Chinese)
+    } else if primary.eq_ignore_ascii_case("ja") {
+        Some(Language::Japanese)
+    } else {
+        None
+    }
+}
+
+/// Splits `text` into pieces, with the readings of the Han characters found in the
+/// dictionaries of `language`.
+///
+/// Chinese gets a reading per character. Japanese gets one per word, or per character with
+/// its on reading in a compound. A character alone in no word gets none, since its kun
+/// reading depends on the okurigana.
+fn annotate(text: &str, language: Language) -> Vec<(&str, Option<&str>)> {
+    let offsets = char_offsets(text);
+    let chars = offsets.len() - 1;
+    let is_han_at = |i: usize| text[offsets[i]..].starts_with(is_han);
+    let slice = |start: usize, end: usize| &text[offsets[start]..offsets[end]];
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    while start < chars {
+        let run_end = (start..chars)
+            .find(|&i| is_han_at(i) != is_han_at(start))
+            .unwrap_or(chars);
+        if !is_han_at(start) {
+            pieces.push((slice(start, run_end), None));
+            start = run_end;
+            continue;
+        }
+
+        let mut i = start;
+        while i < run_end {
+            match language {
+                Language::Chinese => match PINYIN.longest_match(text, &offsets, i, run_end) {
+                    Some((end, syllables)) if syllables.split(' ').count() == end - i => {
+                        for (j, syllable) in (i..end).zip(syllables.split(' ')) {
+                            pieces.push((slice(j, j + 1), Some(syllable)));
+                        }
+                        i = end;
+                        continue;
+                    }
+                    _ => {}
+                },
+                Language::Japanese => {
+                    if let Some((end, reading)) = KANA.longest_match(text, &offsets, i, run_end) {
+                        pieces.push((slice(i, end), Some(reading)));
+                        i = end;
+                        continue;
+                    }
+                    if run_end - start > 1
+                        && let Some((_, on)) = KANJI.longest_match(text, &offsets, i, i + 1)
+                    {
+                        pieces.push((slice(i, i + 1), Some(on)));
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+            pieces.push((slice(i, i + 1), None));
+            i += 1;
+        }
+        start = run_end;
+    }
+    pieces
+}
+
+/// Elements whose text gets no readings, with their descendants.
+fn skips(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"head"
+            | b"ruby"
+            | b"rt"
+            | b"rp"
+            | b"code"
+            | b"kbd"
+            | b"samp"
+            | b"script"
+            | b"style"
+            | b"svg"
+            | b"math"
+    )
+}
+
+struct Element {
+    language: Option<Language>,
+    skipped: bool,
+}
+
+/// Wraps Han characters in `<ruby>` with their readings, pinyin for Chinese and kana for
+/// Japanese, by the language of the element or the book. Text already in ruby is left as is.
+pub struct RubyReadings {
+    book_lang: Option<String>,
+    stack: Vec<Element>,
+}
+
+impl RubyReadings {
+    pub fn new(book_lang: Option<String>) -> Self {
+        Self {
+            book_lang,
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl ContentTransform for RubyReadings {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) => {
+                let parent = self.stack.last();
+                let language = match (element_lang(e), parent) {
+                    (Some(lang), _) => language_of(&lang),
+                    (None, Some(parent)) => parent.language,
+                    (None, None) => self.book_lang.as_deref().and_then(language_of),
+                };
+                let skipped = parent.is_some_and(|p| p.skipped) || skips(e.local_name().as_ref());
+                self.stack.push(Element { language, skipped });
+            }
+            Event::End(_) => {
+                self.stack.pop();
+            }
+            Event::Text(ref e) => {
+                let language = match self.stack.last() {
+                    Some(Element {
+                        language: Some(language),
+                        skipped: false,
+                    }) => *language,
+                    _ => {
+                        out.push(event);
+                        return;
+                    }
+                };
+                let Ok(text) = e.unescape() else {
+                    out.push(event);
+                    return;
+                };
+                let pieces = annotate(&text, language);
+                if pieces.iter().all(|(_, reading)| reading.is_none()) {
+                    out.push(event);
+                    return;
+                }
+
+                // consecutive readings share a `<ruby>`
+                let mut in_ruby = false;
+                for (base, reading) in pieces {
+                    match reading {
+                        Some(reading) => {
+                            if !in_ruby {
+                                out.push(Event::Start(BytesStart::new("ruby")));
+                                in_ruby = true;
+                            }
+                            let rt = BytesStart::new("rt").with_attributes([(GENERATED_ATTR, "")]);
+                            out.push(Event::Text(BytesText::new(base).into_owned()));
+                            out.push(Event::Start(rt));
+                            out.push(Event::Text(BytesText::new(reading).into_owned()));
+                            out.push(Event::End(BytesEnd::new("rt")));
+                        }
+                        None => {
+                            if in_ruby {
+                                out.push(Event::End(BytesEnd::new("ruby")));
+                                in_ruby = false;
+                            }
+                            out.push(Event::Text(BytesText::new(base).into_owned()));
+                        }
+                    }
+                }
+                if in_ruby {
+                    out.push(Event::End(BytesEnd::new("ruby")));
+                }
+                return;
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_annotate() {
+        assert_eq!(
+            vec![
+                ("我", Some("wǒ")),
+                ("去", Some("qù")),
+                ("银", Some("yín")),
+                ("行", Some("háng")),
+                ("。", None)
+            ],
+            annotate("我去银行。", Language::Chinese)
+        );
+        assert_eq!(
+            vec![("重", Some("chóng")), ("庆", Some("qìng"))],
+            annotate("重庆", Language::Chinese)
+        );
+        assert_eq!(
+            vec![
+                ("学校", Some("がっこう")),
+                ("で", None),
+                ("食", None),
+                ("べる", None)
+            ],
+            annotate("学校で食べる", Language::Japanese)
+        );
+        assert_eq!(
+            vec![("山", Some("さん")), ("川", Some("せん"))],
+            annotate("山川", Language::Japanese)
+        );
+    }
+
+    #[test]
+    fn test_ruby_readings() {
+        let mut pipeline = Pipeline::with(RubyReadings::new(Some(String::from("ja"))));
+        let input = "<html><head><title>本</title></head><body>\
+                     <p>今日は<ruby>漢字<rt>かんじ</rt></ruby></p><p lang=\"en\">本</p></body></html>";
+        let expected = "<html><head><title>本</title></head><body>\
+                        <p><ruby>今日<rt data-og-ruby=\"\">きょう</rt></ruby>は\
+                        <ruby>漢字<rt>かんじ</rt></ruby></p><p lang=\"en\">本</p></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,314 @@
+use std::{cell::RefCell, collections::BTreeSet, fmt::Write, rc::Rc};
+
+use cssparser::Token;
+use quick_xml::events::{BytesStart, Event};
+use url::Url;
+
+use super::{ContentTransform, CssContext, CssPosition, quoted_url};
+use crate::prefs::{self, RemotePolicy, Settings};
+
+/// First segment of the paths of remote resources served through the proxy.
+const PROXY_SEGMENT: &str = ".og-remote";
+
+/// Path under `epub:` that serves `url` through the proxy.
+pub fn proxy_path(url: &Url) -> String {
+    let mut path = format!("/{PROXY_SEGMENT}/");
+    for b in url.as_str().bytes() {
+        let _ = write!(path, "{b:02x}");
+    }
+    path
+}
+
+/// The remote URL served at `epub_url`, if it is a proxy path.
+pub fn remote_url(epub_url: &Url) -> Option<Url> {
+    let mut segments = epub_url.path_segments()?;
+    if segments.next() != Some(PROXY_SEGMENT) {
+        return None;
+    }
+    let hex = segments.next()?.as_bytes();
+    let bytes = hex
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Url::parse(std::str::from_utf8(&bytes).ok()?).ok()
+}
+
+/// Hosts whose resources are fetched through the proxy, by the settings of the book.
+pub fn allowed_hosts(settings: &Settings) -> Vec<String> {
+    match settings.get(prefs::REMOTE_RESOURCES) {
+        Some(RemotePolicy::Proxy) => settings.get(prefs::REMOTE_ALLOWLIST).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `url` is on one of the `allowed` hosts.
+pub fn is_allowed(allowed: &[String], url: &Url) -> bool {
+    url.host_str()
+        .is_some_and(|host| allowed.iter().any(|a| a.eq_ignore_ascii_case(host)))
+}
+
+/// Attributes that load a resource, rather than link to a page.
+fn is_resource_attr(element: &[u8], attr: &[u8]) -> bool {
+    match attr {
+        b"src" | b"srcset" | b"poster" | b"data" => true,
+        b"href" => !matches!(element, b"a" | b"area"),
+        _ => false,
+    }
+}
+
+/// The URLs of the image candidates of a `srcset`, with their descriptors, e.g., ` 2x`.
+fn srcset_candidates(srcset: &str) -> Vec<(&str, &str)> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            return candidates;
+        }
+        let url_end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let (url, after) = rest.split_at(url_end);
+        // a URL ending with a comma has no descriptors
+        if let Some(url) = url.strip_suffix(',') {
+            candidates.push((url.trim_end_matches(','), ""));
+            rest = after;
+            continue;
+        }
+        let descriptors_end = after.find(',').unwrap_or(after.len());
+        let (descriptors, after) = after.split_at(descriptors_end);
+        candidates.push((url, descriptors.trim_end()));
+        rest = after;
+    }
+}
+
+/// Blocks resources on the web, or points them to the proxy if their host is allowed.
+pub struct RemoteResources {
+    /// URL of the resource, to resolve relative URLs against. Those of a remote stylesheet
+    /// are remote too.
+    base: Option<Url>,
+    /// Hosts fetched through the proxy.
+    allowed: Vec<String>,
+    /// Resources blocked so far.
+    blocked: Rc<RefCell<BTreeSet<Url>>>,
+}
+
+impl RemoteResources {
+    pub fn new(
+        base: Option<Url>,
+        allowed: Vec<String>,
+        blocked: Rc<RefCell<BTreeSet<Url>>>,
+    ) -> Self {
+        Self {
+            base,
+            allowed,
+            blocked,
+        }
+    }
+
+    /// Returns the replacement of `value` if it is a remote URL: a proxy path, or `None`
+    /// inside if blocked.
+    fn rewrite(&self, value: &str) -> Option<Option<String>> {
+        let url = match &self.base {
+            Some(base) => base.join(value.trim()),
+            None => Url::parse(value.trim()),
+        }
+        .ok()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        if is_allowed(&self.allowed, &url) {
+            Some(Some(proxy_path(&url)))
+        } else {
+            log::debug!("blocked remote resource {url}");
+            self.blocked.borrow_mut().insert(url);
+            Some(None)
+        }
+    }
+
+    /// Returns the replacement of `srcset` if it has remote candidates: the others with
+    /// those allowed through the proxy, or `None` inside if none is left.
+    fn rewrite_srcset(&self, srcset: &str) -> Option<Option<String>> {
+        let mut remote = false;
+        let mut kept = Vec::new();
+        for (url, descriptors) in srcset_candidates(srcset) {
+            match self.rewrite(url) {
+                None => kept.push(format!("{url}{descriptors}")),
+                Some(replacement) => {
+                    remote = true;
+                    if let Some(path) = replacement {
+                        kept.push(format!("{path}{descriptors}"));
+                    }
+                }
+            }
+        }
+        if !remote {
+            return None;
+        }
+        Some((!kept.is_empty()).then(|| kept.join(", ")))
+    }
+
+    fn css_url(&self, value: &str) -> Option<String> {
+        let replacement = self.rewrite(value)?.unwrap_or_default();
+        Some(format!("url(\"{replacement}\")"))
+    }
+
+    /// Copy of `e` with remote resources rewritten, or `None` if it has none.
+    fn rewrite_attrs(&self, e: &BytesStart<'_>) -> Option<BytesStart<'static>> {
+        let element = e.local_name();
+        let replacements: Vec<_> = e
+            .attributes()
+            .flatten()
+            .map(|attr| {
+                let name = attr.key.local_name();
+                if !is_resource_attr(element.as_ref(), name.as_ref()) {
+                    return None;
+                }
+                let value = String::from_utf8_lossy(&attr.value);
+                if name.as_ref() == b"srcset" {
+                    self.rewrite_srcset(&value)
+                } else {
+                    self.rewrite(&value)
+                }
+            })
+            .collect();
+        if replacements.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let mut rewritten = e.to_owned();
+        rewritten.clear_attributes();
+        for (attr, replacement) in e.attributes().flatten().zip(replacements) {
+            match replacement {
+                None => rewritten.push_attribute(attr),
+                Some(Some(value)) => {
+                    rewritten.push_attribute((attr.key.as_ref(), value.as_bytes()));
+                }
+                // no request at all rather than a broken one
+                Some(None) => {}
+            }
+        }
+        Some(rewritten.into_owned())
+    }
+}
+
+impl ContentTransform for RemoteResources {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        match token {
+            Token::UnquotedUrl(value) => self.css_url(value),
+            // `@import "…"` takes a URL without `url()`
+            Token::QuotedString(value)
+                if ctx.position == CssPosition::Prelude
+                    && ctx.at_rule == "import"
+                    && !ctx.nested =>
+            {
+                self.css_url(value)
+            }
+            _ => None,
+        }
+    }
+
+    fn css_function(
+        &mut self,
+        name: &str,
+        arguments: &str,
+        _ctx: &CssContext<'_>,
+    ) -> Option<String> {
+        if name != "url" {
+            return None;
+        }
+        self.css_url(quoted_url(arguments)?)
+    }
+
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        out.push(match event {
+            Event::Start(ref e) => self.rewrite_attrs(e).map_or(event, Event::Start),
+            Event::Empty(ref e) => self.rewrite_attrs(e).map_or(event, Event::Empty),
+            event => event,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{CssBlock, Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_proxy_path() {
+        let url = Url::parse("https://example.com/a b.png?x=1").unwrap();
+        let epub_url = Url::parse("epub:/")
+            .unwrap()
+            .join(&proxy_path(&url))
+            .unwrap();
+        assert_eq!(Some(url), remote_url(&epub_url));
+        assert_eq!(None, remote_url(&Url::parse("epub:/OEBPS/a.png").unwrap()));
+    }
+
+    #[test]
+    fn test_remote_resources() {
+        let blocked = Rc::new(RefCell::new(BTreeSet::new()));
+        let base = Url::parse("epub:/OEBPS/style.css").ok();
+        let pass = RemoteResources::new(base, vec![String::from("fonts.example")], blocked.clone());
+        let mut pipeline = Pipeline::with(pass);
+
+        let input = "@import \"https://ads.example/i.css\";\n\
+                     @font-face { src: url(https://fonts.example/a.woff) format(\"woff\"); }\n\
+                     p { background: url('http://ads.example/b.png'); }\n\
+                     h1 { background: url(../images/h.png); }";
+        let fonts = proxy_path(&Url::parse("https://fonts.example/a.woff").unwrap());
+        let expected = format!(
+            "@import url(\"\");\n\
+             @font-face {{ src: url(\"{fonts}\") format(\"woff\"); }}\n\
+             p {{ background: url(\"\"); }}\n\
+             h1 {{ background: url(../images/h.png); }}"
+        );
+        assert_eq!(
+            expected,
+            pipeline.alter_css_str(input, CssBlock::Rules).unwrap()
+        );
+
+        let input = r#"<p><img src="https://ads.example/c.png" alt="c"/><a href="https://ads.example/">ad</a></p>"#;
+        let expected = r#"<p><img alt="c"/><a href="https://ads.example/">ad</a></p>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+
+        let input = r#"<img src="e.png" srcset="e.png, https://ads.example/d.png 2x, https://fonts.example/e.png 3x"/><img srcset="https://ads.example/d.png"/>"#;
+        let image = proxy_path(&Url::parse("https://fonts.example/e.png").unwrap());
+        let expected = format!(r#"<img src="e.png" srcset="e.png, {image} 3x"/><img/>"#);
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+
+        let blocked: Vec<_> = blocked.borrow().iter().map(Url::to_string).collect();
+        assert_eq!(
+            vec![
+                "http://ads.example/b.png",
+                "https://ads.example/c.png",
+                "https://ads.example/d.png",
+                "https://ads.example/i.css"
+            ],
+            blocked
+        );
+    }
+
+    #[test]
+    fn test_remote_stylesheet() {
+        let blocked = Rc::new(RefCell::new(BTreeSet::new()));
+        let base = Url::parse("https://fonts.example/css/a.css").ok();
+        let pass = RemoteResources::new(base, vec![String::from("fonts.example")], blocked.clone());
+        let mut pipeline = Pipeline::with(pass);
+
+        let input = "@font-face { src: url(../fonts/a.woff2); }\n\
+                     p { background: url(\"https://ads.example/b.png\"); }";
+        let fonts = proxy_path(&Url::parse("https://fonts.example/fonts/a.woff2").unwrap());
+        let expected = format!(
+            "@font-face {{ src: url(\"{fonts}\"); }}\n\
+             p {{ background: url(\"\"); }}"
+        );
+        assert_eq!(
+            expected,
+            pipeline.alter_css_str(input, CssBlock::Rules).unwrap()
+        );
+        assert_eq!(1, blocked.borrow().len());
+    }
+}
@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
+
+use quick_xml::{
+    escape::partial_escape,
+    events::{BytesText, Event},
+};
+use regex::Regex;
+
+use super::{ContentTransform, Pipeline, alter_xhtml};
+use crate::errors::AnyErr;
+use crate::prefs::{self, ReplaceRule, Settings};
+
+enum Matcher {
+    Plain(String),
+    Regex(Regex),
+}
+
+struct Rule {
+    matcher: Matcher,
+    replace: String,
+}
+
+impl Rule {
+    /// Returns `None` if `find` is empty or an invalid regular expression.
+    fn compile(rule: &ReplaceRule) -> Option<Self> {
+        if rule.find.is_empty() {
+            return None;
+        }
+        let matcher = if rule.regex {
+            match Regex::new(&rule.find) {
+                Ok(regex) => Matcher::Regex(regex),
+                Err(e) => {
+                    log::warn!("invalid replace rule {:?}: {e}", rule.find);
+                    return None;
+                }
+            }
+        } else {
+            Matcher::Plain(rule.find.clone())
+        };
+        Some(Self {
+            matcher,
+            replace: rule.replace.clone(),
+        })
+    }
+
+    /// Returns `text` with the matches replaced, and how many there were.
+    fn apply<'t>(&self, text: Cow<'t, str>) -> (Cow<'t, str>, usize) {
+        let count = match &self.matcher {
+            Matcher::Plain(find) => text.matches(find.as_str()).count(),
+            Matcher::Regex(regex) => regex.find_iter(&text).count(),
+        };
+        if count == 0 {
+            return (text, 0);
+        }
+        let replaced = match &self.matcher {
+            Matcher::Plain(find) => text.replace(find.as_str(), &self.replace),
+            Matcher::Regex(regex) => regex.replace_all(&text, &self.replace).into_owned(),
+        };
+        (Cow::Owned(replaced), count)
+    }
+}
+
+/// Elements whose text is not replaced, with their descendants.
+fn skips(name: &[u8]) -> bool {
+    matches!(name, b"script" | b"style")
+}
+
+/// Applies the replace rules to text, in order.
+pub struct ReplaceText {
+    /// `None` for rules that can't match.
+    rules: Vec<Option<Rule>>,
+    /// Matches so far, by rule.
+    counts: Rc<RefCell<Vec<usize>>>,
+    /// Depth inside an element being skipped, or 0.
+    skipping: usize,
+}
+
+impl ReplaceText {
+    pub fn new(rules: &[ReplaceRule], counts: Rc<RefCell<Vec<usize>>>) -> Self {
+        counts.borrow_mut().resize(rules.len(), 0);
+        Self {
+            rules: rules.iter().map(Rule::compile).collect(),
+            counts,
+            skipping: 0,
+        }
+    }
+}
+
+impl ContentTransform for ReplaceText {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        match event {
+            Event::Start(ref e) if self.skipping > 0 || skips(e.local_name().as_ref()) => {
+                self.skipping += 1;
+            }
+            Event::End(_) if self.skipping > 0 => {
+                self.skipping -= 1;
+            }
+            Event::Text(ref e) if self.skipping == 0 => {
+                let Ok(text) = e.unescape() else {
+                    out.push(event);
+                    return;
+                };
+                let mut text = Cow::Borrowed(text.as_ref());
+                let mut changed = false;
+                let mut counts = self.counts.borrow_mut();
+                for (rule, count) in self.rules.iter().zip(counts.iter_mut()) {
+                    if let Some(rule) = rule {
+                        let (replaced, n) = rule.apply(text);
+                        text = replaced;
+                        *count += n;
+                        changed |= n > 0;
+                    }
+                }
+                if changed {
+                    let text = partial_escape(&*text).into_owned();
+                    out.push(Event::Text(BytesText::from_escaped(text)));
+                    return;
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+}
+
+/// How many times a replace rule matches in a document.
+#[derive(serde::Serialize)]
+pub struct RuleMatches {
+    #[serde(flatten)]
+    pub rule: ReplaceRule,
+    /// `None` if the rule can't match, e.g., an invalid regular expression.
+    pub count: Option<usize>,
+}
+
+/// Counts the matches of the replace rules in settings in an XHTML document, each rule
+/// applied to the text as replaced by the rules before.
+pub fn preview_rules<R: Read>(reader: R, settings: &Settings) -> Result<Vec<RuleMatches>, AnyErr> {
+    let rules: Vec<ReplaceRule> = settings.get_joined(prefs::REPLACE_RULES);
+    let counts = Rc::default();
+    let pass = ReplaceText::new(&rules, Rc::clone(&counts));
+    let valid: Vec<bool> = pass.rules.iter().map(Option::is_some).collect();
+    let mut pipeline = Pipeline {
+        passes: vec![Box::new(pass)],
+        user_css: String::new(),
+    };
+    alter_xhtml(reader, &mut pipeline)?;
+
+    let counts = counts.borrow();
+    Ok(rules
+        .into_iter()
+        .zip(valid)
+        .zip(counts.iter())
+        .map(|((rule, valid), count)| RuleMatches {
+            rule,
+            count: valid.then_some(*count),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pass, settings_with};
+
+    #[test]
+    fn test_replace_text() {
+        let rules = serde_json::json!([
+            {"find": "tbe", "replace": "the"},
+            {"find": r"\s*\.\s\.\s\.", "replace": "…", "regex": true},
+            {"find": "(", "replace": "", "regex": true},
+        ]);
+        let settings = settings_with(prefs::REPLACE_RULES, rules);
+        let input = "<html><head><style>.tbe {}</style></head>\
+                     <body><p class=\"tbe\">tbe end . . . &amp; tbe</p></body></html>";
+        let expected = "<html><head><style>.tbe {}</style></head>\
+                        <body><p class=\"tbe\">the end… &amp; the</p></body></html>";
+        let mut pipeline =
+            Pipeline::of_passes([Pass::ReplaceRules], &settings, &Default::default());
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+
+        let matches = preview_rules(input.as_bytes(), &settings).unwrap();
+        let counts: Vec<_> = matches.iter().map(|m| m.count).collect();
+        assert_eq!(vec![Some(2), Some(1), None], counts);
+    }
+}
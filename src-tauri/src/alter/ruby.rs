@@ -0,0 +1,73 @@
+use quick_xml::events::{BytesText, Event};
+
+use super::ContentTransform;
+use crate::prefs::RubyMode;
+
+/// Hides ruby annotations, or puts them in parentheses after the base text.
+pub struct Ruby {
+    mode: RubyMode,
+    /// Depth inside an element being dropped, or 0.
+    skipping: usize,
+}
+
+impl Ruby {
+    pub fn new(mode: RubyMode) -> Self {
+        Self { mode, skipping: 0 }
+    }
+
+    fn drops(&self, name: &[u8]) -> bool {
+        // `<rp>` holds fallback parentheses, which would be doubled inline
+        name == b"rp" || (name == b"rt" && self.mode == RubyMode::Hide)
+    }
+
+    fn unwraps(&self, name: &[u8]) -> bool {
+        self.mode == RubyMode::Inline && matches!(name, b"ruby" | b"rb" | b"rtc")
+    }
+}
+
+impl ContentTransform for Ruby {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        if self.skipping > 0 {
+            match event {
+                Event::Start(_) => self.skipping += 1,
+                Event::End(_) => self.skipping -= 1,
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(ref e) if self.drops(e.local_name().as_ref()) => self.skipping = 1,
+            Event::Empty(ref e) if self.drops(e.local_name().as_ref()) => {}
+            Event::Start(ref e) | Event::Empty(ref e) if self.unwraps(e.local_name().as_ref()) => {}
+            Event::End(ref e) if self.unwraps(e.local_name().as_ref()) => {}
+            Event::Start(ref e) if e.local_name().as_ref() == b"rt" => {
+                out.push(Event::Text(BytesText::new("(")));
+            }
+            Event::End(ref e) if e.local_name().as_ref() == b"rt" => {
+                out.push(Event::Text(BytesText::new(")")));
+            }
+            event => out.push(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alter::{Context, Pipeline, alter_xhtml, settings_with};
+    use crate::prefs;
+
+    fn alter_ruby(mode: &str, input: &str) -> String {
+        let settings = settings_with(prefs::RUBY, serde_json::json!(mode));
+        let mut pipeline = Pipeline::new(&settings, &Context::default());
+        String::from_utf8(alter_xhtml(input.as_bytes(), &mut pipeline).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_ruby() {
+        let input = "<p><ruby>漢<rp>(</rp><rt>かん</rt><rp>)</rp>字<rt>じ</rt></ruby></p>";
+        assert_eq!(input, alter_ruby("show", input));
+        assert_eq!("<p><ruby>漢字</ruby></p>", alter_ruby("hide", input));
+        assert_eq!("<p>漢(かん)字(じ)</p>", alter_ruby("inline", input));
+    }
+}
@@ -0,0 +1,188 @@
+use quick_xml::{
+    escape::unescape,
+    events::{BytesStart, Event, attributes::Attribute},
+};
+use url::Url;
+
+use super::ContentTransform;
+
+/// The value of `attr` with references resolved, or `None` if it has unknown ones.
+fn unescaped(attr: &Attribute<'_>) -> Option<String> {
+    unescape(&String::from_utf8_lossy(&attr.value))
+        .ok()
+        .map(|value| value.into_owned())
+}
+
+/// Whether an attribute is an event handler, e.g., `onclick`.
+fn is_event_handler(key: &[u8]) -> bool {
+    key.len() > 2 && key[..2].eq_ignore_ascii_case(b"on")
+}
+
+/// Whether `value`, unescaped, is a `javascript:` URL as browsers read it: without tabs and
+/// newlines, and leading spaces and control characters. Values that can't be unescaped are
+/// taken as scripts.
+fn is_javascript_url(attr: &Attribute<'_>) -> bool {
+    let Some(value) = unescaped(attr) else {
+        return true;
+    };
+    let value: String = value
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .take(11)
+        .collect();
+    value.eq_ignore_ascii_case("javascript:")
+}
+
+/// Whether an attribute runs code: an event handler, a `javascript:` URL, or a `srcdoc`,
+/// whose frame would share the origin of the reader.
+fn is_script(attr: &Attribute<'_>) -> bool {
+    let key = attr.key.local_name();
+    is_event_handler(key.as_ref()) || key.as_ref() == b"srcdoc" || is_javascript_url(attr)
+}
+
+/// Whether the value of attribute `key` of `e` points inside the book.
+fn points_inside(e: &BytesStart<'_>, key: &str) -> bool {
+    let Ok(Some(attr)) = e.try_get_attribute(key) else {
+        return false;
+    };
+    let Some(value) = unescaped(&attr) else {
+        return false;
+    };
+    Url::parse("epub:/")
+        .and_then(|base| base.join(value.trim()))
+        .is_ok_and(|url| url.scheme() == "epub" && url.host().is_none())
+}
+
+/// Whether `e` is to be dropped with its content, since it runs code or embeds content
+/// that could: `<script>`, frames and plugins showing anything but the book, and SVG
+/// animations setting links or event handlers.
+pub(super) fn is_dropped(e: &BytesStart<'_>) -> bool {
+    match e.local_name().as_ref() {
+        b"script" => true,
+        b"iframe" | b"embed" => !points_inside(e, "src"),
+        b"object" => !points_inside(e, "data"),
+        b"animate" | b"set" => match e.try_get_attribute("attributeName") {
+            Ok(Some(attr)) => {
+                let name = unescaped(&attr).unwrap_or_default();
+                let name = name.trim();
+                let local = name.rsplit(':').next().unwrap_or(name);
+                local.eq_ignore_ascii_case("href") || is_event_handler(local.as_bytes())
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Copy of `e` without event handlers, `javascript:` URLs and `srcdoc`, or `None` if it has
+/// none of them.
+pub(super) fn without_scripts(e: &BytesStart<'_>) -> Option<BytesStart<'static>> {
+    if !e.attributes().flatten().any(|attr| is_script(&attr)) {
+        return None;
+    }
+
+    let mut clean = e.to_owned();
+    clean.clear_attributes();
+    for attr in e.attributes().flatten() {
+        if !is_script(&attr) {
+            clean.push_attribute(attr);
+        }
+    }
+    Some(clean.into_owned())
+}
+
+/// Removes `<script>`, event handler attributes and `javascript:` URLs, and other ways for
+/// content to run code, see `is_dropped`.
+#[derive(Default)]
+pub struct StripScripts {
+    /// Depth inside a dropped element, or 0.
+    skipping: usize,
+}
+
+impl ContentTransform for StripScripts {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        if self.skipping > 0 {
+            match event {
+                Event::Start(_) => self.skipping += 1,
+                Event::End(_) => self.skipping -= 1,
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(ref e) if is_dropped(e) => self.skipping = 1,
+            Event::Empty(ref e) if is_dropped(e) => {}
+            Event::Start(ref e) => {
+                out.push(without_scripts(e).map_or(event, Event::Start));
+            }
+            Event::Empty(ref e) => {
+                out.push(without_scripts(e).map_or(event, Event::Empty));
+            }
+            event => out.push(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alter::{Context, Pass, Pipeline, alter_xhtml, settings_with};
+    use crate::prefs;
+
+    fn alter_scripts(policy: &str, scripted: bool, input: &str) -> String {
+        let settings = settings_with(prefs::SCRIPTS, serde_json::json!(policy));
+        let context = Context {
+            scripted,
+            ..Default::default()
+        };
+        let mut pipeline = Pipeline::of_passes([Pass::Scripts], &settings, &context);
+        String::from_utf8(alter_xhtml(input.as_bytes(), &mut pipeline).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_strip_scripts() {
+        let input = r#"<body onload="init()"><script src="a.js"/><script>alert(1)</script><p><a href=" JavaScript:go()" class="x">go</a><a href="b.xhtml" onclick="go()">b</a></p></body>"#;
+        let expected = r#"<body><p><a class="x">go</a><a href="b.xhtml">b</a></p></body>"#;
+        assert_eq!(expected, alter_scripts("strip", true, input));
+        assert_eq!(expected, alter_scripts("sandbox", false, input));
+        assert_eq!(input, alter_scripts("sandbox", true, input));
+    }
+
+    #[test]
+    fn test_strip_escaped_urls() {
+        let input = r#"<p><a href="&#106;avascript:go()">a</a><a href="java&#9;script:go()">b</a><a href=" &#x0A;java&#x0D;script:go()">c</a></p>"#;
+        let expected = r#"<p><a>a</a><a>b</a><a>c</a></p>"#;
+        assert_eq!(expected, alter_scripts("strip", false, input));
+    }
+
+    #[test]
+    fn test_strip_frames() {
+        let input = r#"<div><iframe srcdoc="&lt;script&gt;go()&lt;/script&gt;"/><iframe src="https://example.com/">x</iframe><object data="data:text/html,x"><p>x</p></object><embed src="//example.com/a.swf"/><iframe src="chart.xhtml" srcdoc="x"/><object data="../images/a.svg"/></div>"#;
+        let expected = r#"<div><iframe src="chart.xhtml"/><object data="../images/a.svg"/></div>"#;
+        assert_eq!(expected, alter_scripts("strip", false, input));
+    }
+
+    #[test]
+    fn test_strip_svg_animations() {
+        let input = r#"<svg><a><set attributeName="href" to="javascript:go()"/><animate attributeName="xlink:href" values="x"/><set attributeName="onclick" to="go()"/><animate attributeName="opacity" to="0"/>a</a></svg>"#;
+        let expected = r#"<svg><a><animate attributeName="opacity" to="0"/>a</a></svg>"#;
+        assert_eq!(expected, alter_scripts("strip", false, input));
+    }
+
+    #[test]
+    fn test_strip_stitched() {
+        let settings = settings_with(prefs::SCRIPTS, serde_json::json!("sandbox"));
+        let mut context = Context {
+            scripted: true,
+            ..Default::default()
+        };
+        context.stitch(2);
+        let passes = [Pass::NamespaceIds, Pass::Scripts];
+        let mut pipeline = Pipeline::of_passes(passes, &settings, &context);
+        let input = r#"<body><p id="a" onclick="go()">a</p><script>go()</script></body>"#;
+        let expected = r#"<body><p id="og-2-a">a</p></body>"#;
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+
+use quick_xml::{Reader, Writer, events::Event};
+use url::Url;
+
+use super::anchors::is_block;
+use crate::errors::AnyErr;
+
+/// Query of the URLs of parts of split documents, holding the index of the part.
+const PART_QUERY_PREFIX: &str = "og-part=";
+
+/// Documents larger than this, in bytes, are split.
+const SPLIT_THRESHOLD: u64 = 1024 * 1024;
+
+/// Size of the parts of split documents in bytes, about. Parts end at the first block
+/// element past it.
+const PART_SIZE: usize = 256 * 1024;
+
+/// The URL of part `index` of the document at `url`, keeping the fragment, e.g.,
+/// `epub:/OEBPS/book.xhtml?og-part=2#ch5`.
+pub fn part_url(url: &Url, index: usize) -> Url {
+    let mut url = url.clone();
+    url.set_query(Some(&format!("{PART_QUERY_PREFIX}{index}")));
+    url
+}
+
+/// Reads the index of the part in the query of a URL made by `part_url`.
+pub fn part_index(query: &str) -> Option<usize> {
+    query.strip_prefix(PART_QUERY_PREFIX)?.parse().ok()
+}
+
+/// The start tag `tag` without its `id`, which only the part where the element starts
+/// keeps, or `None` if it is not a start tag.
+fn without_id(tag: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Reader::from_reader(tag);
+    let Ok(Event::Start(e)) = reader.read_event() else {
+        return None;
+    };
+    let mut start = e.to_owned();
+    start.clear_attributes();
+    for attr in e.attributes().flatten() {
+        if attr.key.local_name().as_ref() != b"id" {
+            start.push_attribute(attr);
+        }
+    }
+    let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+    writer.write_event(Event::Start(start)).ok()?;
+    Some(writer.into_inner().into_inner())
+}
+
+/// Reads a URL made by `part_url`. Returns the URL of the whole document, keeping the
+/// fragment, and the index of the part.
+pub fn part_of(url: &Url) -> Option<(Url, usize)> {
+    let index = part_index(url.query()?)?;
+    let mut doc_url = url.clone();
+    doc_url.set_query(None);
+    Some((doc_url, index))
+}
+
+/// An element open where a part starts, to open again in the part.
+#[derive(Clone, Debug)]
+struct OpenElement {
+    /// Where the start tag is in the document.
+    tag: Range<usize>,
+    name: String,
+}
+
+#[derive(Debug)]
+struct Part {
+    /// Where the part starts in the document.
+    start: usize,
+    /// Elements in `<body>` open where the part starts, outermost first.
+    open: Vec<OpenElement>,
+    /// Path from `<body>` of the first element of the part, as anchored by `BlockAnchors`.
+    /// Empty for the first part.
+    path: Vec<usize>,
+}
+
+/// Where a very large content document splits into parts, served as documents of their
+/// own under `part_url`s. Parts start at block elements, and keep the `<head>` of the
+/// document and the elements open there.
+///
+/// Block elements get the anchors they have in the whole document, so that a
+/// `TextPosition` in a part is one in the whole document too.
+#[derive(Debug)]
+pub struct DocumentSplit {
+    /// Where the content of `<body>` is in the document.
+    body: Range<usize>,
+    parts: Vec<Part>,
+    /// Index of the part of each element with an `id`.
+    ids: HashMap<String, usize>,
+}
+
+impl DocumentSplit {
+    /// Whether a document of `size` bytes is large enough to be split.
+    pub fn is_large(size: u64) -> bool {
+        size > SPLIT_THRESHOLD
+    }
+
+    /// Plans how to split `doc`, or returns `None` if it is served whole.
+    pub fn plan(doc: &[u8]) -> Result<Option<Self>, AnyErr> {
+        if !Self::is_large(doc.len() as u64) {
+            return Ok(None);
+        }
+        let split = Self::plan_parts(doc, PART_SIZE)?;
+        Ok((split.parts.len() > 1).then_some(split))
+    }
+
+    fn plan_parts(doc: &[u8], part_size: usize) -> Result<Self, AnyErr> {
+        let mut reader = Reader::from_reader(doc);
+        reader.config_mut().trim_text(false);
+
+        let mut body_start = None;
+        let mut parts = Vec::new();
+        let mut ids = HashMap::new();
+        // elements open in `<body>`, their indexes, and the number of child elements seen
+        // so far in `<body>` and each of them, as in `BlockAnchors`
+        let mut open: Vec<OpenElement> = Vec::new();
+        let mut path: Vec<usize> = Vec::new();
+        let mut counts: Vec<usize> = Vec::new();
+        loop {
+            let start = reader.buffer_position() as usize;
+            let event = reader.read_event().map_err(|_| AnyErr::EpubContent)?;
+            let end = reader.buffer_position() as usize;
+            match event {
+                Event::Eof => return Err(AnyErr::EpubContent),
+                Event::Start(ref e)
+                    if body_start.is_none() && e.local_name().as_ref() == b"body" =>
+                {
+                    body_start = Some(end);
+                    counts.push(0);
+                    parts.push(Part {
+                        start: end,
+                        open: Vec::new(),
+                        path: Vec::new(),
+                    });
+                }
+                _ if body_start.is_none() => {}
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let count = counts.last_mut().ok_or(AnyErr::EpubContent)?;
+                    let index = *count;
+                    *count += 1;
+
+                    let part_start = parts.last().map_or(start, |part: &Part| part.start);
+                    if is_block(e.local_name().as_ref()) && start - part_start >= part_size {
+                        let mut part_path = path.clone();
+                        part_path.push(index);
+                        parts.push(Part {
+                            start,
+                            open: open.clone(),
+                            path: part_path,
+                        });
+                    }
+                    if let Ok(Some(id)) = e.try_get_attribute("id") {
+                        let id = String::from_utf8_lossy(&id.value).into_owned();
+                        ids.entry(id).or_insert(parts.len() - 1);
+                    }
+
+                    if let Event::Start(_) = event {
+                        open.push(OpenElement {
+                            tag: start..end,
+                            name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+                        });
+                        path.push(index);
+                        counts.push(0);
+                    }
+                }
+                Event::End(_) if open.is_empty() => {
+                    let body_start = body_start.unwrap_or_default();
+                    return Ok(Self {
+                        body: body_start..start,
+                        parts,
+                        ids,
+                    });
+                }
+                Event::End(_) => {
+                    open.pop();
+                    path.pop();
+                    counts.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Number of parts.
+    pub fn count(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Path from `<body>` of the first element of part `index`, for `BlockAnchors` to
+    /// resume from.
+    pub fn path(&self, index: usize) -> &[usize] {
+        self.parts
+            .get(index)
+            .map_or(&[], |part| part.path.as_slice())
+    }
+
+    /// Index of the part with the element of `id`.
+    pub fn part_of_id(&self, id: &str) -> Option<usize> {
+        self.ids.get(id).copied()
+    }
+
+    /// Index of the part with the block element anchored `anchor`, see `TextPosition`.
+    pub fn part_of_anchor(&self, anchor: &str) -> usize {
+        let path: Vec<usize> = anchor
+            .split('.')
+            .map(|index| index.parse().unwrap_or_default())
+            .collect();
+        // parts are in document order, which is the order of paths
+        self.parts
+            .iter()
+            .rposition(|part| part.path <= path)
+            .unwrap_or_default()
+    }
+
+    /// Writes part `index` of `doc` as a document of its own: the document up to the
+    /// content of `<body>`, the elements open where the part starts, without their IDs,
+    /// the part, the end tags of the elements open where it ends, and the rest of the
+    /// document from `</body>`.
+    pub fn part(&self, doc: &[u8], index: usize) -> Option<Vec<u8>> {
+        let part = self.parts.get(index)?;
+        let next = self.parts.get(index + 1);
+        let end = next.map_or(self.body.end, |next| next.start);
+
+        let mut output = Vec::with_capacity(self.body.start + end - part.start);
+        output.extend_from_slice(doc.get(..self.body.start)?);
+        for element in &part.open {
+            output.extend(without_id(doc.get(element.tag.clone())?)?);
+        }
+        output.extend_from_slice(doc.get(part.start..end)?);
+        for element in next.iter().flat_map(|next| next.open.iter().rev()) {
+            output.extend_from_slice(format!("</{}>", element.name).as_bytes());
+        }
+        output.extend_from_slice(doc.get(self.body.end..)?);
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml, anchors::BlockAnchors};
+
+    #[test]
+    fn test_part_url() {
+        let url = Url::parse("epub:/OEBPS/book.xhtml#ch5").unwrap();
+        let part = part_url(&url, 2);
+        assert_eq!("epub:/OEBPS/book.xhtml?og-part=2#ch5", part.as_str());
+        assert_eq!(Some((url, 2)), part_of(&part));
+        assert_eq!(
+            None,
+            part_of(&Url::parse("epub:/a.png?og-original").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_document_split() {
+        let doc = "<html><head><title>t</title></head><body>\
+                   <h1 id=\"top\">T</h1>\
+                   <div class=\"main\" id=\"m\"><p>aaaaaaaaaa</p><p id=\"b\">bbbbbbbbbb</p><p>cc</p></div>\
+                   </body></html>";
+        let split = DocumentSplit::plan_parts(doc.as_bytes(), 47).unwrap();
+        assert_eq!(2, split.count());
+        assert_eq!([1, 1], split.path(1));
+        assert_eq!(Some(0), split.part_of_id("top"));
+        assert_eq!(Some(0), split.part_of_id("m"));
+        assert_eq!(Some(1), split.part_of_id("b"));
+        assert_eq!(0, split.part_of_anchor("1"));
+        assert_eq!(0, split.part_of_anchor("1.0"));
+        assert_eq!(1, split.part_of_anchor("1.2"));
+
+        let part = split.part(doc.as_bytes(), 0).unwrap();
+        let expected = "<html><head><title>t</title></head><body>\
+                        <h1 id=\"top\">T</h1><div class=\"main\" id=\"m\"><p>aaaaaaaaaa</p></div>\
+                        </body></html>";
+        assert_eq!(expected, String::from_utf8_lossy(&part));
+        let part = split.part(doc.as_bytes(), 1).unwrap();
+        let expected = "<html><head><title>t</title></head><body>\
+                        <div class=\"main\"><p id=\"b\">bbbbbbbbbb</p><p>cc</p></div>\
+                        </body></html>";
+        assert_eq!(expected, String::from_utf8_lossy(&part));
+
+        let mut pipeline = Pipeline::with(BlockAnchors::resuming(split.path(1).to_vec()));
+        let output = alter_xhtml(part.as_slice(), &mut pipeline).unwrap();
+        let expected = "<html><head><title>t</title></head><body>\
+                        <div class=\"main\" data-og-id=\"1\">\
+                        <p id=\"b\" data-og-id=\"1.1\">bbbbbbbbbb</p><p data-og-id=\"1.2\">cc</p></div>\
+                        </body></html>";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use quick_xml::events::Event;
+
+use super::{ContentTransform, font_custom_property, hyphen::element_lang};
+use crate::prefs::FontPrefer;
+
+/// Generic families, which are keywords rather than names to quote.
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+    "ui-serif",
+    "ui-sans-serif",
+    "ui-monospace",
+    "ui-rounded",
+    "emoji",
+    "math",
+    "fangsong",
+];
+
+/// The script of `subtags` if written, or else implied by the language, for those whose
+/// tags usually leave it out.
+fn script(subtags: &[&str]) -> Option<String> {
+    if let Some(script) = subtags[1..]
+        .iter()
+        .find(|subtag| subtag.len() == 4 && subtag.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        return Some(script.to_ascii_lowercase());
+    }
+    let primary = subtags[0].to_ascii_lowercase();
+    let region = subtags[1..]
+        .iter()
+        .find(|subtag| subtag.len() == 2)
+        .map(|region| region.to_ascii_uppercase());
+    let script = match (primary.as_str(), region.as_deref()) {
+        ("zh", Some("TW" | "HK" | "MO")) => "hant",
+        ("zh", _) => "hans",
+        ("ja", _) => "jpan",
+        ("ko", _) => "kore",
+        _ => return None,
+    };
+    Some(String::from(script))
+}
+
+/// Returns the stack set for the language tag `lang`: the one of its longest prefix of
+/// more than one subtag, e.g., `zh-Hant` for `zh-Hant-TW`, or else of its script, e.g.,
+/// `Hant` for `zh-TW`, or else of its language. Keys are matched ignoring case.
+fn stack_for<'s>(lang: &str, stacks: &'s BTreeMap<String, Vec<String>>) -> Option<&'s [String]> {
+    let find = |key: &str| {
+        stacks
+            .iter()
+            .find(|(k, stack)| k.eq_ignore_ascii_case(key) && !stack.is_empty())
+            .map(|(_, stack)| stack.as_slice())
+    };
+    let subtags: Vec<&str> = lang.split('-').collect();
+    (2..=subtags.len())
+        .rev()
+        .find_map(|n| find(&subtags[..n].join("-")))
+        .or_else(|| script(&subtags).and_then(|script| find(&script)))
+        .or_else(|| find(subtags[0]))
+}
+
+/// Writes `stack` as the value of `font-family`, ending with `generic` if any.
+fn font_family_value(stack: &[String], generic: Option<FontPrefer>) -> String {
+    let mut families: Vec<String> = stack
+        .iter()
+        .map(|family| family.trim())
+        .filter(|family| !family.is_empty())
+        .map(|family| {
+            if GENERIC_FAMILIES.contains(&family) {
+                String::from(family)
+            } else {
+                format!("\"{}\"", family.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        })
+        .collect();
+    let generic = match generic {
+        Some(FontPrefer::Serif) => Some("serif"),
+        Some(FontPrefer::SansSerif) => Some("sans-serif"),
+        None => None,
+    };
+    if let Some(generic) = generic
+        && !families.iter().any(|family| family == generic)
+    {
+        families.push(String::from(generic));
+    }
+    families.join(", ")
+}
+
+/// The generic family `stack` stands for: the last of serif and sans-serif it lists, or
+/// else the preferred one.
+fn stack_generic(stack: &[String], prefer: Option<FontPrefer>) -> Option<FontPrefer> {
+    stack
+        .iter()
+        .rev()
+        .find_map(|family| match family.trim() {
+            "serif" => Some(FontPrefer::Serif),
+            "sans-serif" => Some(FontPrefer::SansSerif),
+            _ => None,
+        })
+        .or(prefer)
+}
+
+/// Whether `lang` can go in `:lang()` as is.
+fn is_valid_tag(lang: &str) -> bool {
+    !lang.is_empty()
+        && lang
+            .split('-')
+            .all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Gives text the font stack set for its language, found in the document or the book.
+///
+/// Elements declaring a language, and the reader if the book is in it, use the stack
+/// instead of inheriting fonts meant for other scripts, though the rules of the publisher
+/// for the elements themselves win. Publisher font families falling back to the generic
+/// family of the stack fall back to the stack too, through the `--og-font-*` custom
+/// property of that family.
+pub struct FontStacks {
+    stacks: BTreeMap<String, Vec<String>>,
+    generic: Option<FontPrefer>,
+    langs: BTreeSet<String>,
+}
+
+impl FontStacks {
+    pub fn new(
+        stacks: BTreeMap<String, Vec<String>>,
+        generic: Option<FontPrefer>,
+        book_lang: Option<String>,
+    ) -> Self {
+        Self {
+            stacks,
+            generic,
+            langs: book_lang.into_iter().collect(),
+        }
+    }
+}
+
+impl ContentTransform for FontStacks {
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        if let Event::Start(ref e) | Event::Empty(ref e) = event
+            && let Some(lang) = element_lang(e)
+        {
+            self.langs.insert(lang);
+        }
+        out.push(event);
+    }
+
+    fn head_css(&mut self) -> Option<String> {
+        // longer tags last, to win over the tags they start with
+        let mut langs: Vec<&String> = self.langs.iter().filter(|l| is_valid_tag(l)).collect();
+        langs.sort_by_key(|lang| lang.split('-').count());
+
+        let mut css = String::new();
+        for lang in langs {
+            let Some(stack) = stack_for(lang, &self.stacks) else {
+                continue;
+            };
+            let value = font_family_value(stack, self.generic);
+            if value.is_empty() {
+                continue;
+            }
+            css += &format!(
+                ":host(:lang({lang})), :where([lang]:lang({lang})) {{ font-family: {value}; }}\n"
+            );
+            let generic = match stack_generic(stack, self.generic) {
+                Some(FontPrefer::Serif) => "serif",
+                Some(FontPrefer::SansSerif) => "sans-serif",
+                None => continue,
+            };
+            let property = font_custom_property(generic);
+            css += &format!(":lang({lang}) {{ {property}: {value}; }}\n");
+        }
+        (!css.is_empty()).then_some(css)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_stack_for() {
+        let stacks: BTreeMap<String, Vec<String>> = [
+            ("zh", "Noto Sans SC"),
+            ("Hant", "Noto Sans TC"),
+            ("zh-Hant-HK", "Noto Sans HK"),
+            ("ja", "Noto Serif JP"),
+        ]
+        .into_iter()
+        .map(|(key, family)| (String::from(key), vec![String::from(family)]))
+        .collect();
+        let family = |lang| stack_for(lang, &stacks).map(|stack| stack[0].as_str());
+        assert_eq!(Some("Noto Sans SC"), family("zh-CN"));
+        assert_eq!(Some("Noto Sans TC"), family("zh-TW"));
+        assert_eq!(Some("Noto Sans TC"), family("zh-hant"));
+        assert_eq!(Some("Noto Sans HK"), family("zh-Hant-HK"));
+        assert_eq!(Some("Noto Serif JP"), family("ja-JP"));
+        assert_eq!(None, family("en"));
+    }
+
+    #[test]
+    fn test_font_stacks() {
+        let stacks = BTreeMap::from([
+            (String::from("ja"), vec![String::from("Noto Serif JP")]),
+            (
+                String::from("en"),
+                vec![String::from("Georgia"), String::from("serif")],
+            ),
+        ]);
+        let pass = FontStacks::new(
+            stacks,
+            Some(FontPrefer::SansSerif),
+            Some(String::from("en")),
+        );
+        let mut pipeline = Pipeline::with(pass);
+        let input = "<html><head><title>A</title></head><body><p>A<span lang=\"ja-JP\">日本</span></p></body></html>";
+        let expected = "<html><head><title>A</title><style>\
+                        :host(:lang(en)), :where([lang]:lang(en)) { font-family: \"Georgia\", serif, sans-serif; }\n\
+                        :lang(en) { --og-font-7365726966: \"Georgia\", serif, sans-serif; }\n\
+                        :host(:lang(ja-JP)), :where([lang]:lang(ja-JP)) { font-family: \"Noto Serif JP\", sans-serif; }\n\
+                        :lang(ja-JP) { --og-font-73616e732d7365726966: \"Noto Serif JP\", sans-serif; }\n\
+                        </style></head><body><p>A<span lang=\"ja-JP\">日本</span></p></body></html>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
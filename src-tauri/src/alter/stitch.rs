@@ -0,0 +1,299 @@
+use std::io::BufRead;
+
+use cssparser::{ToCss, Token};
+use quick_xml::{
+    Reader,
+    escape::{escape, partial_escape},
+    events::{BytesStart, Event},
+};
+use url::Url;
+
+use super::{ContentTransform, CssBlock, CssContext, CssPosition, Pipeline, quoted_url};
+use crate::errors::AnyErr;
+
+/// Attribute of the sections of the continuous document, holding the URL of the content
+/// document to fill each with.
+const CHAPTER_ATTR: &str = "data-og-chapter";
+
+/// Prefix of the IDs of the content document at `index` in the spine, when stitched into
+/// the continuous document.
+pub fn id_prefix(index: usize) -> String {
+    format!("og-{index}-")
+}
+
+/// Prefixes `id`s, and links to fragments of the same document, so that the IDs of content
+/// documents stitched together don't collide. ID selectors of the `<style>`s of the
+/// document are prefixed too, but not those of linked stylesheets, which no longer apply.
+pub struct NamespaceIds {
+    prefix: String,
+}
+
+impl NamespaceIds {
+    pub fn new(prefix: String) -> Self {
+        Self { prefix }
+    }
+
+    /// Copy of `e` with IDs prefixed, or `None` if it refers to none.
+    fn prefix_ids(&self, e: &BytesStart<'_>) -> Option<BytesStart<'static>> {
+        let is_id_ref =
+            |key: &[u8], value: &[u8]| key == b"id" || (key == b"href" && value.starts_with(b"#"));
+        if !e
+            .attributes()
+            .flatten()
+            .any(|attr| is_id_ref(attr.key.local_name().as_ref(), &attr.value))
+        {
+            return None;
+        }
+
+        let mut prefixed = e.to_owned();
+        prefixed.clear_attributes();
+        for attr in e.attributes().flatten() {
+            let key = attr.key.local_name();
+            let value = if key.as_ref() == b"id" {
+                [self.prefix.as_bytes(), &attr.value].concat()
+            } else if let Some(id) = attr
+                .value
+                .strip_prefix(b"#")
+                .filter(|_| key.as_ref() == b"href")
+            {
+                [b"#", self.prefix.as_bytes(), id].concat()
+            } else {
+                attr.value.to_vec()
+            };
+            prefixed.push_attribute((attr.key.as_ref(), value.as_slice()));
+        }
+        Some(prefixed.into_owned())
+    }
+}
+
+impl ContentTransform for NamespaceIds {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        match token {
+            Token::IDHash(id) if ctx.position == CssPosition::Prelude => {
+                Some(Token::IDHash(format!("{}{id}", self.prefix).into()).to_css_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn xhtml_event(&mut self, event: Event<'static>, out: &mut Vec<Event<'static>>) {
+        out.push(match event {
+            Event::Start(ref e) => self.prefix_ids(e).map_or(event, Event::Start),
+            Event::Empty(ref e) => self.prefix_ids(e).map_or(event, Event::Empty),
+            event => event,
+        });
+    }
+}
+
+/// Resolves the URLs of a stylesheet against `base`, so that they still point to the same
+/// resources once the stylesheet is moved.
+struct ResolveUrls {
+    base: Url,
+}
+
+impl ResolveUrls {
+    fn css_url(&self, value: &str) -> Option<String> {
+        let url = self.base.join(value.trim()).ok()?;
+        Some(format!("url(\"{url}\")"))
+    }
+}
+
+impl ContentTransform for ResolveUrls {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        match token {
+            Token::UnquotedUrl(value) => self.css_url(value),
+            Token::QuotedString(value)
+                if ctx.position == CssPosition::Prelude
+                    && ctx.at_rule == "import"
+                    && !ctx.nested =>
+            {
+                self.css_url(value)
+            }
+            _ => None,
+        }
+    }
+
+    fn css_function(
+        &mut self,
+        name: &str,
+        arguments: &str,
+        _ctx: &CssContext<'_>,
+    ) -> Option<String> {
+        if name != "url" {
+            return None;
+        }
+        self.css_url(quoted_url(arguments)?)
+    }
+}
+
+/// Runs `pass` alone on the rules of `css`, or returns `css` as is if it can't be parsed.
+fn alter_rules(css: String, pass: impl ContentTransform + 'static) -> String {
+    let mut pipeline = Pipeline {
+        passes: vec![Box::new(pass)],
+        user_css: String::new(),
+    };
+    pipeline.alter_css_str(&css, CssBlock::Rules).unwrap_or(css)
+}
+
+/// The stylesheets in the `<head>` of a content document, as written.
+#[derive(Debug, Default, PartialEq)]
+pub struct HeadStyles {
+    /// Linked stylesheets, resolved against the document.
+    pub links: Vec<Url>,
+    /// Contents of `<style>`s, with URLs resolved against the document.
+    pub styles: Vec<String>,
+}
+
+impl HeadStyles {
+    /// Reads the `<head>` of the document at `url`, up to its end only.
+    pub fn read<R: BufRead>(reader: R, url: &Url) -> Result<Self, AnyErr> {
+        let mut reader = Reader::from_reader(reader);
+        reader.config_mut().trim_text(false);
+
+        let mut buffer = Vec::new();
+        let mut head = Self::default();
+        let mut style: Option<String> = None;
+        loop {
+            match reader.read_event_into(&mut buffer) {
+                Err(_) => return Err(AnyErr::EpubContent),
+                Ok(Event::Start(e) | Event::Empty(e)) if e.local_name().as_ref() == b"link" => {
+                    let is_stylesheet = matches!(
+                        e.try_get_attribute("rel"),
+                        Ok(Some(rel)) if String::from_utf8_lossy(&rel.value)
+                            .split_ascii_whitespace()
+                            .any(|token| token.eq_ignore_ascii_case("stylesheet"))
+                    );
+                    if let (true, Ok(Some(href))) = (is_stylesheet, e.try_get_attribute("href"))
+                        && let Ok(link) = url.join(String::from_utf8_lossy(&href.value).trim())
+                    {
+                        head.links.push(link);
+                    }
+                }
+                Ok(Event::Start(e)) if e.local_name().as_ref() == b"style" => {
+                    style = Some(String::new());
+                }
+                Ok(Event::Text(e)) if style.is_some() => {
+                    let css = e.unescape().map_err(|_| AnyErr::EpubContent)?;
+                    style.get_or_insert_default().push_str(&css);
+                }
+                Ok(Event::CData(e)) if style.is_some() => {
+                    let css = String::from_utf8_lossy(&e);
+                    style.get_or_insert_default().push_str(&css);
+                }
+                Ok(Event::End(e)) if e.local_name().as_ref() == b"style" => {
+                    if let Some(style) = style.take() {
+                        let base = url.clone();
+                        head.styles.push(alter_rules(style, ResolveUrls { base }));
+                    }
+                }
+                Ok(Event::End(e)) if e.local_name().as_ref() == b"head" => return Ok(head),
+                Ok(Event::Start(e)) if e.local_name().as_ref() == b"body" => return Ok(head),
+                Ok(Event::Eof) => return Ok(head),
+                Ok(_) => {}
+            }
+            buffer.clear();
+        }
+    }
+}
+
+/// Writes a document to read content documents one after another, with the stylesheets of
+/// their heads, each once, and an empty `<section>` for each, for the reader to fill with
+/// its body as it scrolls near. `chapters` are the whole spine, in order, for the ID
+/// selectors of their `<style>`s to match their IDs once stitched.
+pub fn continuous_document<'a, I>(chapters: I, lang: Option<&str>) -> String
+where
+    I: IntoIterator<Item = (&'a Url, HeadStyles)>,
+{
+    let mut links: Vec<Url> = Vec::new();
+    let mut styles: Vec<String> = Vec::new();
+    let mut sections = String::new();
+    for (index, (url, head)) in chapters.into_iter().enumerate() {
+        for link in head.links {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+        for style in head.styles {
+            let style = alter_rules(style, NamespaceIds::new(id_prefix(index)));
+            if !styles.contains(&style) {
+                styles.push(style);
+            }
+        }
+        sections += &format!(
+            "<section {CHAPTER_ATTR}=\"{}\"></section>\n",
+            escape(url.as_str())
+        );
+    }
+
+    let mut doc = String::from("<html xmlns=\"http://www.w3.org/1999/xhtml\"");
+    if let Some(lang) = lang {
+        let lang = escape(lang);
+        doc += &format!(" lang=\"{lang}\" xml:lang=\"{lang}\"");
+    }
+    doc += "><head><title></title>\n";
+    for link in links {
+        doc += &format!(
+            "<link rel=\"stylesheet\" href=\"{}\"/>\n",
+            escape(link.as_str())
+        );
+    }
+    for style in styles {
+        doc += &format!("<style>{}</style>\n", partial_escape(&style));
+    }
+    doc += "</head><body>\n";
+    doc += &sections;
+    doc += "</body></html>\n";
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Pipeline, alter_xhtml};
+
+    #[test]
+    fn test_namespace_ids() {
+        let mut pipeline = Pipeline::with(NamespaceIds::new(id_prefix(3)));
+        let input = "<body><h1 id=\"ch\">T</h1><p>a<a href=\"#n1\">1</a>\
+                     <a href=\"ch2.xhtml#x\">b</a></p><aside id=\"n1\">N</aside></body>";
+        let expected = "<body><h1 id=\"og-3-ch\">T</h1><p>a<a href=\"#og-3-n1\">1</a>\
+                        <a href=\"ch2.xhtml#x\">b</a></p><aside id=\"og-3-n1\">N</aside></body>";
+        let output = alter_xhtml(input.as_bytes(), &mut pipeline).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_continuous_document() {
+        let ch1 = Url::parse("epub:/OEBPS/text/ch1.xhtml").unwrap();
+        let ch2 = Url::parse("epub:/OEBPS/text/ch2.xhtml").unwrap();
+        let head = |url| {
+            let doc = "<html><head><link rel=\"stylesheet\" href=\"../css/book.css\"/>\
+                       <style>p { margin: 0; }</style>\
+                       <style>#t { background: url(../img/t.png); }</style></head><body></body></html>";
+            HeadStyles::read(doc.as_bytes(), url).unwrap()
+        };
+        assert_eq!(
+            HeadStyles {
+                links: vec![Url::parse("epub:/OEBPS/css/book.css").unwrap()],
+                styles: vec![
+                    String::from("p { margin: 0; }"),
+                    String::from("#t { background: url(\"epub:/OEBPS/img/t.png\"); }")
+                ],
+            },
+            head(&ch1)
+        );
+
+        let doc = continuous_document([(&ch1, head(&ch1)), (&ch2, head(&ch2))], Some("en"));
+        let expected = "<html xmlns=\"http://www.w3.org/1999/xhtml\" lang=\"en\" xml:lang=\"en\">\
+                        <head><title></title>\n\
+                        <link rel=\"stylesheet\" href=\"epub:/OEBPS/css/book.css\"/>\n\
+                        <style>p { margin: 0; }</style>\n\
+                        <style>#og-0-t { background: url(\"epub:/OEBPS/img/t.png\"); }</style>\n\
+                        <style>#og-1-t { background: url(\"epub:/OEBPS/img/t.png\"); }</style>\n\
+                        </head><body>\n\
+                        <section data-og-chapter=\"epub:/OEBPS/text/ch1.xhtml\"></section>\n\
+                        <section data-og-chapter=\"epub:/OEBPS/text/ch2.xhtml\"></section>\n\
+                        </body></html>\n";
+        assert_eq!(expected, doc);
+    }
+}
@@ -0,0 +1,144 @@
+use cssparser::{ParseError, Parser, ParserInput, ToCss, Token};
+
+use super::{ContentTransform, CssContext, CssPosition, is_group_at_rule};
+
+fn is_writing_mode_property(name: &str) -> bool {
+    ["writing-mode", "-epub-writing-mode", "-webkit-writing-mode"]
+        .iter()
+        .any(|p| name.eq_ignore_ascii_case(p))
+}
+
+/// Includes the old `tb-*` values.
+fn is_vertical_writing_mode(value: &str) -> bool {
+    let value = value.to_ascii_lowercase();
+    value.starts_with("vertical-") || value.starts_with("sideways-") || value.starts_with("tb")
+}
+
+/// Whether a selector list selects the root element or `body`.
+fn selects_root(selectors: &str) -> bool {
+    selectors.split(',').any(|selector| {
+        let selector = selector.trim_start().to_ascii_lowercase();
+        let compound_end = selector
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '+' | '~'))
+            .unwrap_or(selector.len());
+        let compound = &selector[..compound_end];
+        let type_end = compound
+            .find(['.', '#', '[', ':'])
+            .unwrap_or(compound.len());
+        matches!(&compound[..type_end], "html" | "body") || compound.contains(":root")
+    })
+}
+
+// These functions read to the end, because `parse_nested_block` fails on leftover input.
+
+fn declares_vertical<'i>(parser: &mut Parser<'i, '_>) -> bool {
+    let mut found = false;
+    let mut property = None::<String>;
+    let mut in_value = false;
+    while let Ok(token) = parser.next() {
+        match token {
+            Token::Semicolon => {
+                property = None;
+                in_value = false;
+            }
+            Token::Ident(ident) if !in_value => property = Some(ident.to_string()),
+            Token::Colon => in_value = true,
+            Token::Ident(value)
+                if property.as_deref().is_some_and(is_writing_mode_property)
+                    && is_vertical_writing_mode(value) =>
+            {
+                found = true;
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+fn rules_set_vertical_root<'i>(parser: &mut Parser<'i, '_>) -> bool {
+    let mut found = false;
+    let mut prelude = String::new();
+    while let Ok(token) = parser.next_including_whitespace() {
+        match token {
+            Token::CurlyBracketBlock => {
+                let prelude = std::mem::take(&mut prelude);
+                let is_group = prelude
+                    .strip_prefix('@')
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .is_some_and(|name| is_group_at_rule(&name.to_ascii_lowercase()));
+                let found_in_block = parser.parse_nested_block(|parser_nested| {
+                    Ok::<_, ParseError<'i, ()>>(if is_group {
+                        rules_set_vertical_root(parser_nested)
+                    } else {
+                        selects_root(&prelude) && declares_vertical(parser_nested)
+                    })
+                });
+                found |= found_in_block.unwrap_or(false);
+            }
+            Token::Semicolon => prelude.clear(),
+            token => prelude.push_str(&token.to_css_string()),
+        }
+    }
+    found
+}
+
+/// Whether `css` sets a vertical writing mode on `html`, `body` or `:root`, directly or
+/// through `-epub-writing-mode`.
+pub fn is_vertical_css(css: &str) -> bool {
+    let mut input = ParserInput::new(css);
+    let mut parser = Parser::new(&mut input);
+    rules_set_vertical_root(&mut parser)
+}
+
+/// Lays out vertically written content horizontally.
+pub struct Horizontal;
+
+impl ContentTransform for Horizontal {
+    fn css_token(&mut self, token: &Token<'_>, ctx: &CssContext<'_>) -> Option<String> {
+        if ctx.position != CssPosition::Value || ctx.nested {
+            return None;
+        }
+        let Token::Ident(value) = token else {
+            return None;
+        };
+        match ctx.property {
+            "writing-mode" if is_vertical_writing_mode(value) => {
+                Some(String::from("horizontal-tb"))
+            }
+            // tate-chu-yoko has no meaning in horizontal text
+            "text-combine-upright" => Some(String::from("none")),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alter::{Context, CssBlock, Pipeline, settings_with};
+    use crate::prefs;
+
+    #[test]
+    fn test_is_vertical_css() {
+        assert!(is_vertical_css("html { -epub-writing-mode: vertical-rl; }"));
+        assert!(is_vertical_css(
+            "@media screen { body.main, p { writing-mode: tb-rl } }"
+        ));
+        assert!(!is_vertical_css("p.tate { writing-mode: vertical-rl; }"));
+        assert!(!is_vertical_css("html { writing-mode: horizontal-tb; }"));
+    }
+
+    #[test]
+    fn test_horizontal() {
+        let settings = settings_with(prefs::VERTICAL_LAYOUT, serde_json::json!("horizontal"));
+        let mut pipeline = Pipeline::new(&settings, &Context::default());
+        let input =
+            "html { -epub-writing-mode: vertical-rl; } .tcy { -webkit-text-combine: horizontal; }";
+        let expected =
+            ":host { writing-mode: horizontal-tb; } .tcy { text-combine-upright: none; }";
+        assert_eq!(
+            expected,
+            pipeline.alter_css_str(input, CssBlock::Rules).unwrap()
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::io::{self, BufRead};
+
+use quick_xml::{
+    Reader, Writer,
+    events::{BytesEnd, BytesStart, Event},
+};
+
+use super::XHTML_NS;
+
+/// Attributes of DTBook elements that mean the same in XHTML.
+const KEPT_ATTRIBUTES: &[&[u8]] = &[
+    b"id",
+    b"class",
+    b"lang",
+    b"xml:lang",
+    b"dir",
+    b"title",
+    b"href",
+    b"src",
+    b"alt",
+    b"width",
+    b"height",
+    b"colspan",
+    b"rowspan",
+    b"start",
+    b"summary",
+    b"name",
+    b"content",
+    b"rel",
+    b"type",
+    b"media",
+];
+
+/// What a DTBook element becomes in XHTML.
+struct Mapping {
+    name: String,
+    class: Option<&'static str>,
+    role: Option<&'static str>,
+}
+
+impl Mapping {
+    fn new(name: &str, class: Option<&'static str>, role: Option<&'static str>) -> Self {
+        Self {
+            name: String::from(name),
+            class,
+            role,
+        }
+    }
+}
+
+/// Maps the DTBook element `e` to XHTML. `parent` is the XHTML element it is in, and
+/// `depth` how many `level` elements it is in.
+fn map(e: &BytesStart<'_>, parent: Option<&str>, depth: usize) -> Mapping {
+    let local_name = e.local_name();
+    let name = local_name.as_ref();
+    let to = |to: &str| Mapping::new(to, None, None);
+    let with_class = |to: &str, class| Mapping::new(to, Some(class), None);
+    match name {
+        b"dtbook" => to("html"),
+        b"book" => to("body"),
+        b"frontmatter" => with_class("section", "frontmatter"),
+        b"bodymatter" => with_class("section", "bodymatter"),
+        b"rearmatter" => with_class("section", "rearmatter"),
+        b"level" | b"level1" | b"level2" | b"level3" | b"level4" | b"level5" | b"level6" => {
+            to("section")
+        }
+        b"hd" | b"levelhd" => to(&format!("h{}", depth.clamp(1, 6))),
+        b"doctitle" => with_class("h1", "doctitle"),
+        b"docauthor" => with_class("p", "docauthor"),
+        b"covertitle" => with_class("p", "covertitle"),
+        b"bridgehead" => with_class("p", "bridgehead"),
+        b"list" => {
+            let ordered = e
+                .try_get_attribute("type")
+                .ok()
+                .flatten()
+                .is_some_and(|attr| attr.value.as_ref() == b"ol");
+            to(if ordered { "ol" } else { "ul" })
+        }
+        b"lic" => with_class("span", "lic"),
+        b"pagenum" => Mapping::new("span", Some("pagenum"), Some("doc-pagebreak")),
+        b"noteref" => Mapping::new("a", Some("noteref"), Some("doc-noteref")),
+        b"annoref" => with_class("a", "annoref"),
+        b"note" => Mapping::new("aside", Some("note"), Some("doc-footnote")),
+        b"annotation" => with_class("aside", "annotation"),
+        b"sidebar" => with_class("aside", "sidebar"),
+        b"prodnote" => with_class("aside", "prodnote"),
+        b"imggroup" => to("figure"),
+        b"caption" if parent == Some("figure") => to("figcaption"),
+        b"poem" => with_class("div", "poem"),
+        b"linegroup" => with_class("div", "linegroup"),
+        b"line" => with_class("p", "line"),
+        b"linenum" => with_class("span", "linenum"),
+        b"epigraph" => with_class("blockquote", "epigraph"),
+        b"byline" => with_class("p", "byline"),
+        b"dateline" => with_class("p", "dateline"),
+        b"author" => with_class("p", "author"),
+        b"title" if parent == Some("head") => to("title"),
+        b"title" => with_class("span", "title"),
+        b"acronym" => to("abbr"),
+        b"sent" => with_class("span", "sent"),
+        b"w" => with_class("span", "w"),
+        b"head" | b"meta" | b"link" | b"style" | b"p" | b"div" | b"span" | b"a" | b"em"
+        | b"strong" | b"sub" | b"sup" | b"br" | b"hr" | b"img" | b"blockquote" | b"pre"
+        | b"code" | b"kbd" | b"samp" | b"q" | b"cite" | b"abbr" | b"dfn" | b"bdo" | b"address"
+        | b"li" | b"dl" | b"dt" | b"dd" | b"table" | b"caption" | b"thead" | b"tbody"
+        | b"tfoot" | b"tr" | b"th" | b"td" | b"col" | b"colgroup" | b"h1" | b"h2" | b"h3"
+        | b"h4" | b"h5" | b"h6" => to(&String::from_utf8_lossy(name)),
+        _ => to("div"),
+    }
+}
+
+/// Returns the XHTML start tag of the DTBook element `e` as mapped.
+fn start_tag(e: &BytesStart<'_>, mapping: &Mapping) -> BytesStart<'static> {
+    let mut start = BytesStart::new(mapping.name.clone());
+    if mapping.name == "html" {
+        start.push_attribute(("xmlns", XHTML_NS));
+    }
+    let mut class = mapping.class.map(String::from);
+    for attr in e.attributes().flatten() {
+        match attr.key.as_ref() {
+            b"class" => {
+                let value = String::from_utf8_lossy(&attr.value);
+                class = Some(match class {
+                    Some(class) => format!("{class} {value}"),
+                    None => value.into_owned(),
+                });
+            }
+            // the target of note and annotation references
+            b"idref" => start.push_attribute((&b"href"[..], attr.value.as_ref())),
+            b"type" if mapping.name == "ol" || mapping.name == "ul" => {}
+            key if KEPT_ATTRIBUTES.contains(&key) => start.push_attribute(attr),
+            _ => {}
+        }
+    }
+    if let Some(class) = class {
+        start.push_attribute(("class", class.as_str()));
+    }
+    if let Some(role) = mapping.role {
+        start.push_attribute(("role", role));
+    }
+    start
+}
+
+/// Converts a DTBook document to XHTML, element by element.
+pub(super) fn to_xhtml<R: BufRead>(reader: R) -> Result<Vec<u8>, quick_xml::Error> {
+    let mut reader = Reader::from_reader(reader);
+    reader.config_mut().trim_text(false);
+
+    let mut buffer = Vec::new();
+    let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+
+    // XHTML names of the open elements, and whether each is a `level`
+    let mut stack: Vec<(String, bool)> = Vec::new();
+    loop {
+        let evt = reader.read_event_into(&mut buffer)?.into_owned();
+        buffer.clear();
+        let evt = match evt {
+            Event::Eof => return Ok(writer.into_inner().into_inner()),
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let parent = stack.last().map(|(name, _)| name.as_str());
+                let depth = stack.iter().filter(|(_, is_level)| *is_level).count();
+                let mapping = map(e, parent, depth);
+                let start = start_tag(e, &mapping);
+                if let Event::Start(_) = evt {
+                    let is_level = mapping.name == "section" && mapping.class.is_none();
+                    stack.push((mapping.name, is_level));
+                    Event::Start(start)
+                } else {
+                    Event::Empty(start)
+                }
+            }
+            Event::End(_) => {
+                let Some((name, _)) = stack.pop() else {
+                    continue;
+                };
+                Event::End(BytesEnd::new(name))
+            }
+            Event::Text(_) | Event::CData(_) | Event::Comment(_) | Event::Decl(_) => evt,
+            // the DTBook DTD, and processing instructions such as stylesheets
+            _ => continue,
+        };
+        let _ = writer.write_event(evt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dtbook_to_xhtml() {
+        let input = r##"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE dtbook PUBLIC "-//NISO//DTD dtbook 2005-3//EN" "http://www.daisy.org/z3986/2005/dtbook-2005-3.dtd">
+<dtbook xmlns="http://www.daisy.org/z3986/2005/dtbook/" version="2005-3" xml:lang="en"><head><meta name="dc:Title" content="Book"/></head><book><frontmatter><doctitle>Book</doctitle></frontmatter><bodymatter><level1 id="c1"><h1>One</h1><level2><hd>Part</hd><p smilref="a.smil#p1">Text<noteref idref="#n1">1</noteref><pagenum id="p2">2</pagenum></p><list type="ol"><li>Item</li></list></level2></level1><note id="n1"><p>Note</p></note></bodymatter></book></dtbook>"##;
+        let expected = r##"<?xml version="1.0" encoding="UTF-8"?>
+
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en"><head><meta name="dc:Title" content="Book"/></head><body><section class="frontmatter"><h1 class="doctitle">Book</h1></section><section class="bodymatter"><section id="c1"><h1>One</h1><section><h2>Part</h2><p>Text<a href="#n1" class="noteref" role="doc-noteref">1</a><span id="p2" class="pagenum" role="doc-pagebreak">2</span></p><ol><li>Item</li></ol></section></section><aside id="n1" class="note" role="doc-footnote"><p>Note</p></aside></section></body></html>"##;
+        let output = to_xhtml(input.as_bytes()).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Read};
+
+use html5ever::{ParseOpts, parse_document, tendril::TendrilSink};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use quick_xml::{
+    Writer,
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+};
+
+use crate::errors::AnyErr;
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Whether `name` can be written in XML as is, with no namespace prefix to declare.
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Writes `node` and its descendants as XML. `parent_ns` is the namespace of the element
+/// it is in, declared again where it changes, e.g., on `<svg>` and `<math>`.
+fn write_node(node: &Handle, parent_ns: &str, writer: &mut Writer<io::Cursor<Vec<u8>>>) {
+    let write_children = |writer: &mut Writer<_>, ns: &str| {
+        for child in node.children.borrow().iter() {
+            write_node(child, ns, writer);
+        }
+    };
+    match node.data {
+        NodeData::Document => write_children(writer, parent_ns),
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => {
+            let ns: &str = &name.ns;
+            let local: &str = &name.local;
+            // left by sloppy markup, e.g., `<p:x>`
+            if !is_xml_name(local) {
+                write_children(writer, parent_ns);
+                return;
+            }
+
+            let mut start = BytesStart::new(local);
+            if ns != parent_ns {
+                start.push_attribute(("xmlns", ns));
+                if ns == SVG_NS {
+                    start.push_attribute(("xmlns:xlink", XLINK_NS));
+                }
+            }
+            for attr in attrs.borrow().iter() {
+                let attr_ns: &str = &attr.name.ns;
+                let attr_local: &str = &attr.name.local;
+                let key = match attr_ns {
+                    XLINK_NS if ns == SVG_NS => format!("xlink:{attr_local}"),
+                    XML_NS => format!("xml:{attr_local}"),
+                    "" if attr_local == "xml:lang" => String::from(attr_local),
+                    // namespaces are declared above
+                    "" if attr_local != "xmlns" && is_xml_name(attr_local) => {
+                        String::from(attr_local)
+                    }
+                    _ => continue,
+                };
+                start.push_attribute((key.as_str(), &*attr.value));
+            }
+
+            if node.children.borrow().is_empty() {
+                let _ = writer.write_event(Event::Empty(start));
+            } else {
+                let _ = writer.write_event(Event::Start(start));
+                write_children(writer, ns);
+                let _ = writer.write_event(Event::End(BytesEnd::new(local)));
+            }
+        }
+        NodeData::Text { ref contents } => {
+            let _ = writer.write_event(Event::Text(BytesText::new(&contents.borrow())));
+        }
+        // doctypes, comments, which may not be well-formed in XML, and processing
+        // instructions, which HTML has none of
+        _ => {}
+    }
+}
+
+/// Parses HTML as browsers do, and writes the document as XHTML.
+pub(super) fn to_xhtml<R: Read>(reader: &mut R) -> Result<Vec<u8>, AnyErr> {
+    let dom = parse_document(RcDom::default(), ParseOpts::default())
+        .from_utf8()
+        .read_from(reader)?;
+    let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+    let _ = writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)));
+    write_node(&dom.document, "", &mut writer);
+    Ok(writer.into_inner().into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_xhtml() {
+        let input = "<!DOCTYPE html><title>A</title><p class=x>A<br>B &amp; C<svg viewBox=\"0 0 1 1\"><image xlink:href=\"a.png\"/></svg>";
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                        <html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>A</title></head>\
+                        <body><p class=\"x\">A<br/>B &amp; C\
+                        <svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" viewBox=\"0 0 1 1\">\
+                        <image xlink:href=\"a.png\"/></svg></p></body></html>";
+        let output = to_xhtml(&mut input.as_bytes()).unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,36 @@
+//! Converts content documents of other types to XHTML, before they go through `alter_xhtml`.
+
+mod dtbook;
+mod html;
+mod text;
+
+use std::io::{BufReader, Read};
+
+use crate::errors::AnyErr;
+
+pub const MIMETYPE_HTML: &str = "text/html";
+pub const MIMETYPE_DTBOOK: &str = "application/x-dtbook+xml";
+pub const MIMETYPE_TEXT: &str = "text/plain";
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+
+/// Whether content documents of `media_type` can be converted to XHTML.
+pub fn is_convertible(media_type: &str) -> bool {
+    matches!(media_type, MIMETYPE_HTML | MIMETYPE_DTBOOK | MIMETYPE_TEXT)
+}
+
+/// Converts a content document of `media_type` to XHTML.
+pub fn to_xhtml<R: Read>(mut reader: R, media_type: &str) -> Result<Vec<u8>, AnyErr> {
+    match media_type {
+        MIMETYPE_HTML => html::to_xhtml(&mut reader),
+        MIMETYPE_DTBOOK => {
+            dtbook::to_xhtml(BufReader::new(reader)).map_err(|_| AnyErr::EpubContent)
+        }
+        MIMETYPE_TEXT => {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            Ok(text::to_xhtml(&String::from_utf8_lossy(&buf)))
+        }
+        _ => Err(AnyErr::EpubContent),
+    }
+}
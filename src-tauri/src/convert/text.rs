@@ -0,0 +1,58 @@
+use quick_xml::escape::escape;
+
+use super::XHTML_NS;
+
+/// Whether the lines of a paragraph are laid out by hand, e.g., verse or a table, rather
+/// than hard wrapped prose. Only the first line of prose may be indented.
+fn is_preformatted(lines: &[&str]) -> bool {
+    lines.iter().any(|line| line.contains('\t'))
+        || lines[1..].iter().any(|line| line.starts_with(' '))
+}
+
+/// Wraps plain text in XHTML, a `<p>` per paragraph, or a `<pre>` if preformatted.
+/// Paragraphs are separated by blank lines.
+pub(super) fn to_xhtml(text: &str) -> Vec<u8> {
+    let mut body = String::new();
+    let mut lines = Vec::new();
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    for line in text.lines().chain([""]) {
+        let line = line.trim_end();
+        if !line.is_empty() {
+            lines.push(line);
+            continue;
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        if is_preformatted(&lines) {
+            body += &format!("<pre>{}</pre>\n", escape(lines.join("\n")));
+        } else {
+            let words: Vec<_> = lines.iter().map(|line| line.trim()).collect();
+            body += &format!("<p>{}</p>\n", escape(words.join(" ")));
+        }
+        lines.clear();
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <html xmlns=\"{XHTML_NS}\"><head><title></title></head><body>\n{body}</body></html>\n"
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_to_xhtml() {
+        let input = "\u{feff}Title\r\n\r\n  It was a dark\nand stormy night.\n\n\n\
+                     Roses are red,\n    violets are blue.\n";
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                        <html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title></title></head><body>\n\
+                        <p>Title</p>\n\
+                        <p>It was a dark and stormy night.</p>\n\
+                        <pre>Roses are red,\n    violets are blue.</pre>\n\
+                        </body></html>\n";
+        assert_eq!(expected, String::from_utf8(to_xhtml(input)).unwrap());
+    }
+}
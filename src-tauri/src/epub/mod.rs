@@ -234,6 +234,56 @@ impl Epub {
             .ok_or(UrlNotFoundErr)
     }
 
+    /// URLs and media types of resources in manifest but not in the (linear) spine.
+    pub fn resources_off_spine(&self) -> impl Iterator<Item = (&url::Url, &str)> {
+        self.resource_indexes
+            .iter()
+            .filter(|(url, _i)| !self.spine.contains(url))
+            .map(|(url, i)| (url, self.resources[*i].media_type.as_str()))
+    }
+
+    /// URLs of resources in manifest that are on the web.
+    pub fn remote_resources(&self) -> impl Iterator<Item = &url::Url> {
+        self.resource_indexes
+            .keys()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+    }
+
+    /// URLs of resources in manifest with the media type.
+    pub fn resources_of_type<'a>(
+        &'a self,
+        media_type: &'a str,
+    ) -> impl Iterator<Item = &'a url::Url> + 'a {
+        self.resource_indexes
+            .iter()
+            .filter(move |(_url, i)| self.resources[**i].media_type == media_type)
+            .map(|(url, _i)| url)
+    }
+
+    /// Value of the `primary-writing-mode` metadata, e.g., "vertical-rl".
+    pub fn primary_writing_mode(&self) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|item| item.property == "primary-writing-mode")
+            .map(|item| item.value.as_str())
+    }
+
+    /// Value of the `rendition:flow` metadata, e.g., "scrolled-continuous".
+    pub fn rendition_flow(&self) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|item| item.property == "rendition:flow")
+            .map(|item| item.value.as_str())
+    }
+
+    /// The first `dc:language`.
+    pub fn language(&self) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|item| item.property == "language")
+            .map(|item| item.value.as_str())
+    }
+
     pub fn title(&self) -> Option<&package::MetadataItem> {
         self.metadata.iter().find(|item| item.property == "title")
     }
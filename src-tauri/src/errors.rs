@@ -16,6 +16,14 @@ pub enum AnyErr {
     EpubUrlNotFound(#[from] crate::epub::UrlNotFoundErr),
     #[error("EPUB content error")]
     EpubContent,
+    // remote resources
+    #[error(transparent)]
+    Remote(#[from] reqwest::Error),
+    #[error("Remote resource too large")]
+    RemoteTooLarge,
+    // images
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
     // else
     #[error("Unknown internal error")]
     Unknown,
@@ -0,0 +1,27 @@
+//! Lists the fonts installed, to substitute for publisher fonts, see
+//! `prefs::FONT_SUBSTITUTE`.
+
+use std::collections::BTreeSet;
+use std::process::Command;
+
+use crate::errors::AnyErr;
+
+/// Returns the families of the fonts installed, sorted, as fontconfig lists them.
+///
+/// A font may have several names, e.g., localized ones, `Noto Sans CJK JP,Noto Sans CJK JP
+/// Regular`. Only the first is kept.
+pub fn system_families() -> Result<Vec<String>, AnyErr> {
+    let output = Command::new("fc-list").args([":", "family"]).output()?;
+    if !output.status.success() {
+        log::warn!("fc-list exited with {}", output.status);
+        return Err(AnyErr::Unknown);
+    }
+
+    let families: BTreeSet<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split(',').next())
+        .map(|family| family.replace('\\', "").trim().to_owned())
+        .filter(|family| !family.is_empty())
+        .collect();
+    Ok(families.into_iter().collect())
+}
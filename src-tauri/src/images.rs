@@ -0,0 +1,78 @@
+//! Downscales large raster images of books, see `prefs::DOWNSCALE_IMAGES`.
+
+use std::fs;
+use std::hash::Hasher;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use image::{ImageFormat, ImageReader, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use twox_hash::XxHash64;
+use url::Url;
+
+use crate::errors::AnyErr;
+
+/// Largest width or height of downscaled images, in pixels, if not set.
+pub const DEFAULT_MAX_DIMENSION: u32 = 2048;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Downscaling of one image of the opened book.
+pub struct Downscale {
+    /// Where results for the book are cached.
+    cache_dir: PathBuf,
+    /// Name of the cached result, from the URL of the image.
+    key: String,
+    max_dimension: u32,
+}
+
+impl Downscale {
+    pub fn new(cache_dir: PathBuf, url: &Url, max_dimension: u32) -> Self {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(url.path().as_bytes());
+        Self {
+            cache_dir,
+            key: format!("{:016x}-{max_dimension}", hasher.finish()),
+            max_dimension,
+        }
+    }
+
+    /// Returns `body`, an image of `media_type`, fitted in the maximum dimension, from the
+    /// cache if there. Returns `None` if the image is small enough or of another type.
+    pub fn apply(&self, body: &[u8], media_type: &str) -> Result<Option<Vec<u8>>, AnyErr> {
+        let format = match media_type {
+            "image/png" => ImageFormat::Png,
+            "image/jpeg" => ImageFormat::Jpeg,
+            _ => return Ok(None),
+        };
+        let path = self.cache_dir.join(&self.key);
+        if let Ok(cached) = fs::read(&path) {
+            return Ok(Some(cached));
+        }
+
+        let (width, height) =
+            ImageReader::with_format(Cursor::new(body), format).into_dimensions()?;
+        if width.max(height) <= self.max_dimension {
+            return Ok(None);
+        }
+        let image = image::load_from_memory_with_format(body, format)?;
+        let resized = image.resize(self.max_dimension, self.max_dimension, FilterType::Lanczos3);
+        let mut output = Cursor::new(Vec::new());
+        match format {
+            ImageFormat::Jpeg => resized
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut output, JPEG_QUALITY))?,
+            _ => resized.write_to(&mut output, format)?,
+        }
+        let output = output.into_inner();
+        log::debug!(
+            "downscaled image from {width}x{height} to {}x{}, {} to {} bytes",
+            resized.width(),
+            resized.height(),
+            body.len(),
+            output.len()
+        );
+
+        fs::create_dir_all(&self.cache_dir)?;
+        fs::write(&path, &output)?;
+        Ok(Some(output))
+    }
+}
@@ -1,27 +1,37 @@
 mod alter;
+mod convert;
 mod epub;
 mod errors;
+mod fonts;
+mod images;
 mod menus;
 mod prefs;
+mod proxy;
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::hash::Hasher;
 use std::io::{BufReader, Error as IoError, Read};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Mutex, MutexGuard};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use tauri::{AppHandle, Manager, State, Window, http};
+use tauri::{AppHandle, Listener, Manager, State, Window, http};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_store::{StoreExt, resolve_store_path};
 use twox_hash::XxHash64;
 use url::Url;
 
-use alter::{alter_css, alter_xhtml};
+use alter::{Pipeline, alter_css, alter_xhtml, extract_note};
 use epub::Epub;
 use errors::AnyErr;
 
 type EpubArchive = epub::EpubArchive<BufReader<File>>;
 type EpubHash = arrayvec::ArrayString<16>;
+/// A part of a split content document, and the path from `<body>` of its first element.
+type PartAndPath = (Vec<u8>, Vec<usize>);
 
 #[derive(serde::Serialize)]
 struct AboutPub {
@@ -47,6 +57,8 @@ struct AboutPub {
     pub pub_toc_is_legacy: bool,
     #[serde(rename(serialize = "pubLandingPage"))]
     pub pub_landing_page: Url,
+    #[serde(rename(serialize = "pubVertical"))]
+    pub pub_vertical: bool,
 }
 
 struct AppOpenedEpub {
@@ -54,13 +66,22 @@ struct AppOpenedEpub {
     pb: Epub,
     archive: EpubArchive,
     hash: EpubHash,
+    /// Whether the book is written vertically.
+    vertical: bool,
+    /// Resources on the web blocked so far, for diagnostics.
+    blocked_remote: BTreeSet<Url>,
+    /// How the content documents served so far split into parts, `None` for those served
+    /// whole.
+    splits: HashMap<Url, Option<alter::DocumentSplit>>,
 }
 
 impl TryFrom<&AppOpenedEpub> for AboutPub {
     type Error = AnyErr;
 
     fn try_from(opened: &AppOpenedEpub) -> Result<Self, Self::Error> {
-        let AppOpenedEpub { path, pb, .. } = opened;
+        let AppOpenedEpub {
+            path, pb, vertical, ..
+        } = opened;
 
         let file_metadata = std::fs::metadata(path)?;
         let as_ms = |time: SystemTime| {
@@ -90,6 +111,7 @@ impl TryFrom<&AppOpenedEpub> for AboutPub {
             pub_toc_url,
             pub_toc_is_legacy,
             pub_landing_page: pb.first_page_to_open().clone(),
+            pub_vertical: *vertical,
         };
         log::debug!(
             "AboutPub: {}",
@@ -100,22 +122,59 @@ impl TryFrom<&AppOpenedEpub> for AboutPub {
     }
 }
 
+/// Whether the preferences store changed since settings were cached. Set by a listener that
+/// may run while the state is locked, so it is apart from the state.
+static PREFS_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Settings of the opened book, kept until the files they are read from change.
+struct CachedSettings {
+    /// When the filewise styles file was modified, as read. `None` if there was none.
+    modified: Option<SystemTime>,
+    settings: prefs::Settings,
+}
+
 #[derive(Default)]
 struct AppData {
     opened_pub: Option<AppOpenedEpub>,
     setup_err: Option<AnyErr>,
+    settings: Option<CachedSettings>,
 }
 
 type AppState = Mutex<AppData>;
 
 const PROGRESS_STORE: &str = "progress.json";
 const PREFS_STORE: &str = "prefs.json";
+/// Directory of hyphenation pattern files, under the resource and app data directories.
+const HYPHENATION_DIR: &str = "hyphenation";
+/// Directory of remote resources fetched by the proxy, under the app cache directory.
+const REMOTE_CACHE_DIR: &str = "remote";
+/// Downscaled images, in the app cache directory, per book hash.
+const IMAGE_CACHE_DIR: &str = "images";
+/// User stylesheet applied to all books, next to the stores. Those of each book are named
+/// by its hash.
+const USER_STYLES: &str = "user.css";
+
+/// Query of requests for images as they are in the book, e.g., to zoom in.
+const ORIGINAL_IMAGE_QUERY: &str = "og-original";
+
+/// Query of requests for the document to read the spine continuously, at the root, and for
+/// the content documents stitched into it, see `alter::continuous_document`.
+const CONTINUOUS_QUERY: &str = "og-continuous";
 
 pub const MIMETYPE_XHTML: &str = "application/xhtml+xml";
 pub const MIMETYPE_SVG: &str = "image/svg+xml";
 pub const MIMETYPE_CSS: &str = "text/css";
 
-struct BytesAndMediaType(Vec<u8>, String);
+/// Policy of documents served into the sandboxed frame, see `prefs::ScriptPolicy`.
+const SANDBOX_CSP: &str = "default-src 'self' epub: http://epub.localhost https://epub.localhost data:; \
+    script-src 'self' epub: http://epub.localhost https://epub.localhost 'unsafe-inline'; \
+    style-src 'self' epub: http://epub.localhost https://epub.localhost 'unsafe-inline'; \
+    connect-src 'none'; frame-src 'none'; object-src 'none'; form-action 'none'";
+/// Response header telling the front-end to show the document in the sandboxed frame.
+const SANDBOXED_HEADER: &str = "Ogier-Epub-Sandboxed";
+
+/// The body and media type of a response, and whether it is to be sandboxed.
+struct BytesAndMediaType(Vec<u8>, String, bool);
 
 /// The same file produces the same hash.
 fn compute_file_hash(filepath: &PathBuf) -> Result<EpubHash, IoError> {
@@ -141,25 +200,55 @@ fn compute_file_hash(filepath: &PathBuf) -> Result<EpubHash, IoError> {
 }
 
 /// Do several things that are necessary when a book just opened.
-fn post_book_open(window: &Window, state: &mut MutexGuard<'_, AppData>) -> Result<bool, AnyErr> {
-    if let Some(setup_err) = state.setup_err.take() {
-        return Err(setup_err);
-    }
+fn post_book_open(window: &Window, state: &AppState) -> Result<bool, AnyErr> {
+    let (title, options) = {
+        let mut state_guard = state.lock().unwrap();
+        if let Some(setup_err) = state_guard.setup_err.take() {
+            return Err(setup_err);
+        }
 
-    let Some(opened) = &state.opened_pub else {
-        return Ok(false);
+        let Some(opened) = &state_guard.opened_pub else {
+            return Ok(false);
+        };
+        let title = match opened.pb.title() {
+            Some(item) => format!("{} - OgierEPUB", item.value),
+            None => String::from("OgierEPUB"),
+        };
+
+        let settings = content_settings(window.app_handle(), &mut state_guard)?;
+        let continuous_scroll = state_guard
+            .opened_pub
+            .as_ref()
+            .is_some_and(|opened| continuous_scroll(&settings, &opened.pb));
+        let options = menus::BookOptions {
+            publisher_styles: settings
+                .get(prefs::PUBLISHER_STYLES)
+                .unwrap_or(prefs::PublisherStyles::Keep),
+            vertical_layout: settings
+                .get(prefs::VERTICAL_LAYOUT)
+                .unwrap_or(prefs::VerticalLayout::Keep),
+            ruby: settings.get(prefs::RUBY).unwrap_or(prefs::RubyMode::Show),
+            ruby_readings: settings.get(prefs::RUBY_READINGS).unwrap_or(false),
+            chinese_script: settings.get(prefs::CHINESE_SCRIPT),
+            reading_emphasis: settings.get(prefs::READING_EMPHASIS),
+            scripts: settings
+                .get(prefs::SCRIPTS)
+                .unwrap_or(prefs::ScriptPolicy::Strip),
+            downscale_images: settings.get(prefs::DOWNSCALE_IMAGES).unwrap_or(false),
+            hyphenation: settings.get(prefs::HYPHENATION).unwrap_or(false),
+            continuous_scroll,
+        };
+        (title, options)
     };
+    // the state is unlocked, as menus and stores may call back into handlers locking it
 
     // set window title with book title
-    let title = match opened.pb.title() {
-        Some(item) => format!("{} - OgierEPUB", item.value),
-        None => String::from("OgierEPUB"),
-    };
     let _ = window.set_title(&title);
 
     // update menu to complete
     let prefs_store = window.store(PREFS_STORE)?;
-    menus::update(&window, &prefs_store)?;
+    menus::update(window, &prefs_store)?;
+    menus::set_book_options(window, &options)?;
 
     Ok(true)
 }
@@ -174,21 +263,41 @@ fn book_open(state: &mut MutexGuard<'_, AppData>, path: &PathBuf) -> Result<(),
 
     // open file
     let file = File::open(path)?;
-    let (pb, archive) = Epub::open(BufReader::new(file))?;
+    let (pb, mut archive) = Epub::open(BufReader::new(file))?;
 
     let hash = compute_file_hash(&path)?;
+    let vertical = detect_vertical(&pb, &mut archive);
     state.opened_pub = Some(AppOpenedEpub {
         path: path.clone(),
         pb,
         archive,
         hash,
+        vertical,
+        blocked_remote: BTreeSet::new(),
+        splits: HashMap::new(),
     });
+    state.settings = None;
 
     log::debug!("book opened and info extracted");
 
     Ok(())
 }
 
+/// Whether the book is written vertically, by the `primary-writing-mode` metadata,
+/// or else by the writing mode its stylesheets set on the root element.
+fn detect_vertical(pb: &Epub, archive: &mut EpubArchive) -> bool {
+    if let Some(mode) = pb.primary_writing_mode() {
+        return mode.starts_with("vertical");
+    }
+    pb.resources_of_type(MIMETYPE_CSS).any(|url| {
+        let mut css = String::new();
+        archive
+            .get_reader(url)
+            .is_ok_and(|mut reader| reader.read_to_string(&mut css).is_ok())
+            && alter::is_vertical_css(&css)
+    })
+}
+
 fn filewise_styles_path(
     app_handle: &AppHandle,
     state: &MutexGuard<'_, AppData>,
@@ -199,6 +308,220 @@ fn filewise_styles_path(
     Ok(path)
 }
 
+/// Path of the user stylesheet of the opened book if `filewise`, or else of all books.
+fn user_styles_path(
+    app_handle: &AppHandle,
+    state: &MutexGuard<'_, AppData>,
+    filewise: bool,
+) -> Result<PathBuf, AnyErr> {
+    if !filewise {
+        return Ok(resolve_store_path(app_handle, USER_STYLES)?);
+    }
+    let mut path = filewise_styles_path(app_handle, state)?;
+    path.set_extension("css");
+    Ok(path)
+}
+
+/// The global user stylesheet, then that of the opened book.
+fn read_user_styles(app_handle: &AppHandle, state: &MutexGuard<'_, AppData>) -> String {
+    [false, true]
+        .into_iter()
+        .filter_map(|filewise| user_styles_path(app_handle, state, filewise).ok())
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn read_filewise_values(path: &PathBuf) -> serde_json::Map<String, serde_json::Value> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Set `key` in the filewise styles file of the opened book, keeping other values.
+fn set_filewise_value(
+    app_handle: &AppHandle,
+    state: &mut MutexGuard<'_, AppData>,
+    key: &str,
+    value: serde_json::Value,
+) -> Result<(), AnyErr> {
+    let path = filewise_styles_path(app_handle, state)?;
+    let mut values = read_filewise_values(&path);
+    values.insert(key.into(), value);
+    let content = serde_json::to_string_pretty(&values).map_err(|_| AnyErr::Unknown)?;
+    std::fs::write(path, content)?;
+    // the modification time may not tell within the same tick
+    state.settings = None;
+    Ok(())
+}
+
+/// Settings for serving the opened book, see `prefs::Settings`.
+///
+/// Cached until the filewise styles file, or the preferences store, changes. Users may edit
+/// the former by hand.
+fn content_settings(
+    app_handle: &AppHandle,
+    state: &mut MutexGuard<'_, AppData>,
+) -> Result<prefs::Settings, AnyErr> {
+    let path = filewise_styles_path(app_handle, state)?;
+    let modified = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let prefs_changed = PREFS_CHANGED.swap(false, Ordering::Relaxed);
+    if let Some(cached) = &state.settings
+        && cached.modified == modified
+        && !prefs_changed
+    {
+        return Ok(cached.settings.clone());
+    }
+
+    let filewise = read_filewise_values(&path);
+    let app = app_handle
+        .store(PREFS_STORE)?
+        .entries()
+        .into_iter()
+        .collect();
+    let settings = prefs::Settings::new(filewise, app);
+    state.settings = Some(CachedSettings {
+        modified,
+        settings: settings.clone(),
+    });
+    Ok(settings)
+}
+
+/// Whether the spine is read as one scrolling document, as set for the book or else as the
+/// book asks.
+fn continuous_scroll(settings: &prefs::Settings, pb: &Epub) -> bool {
+    settings
+        .get(prefs::CONTINUOUS_SCROLL)
+        .unwrap_or_else(|| pb.rendition_flow() == Some("scrolled-continuous"))
+}
+
+/// Whether the resource at `url` is marked `scripted` in the manifest.
+fn is_scripted(opened: &AppOpenedEpub, url: &Url) -> bool {
+    opened
+        .pb
+        .resource(url)
+        .ok()
+        .and_then(|info| info.properties.as_ref())
+        .is_some_and(|properties| properties.has("scripted"))
+}
+
+/// Whether the content document at `url` is shown in the sandboxed frame with its scripts,
+/// see `prefs::ScriptPolicy`. Such documents are served whole.
+fn is_sandboxed(opened: &AppOpenedEpub, url: &Url, settings: &prefs::Settings) -> bool {
+    is_scripted(opened, url) && settings.get(prefs::SCRIPTS) == Some(prefs::ScriptPolicy::Sandbox)
+}
+
+/// What content transforms know about the opened book, serving the resource at `url`.
+fn content_context(opened: &AppOpenedEpub, url: &Url, standalone: bool) -> alter::Context {
+    alter::Context {
+        book_lang: opened.pb.language().map(String::from),
+        doc_url: Some(url.clone()),
+        scripted: is_scripted(opened, url),
+        standalone,
+        blocked_remote: Default::default(),
+        off_spine: opened
+            .pb
+            .resources_off_spine()
+            .map(|(url, media_type)| (url.clone(), String::from(media_type)))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Reads the resource at `url` whole.
+fn read_resource(archive: &mut EpubArchive, url: &Url) -> Result<Vec<u8>, AnyErr> {
+    let mut reader = archive.get_reader(url)?;
+    let mut buf = Vec::with_capacity(reader.size() as usize);
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// How the content document at `url` splits into parts, planned once per opened book, or
+/// `None` if it is served whole.
+fn document_split<'a>(
+    opened: &'a mut AppOpenedEpub,
+    url: &Url,
+) -> Result<Option<&'a alter::DocumentSplit>, AnyErr> {
+    if !opened.splits.contains_key(url) {
+        let is_xhtml = opened.pb.resource(url)?.media_type == MIMETYPE_XHTML;
+        let split =
+            if is_xhtml && alter::DocumentSplit::is_large(opened.archive.get_reader(url)?.size()) {
+                alter::DocumentSplit::plan(&read_resource(&mut opened.archive, url)?)?
+            } else {
+                None
+            };
+        opened.splits.insert(url.clone(), split);
+    }
+    Ok(opened.splits[url].as_ref())
+}
+
+/// Reads part `index` of the content document at `url`, and the path of its first element,
+/// if the document is split.
+fn read_document_part(
+    opened: &mut AppOpenedEpub,
+    url: &Url,
+    index: usize,
+) -> Result<Option<PartAndPath>, AnyErr> {
+    if document_split(opened, url)?.is_none() {
+        return Ok(None);
+    }
+    let doc = read_resource(&mut opened.archive, url)?;
+    let split = opened.splits[url].as_ref().ok_or(AnyErr::Unknown)?;
+    let part = split.part(&doc, index).ok_or(AnyErr::EpubContent)?;
+    Ok(Some((part, split.path(index).to_vec())))
+}
+
+/// A part of a content document, see `alter::DocumentSplit`. Documents served whole are
+/// their only part.
+#[derive(serde::Serialize)]
+struct DocumentPart {
+    /// URL of the part, with the fragment asked for.
+    url: Url,
+    index: usize,
+    count: usize,
+}
+
+/// Finds the part of the document that `url` points to: that of the element or text
+/// position in the fragment if any, or else the part in the URL, or else the first.
+fn locate_document_part(
+    opened: &mut AppOpenedEpub,
+    url: &Url,
+    settings: &prefs::Settings,
+) -> Result<DocumentPart, AnyErr> {
+    let (doc_url, index) = alter::part_of(url).unwrap_or_else(|| (url.clone(), 0));
+    let mut whole_url = doc_url.clone();
+    whole_url.set_fragment(None);
+    let split = if is_sandboxed(opened, &whole_url, settings) {
+        None
+    } else {
+        document_split(opened, &whole_url)?
+    };
+    let Some(split) = split else {
+        return Ok(DocumentPart {
+            url: doc_url,
+            index: 0,
+            count: 1,
+        });
+    };
+
+    let index = match alter::TextPosition::from_url(&doc_url) {
+        Some(position) => split.part_of_anchor(&position.anchor),
+        None => doc_url
+            .fragment()
+            .and_then(|id| split.part_of_id(id))
+            .unwrap_or(index),
+    };
+    let index = index.min(split.count() - 1);
+    Ok(DocumentPart {
+        url: alter::part_url(&doc_url, index),
+        index,
+        count: split.count(),
+    })
+}
+
 #[tauri::command]
 fn get_filewise_styles(app_handle: AppHandle, state: State<AppState>) -> Result<String, AnyErr> {
     let path = {
@@ -214,10 +537,56 @@ fn set_filewise_styles(
     state: State<AppState>,
     content: String,
 ) -> Result<(), AnyErr> {
+    let mut state_guard = state.lock().unwrap();
+    let path = filewise_styles_path(&app_handle, &state_guard)?;
+
+    // The file also holds values that the front-end doesn't send, e.g., content transforms.
+    // Keep them.
+    let content = match serde_json::from_str(&content) {
+        Ok(serde_json::Value::Object(styles)) => {
+            let mut values = read_filewise_values(&path);
+            values.extend(styles);
+            serde_json::to_string_pretty(&values).unwrap_or(content)
+        }
+        _ => content,
+    };
+    std::fs::write(path, content)?;
+    // the modification time may not tell within the same tick
+    state_guard.settings = None;
+    Ok(())
+}
+
+/// Front-end invokes this to read the user stylesheet of the opened book if `filewise`, or
+/// else of all books.
+#[tauri::command]
+fn get_user_styles(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    filewise: bool,
+) -> Result<String, AnyErr> {
     let path = {
         let state_guard = state.lock().unwrap();
-        filewise_styles_path(&app_handle, &state_guard)?
+        user_styles_path(&app_handle, &state_guard, filewise)?
+    };
+    Ok(std::fs::read_to_string(path).unwrap_or_default())
+}
+
+/// Front-end invokes this to write the user stylesheet of the opened book if `filewise`,
+/// or else of all books. Applies to content documents served from then on.
+#[tauri::command]
+fn set_user_styles(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    filewise: bool,
+    content: String,
+) -> Result<(), AnyErr> {
+    let path = {
+        let state_guard = state.lock().unwrap();
+        user_styles_path(&app_handle, &state_guard, filewise)?
     };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
     std::fs::write(path, content)?;
     Ok(())
 }
@@ -232,8 +601,22 @@ fn set_reading_position(
     let progress_store = window.store(PROGRESS_STORE)?;
 
     let state_guard = state.lock().unwrap();
-    let hash = &state_guard.opened_pub.as_ref().unwrap().hash;
-    progress_store.set(hash.as_str(), serde_json::json!([url, percentage]));
+    let opened = state_guard.opened_pub.as_ref().unwrap();
+    // positions in parts are kept as in the whole document, whose anchors parts share
+    let (url, percentage) = match alter::part_of(&url) {
+        Some((doc_url, index)) => {
+            let mut whole_url = doc_url.clone();
+            whole_url.set_fragment(None);
+            let count = opened
+                .splits
+                .get(&whole_url)
+                .and_then(Option::as_ref)
+                .map_or(1, |split| split.count());
+            (doc_url, (index as f64 + percentage) / count as f64)
+        }
+        None => (url, percentage),
+    };
+    progress_store.set(opened.hash.as_str(), serde_json::json!([url, percentage]));
     Ok(())
 }
 
@@ -245,24 +628,43 @@ fn get_reading_position(
     log::debug!("command get_reading_position");
     let progress_store = window.store(PROGRESS_STORE)?;
 
-    let state_guard = state.lock().unwrap();
-    let opened = state_guard.opened_pub.as_ref().unwrap();
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(window.app_handle(), &mut state_guard)?;
+    let opened = state_guard.opened_pub.as_mut().unwrap();
 
     let Some(val) = progress_store.get(opened.hash) else {
         return Ok(None);
     };
-    let Ok(val) = serde_json::from_value::<(Url, Option<f64>)>(val) else {
+    let Ok((mut url, percentage)) = serde_json::from_value::<(Url, Option<f64>)>(val) else {
         log::warn!(" ignore invalid value in progress file");
         return Ok(None);
     };
 
     // validate before responding
-    if !opened.pb.spine().contains(&val.0) {
+    let position = alter::TextPosition::from_url(&url);
+    if position.is_none() && url.fragment().is_some() {
+        log::warn!(" ignore invalid text position found in progress file");
+        url.set_fragment(None);
+    }
+    let doc_url = position
+        .as_ref()
+        .map_or_else(|| url.clone(), |position| position.url.clone());
+    if !opened.pb.spine().contains(&doc_url) {
         log::warn!(" ignore unrecognized URL found in progress file");
         return Ok(None);
     }
 
-    Ok(Some(val))
+    // the part of a split document with the position, or else at the percentage
+    let part = locate_document_part(opened, &url, &settings)?;
+    if part.count > 1 && position.is_none() {
+        let whole = percentage.unwrap_or_default() * part.count as f64;
+        let index = (whole.floor() as usize).min(part.count - 1);
+        return Ok(Some((
+            alter::part_url(&url, index),
+            Some(whole - index as f64),
+        )));
+    }
+    Ok(Some((part.url, percentage)))
 }
 
 fn open_epub_impl(
@@ -270,11 +672,8 @@ fn open_epub_impl(
     state: State<AppState>,
     path: PathBuf,
 ) -> Result<AboutPub, AnyErr> {
-    {
-        let mut state_guard = state.lock().unwrap();
-        book_open(&mut state_guard, &path)?;
-        post_book_open(&window, &mut state_guard)?;
-    }
+    book_open(&mut state.lock().unwrap(), &path)?;
+    post_book_open(&window, &state)?;
     let state_guard = state.lock().unwrap();
     let opened = state_guard.opened_pub.as_ref().unwrap();
     AboutPub::try_from(opened)
@@ -304,13 +703,10 @@ fn reload_book(window: Window, state: State<AppState>) -> Result<AboutPub, AnyEr
 #[tauri::command]
 fn open_epub_if_loaded(window: Window, state: State<AppState>) -> Result<Option<AboutPub>, AnyErr> {
     log::debug!("command open_epub_if_loaded");
-    {
-        let mut state_guard = state.lock().unwrap();
-        let exists = post_book_open(&window, &mut state_guard)?;
-        if !exists {
-            log::debug!("no book was loaded");
-            return Ok(None);
-        }
+    let exists = post_book_open(&window, &state)?;
+    if !exists {
+        log::debug!("no book was loaded");
+        return Ok(None);
     }
 
     let state_guard = state.lock().unwrap();
@@ -330,22 +726,34 @@ fn serve_epub_request_body<R: Read>(
     mut zipfile: zip::read::ZipFile<'_, R>,
     media_type: &str,
     is_content_doc: bool,
+    pipeline: &mut Pipeline,
+    downscale: Option<&images::Downscale>,
 ) -> Result<Vec<u8>, AnyErr> {
     if is_content_doc {
-        if media_type == MIMETYPE_XHTML {
-            return alter_xhtml(zipfile);
-        } else if media_type == MIMETYPE_SVG {
-            // original
+        if media_type == MIMETYPE_XHTML || media_type == MIMETYPE_SVG {
+            return alter_xhtml(zipfile, pipeline);
+        } else if convert::is_convertible(media_type) {
+            let xhtml = convert::to_xhtml(zipfile, media_type)?;
+            return alter_xhtml(xhtml.as_slice(), pipeline);
         } else {
             return Err(AnyErr::EpubContent);
         }
     } else if media_type == MIMETYPE_CSS {
-        return alter_css(zipfile);
+        return alter_css(zipfile, pipeline);
+    } else if media_type == MIMETYPE_SVG {
+        return alter_xhtml(zipfile, pipeline);
     }
 
     let mut buf = Vec::new();
     buf.reserve(zipfile.size() as usize);
     zipfile.read_to_end(&mut buf)?;
+    if let Some(downscale) = downscale {
+        match downscale.apply(&buf, media_type) {
+            Ok(Some(downscaled)) => return Ok(downscaled),
+            Ok(None) => {}
+            Err(e) => log::warn!("could not downscale image: {e}"),
+        }
+    }
     Ok(buf)
 }
 
@@ -353,9 +761,24 @@ fn serve_epub_request(
     app_handle: &AppHandle,
     uri: &Url,
     is_content_doc: bool,
+    original: bool,
+    part: Option<usize>,
+    stitched: bool,
 ) -> Result<BytesAndMediaType, http::StatusCode> {
     let state = app_handle.state::<AppState>();
     let mut state_guard = state.lock().unwrap();
+
+    let settings = content_settings(app_handle, &mut state_guard)
+        .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    // the frame requests the document itself, which is to be altered too
+    let sandboxed =
+        !stitched && is_sandboxed(state_guard.opened_pub.as_ref().unwrap(), uri, &settings);
+    let user_css = if is_content_doc || sandboxed {
+        read_user_styles(app_handle, &state_guard)
+    } else {
+        String::new()
+    };
+
     let opened = state_guard.opened_pub.as_mut().unwrap();
 
     let media_type = {
@@ -366,22 +789,550 @@ fn serve_epub_request(
         info.media_type.clone()
     };
 
-    let reader = opened
-        .archive
-        .get_reader(uri)
-        .map_err(|e| match e.narrow() {
-            Ok(epub::UrlNotFoundErr) => http::StatusCode::NOT_FOUND,
-            _ => http::StatusCode::INTERNAL_SERVER_ERROR,
-        })?;
+    // SVG images are shown apart from the reader
+    let standalone = !is_content_doc && media_type == MIMETYPE_SVG;
+    let mut context = alter::Context {
+        user_css,
+        ..content_context(opened, uri, standalone)
+    };
+
+    // stitched documents are served whole, their IDs apart from those of the others
+    if is_content_doc && stitched {
+        let index = opened.pb.spine().iter().position(|url| url == uri);
+        context.stitch(index.unwrap_or_default());
+    }
 
-    let body =
-        serve_epub_request_body(reader, &media_type, is_content_doc).map_err(|e| match e {
+    // very large documents are served in parts, anchored as in the whole document
+    let mut part_doc = None;
+    if is_content_doc && !sandboxed && !stitched {
+        let index = part.unwrap_or_default();
+        if let Some((doc, path)) = read_document_part(opened, uri, index).map_err(|e| match e {
             AnyErr::EpubUrlNotFound(_) => http::StatusCode::NOT_FOUND,
-            AnyErr::EpubContent => http::StatusCode::BAD_REQUEST,
             _ => http::StatusCode::INTERNAL_SERVER_ERROR,
-        })?;
+        })? {
+            context.part_path = path;
+            part_doc = Some(doc);
+        }
+    }
+
+    let mut pipeline = Pipeline::new(&settings, &context);
+    let is_content_doc = is_content_doc || sandboxed;
+
+    let downscale =
+        if !original && !is_content_doc && settings.get(prefs::DOWNSCALE_IMAGES) == Some(true) {
+            let cache_dir = app_handle
+                .path()
+                .app_cache_dir()
+                .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?
+                .join(IMAGE_CACHE_DIR)
+                .join(opened.hash.as_str());
+            let max_dimension = settings
+                .get(prefs::IMAGE_MAX_DIMENSION)
+                .unwrap_or(images::DEFAULT_MAX_DIMENSION);
+            Some(images::Downscale::new(cache_dir, uri, max_dimension))
+        } else {
+            None
+        };
+
+    let body = if let Some(doc) = part_doc {
+        alter_xhtml(doc.as_slice(), &mut pipeline)
+    } else {
+        let reader = opened
+            .archive
+            .get_reader(uri)
+            .map_err(|e| match e.narrow() {
+                Ok(epub::UrlNotFoundErr) => http::StatusCode::NOT_FOUND,
+                _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+            })?;
+
+        serve_epub_request_body(
+            reader,
+            &media_type,
+            is_content_doc,
+            &mut pipeline,
+            downscale.as_ref(),
+        )
+    }
+    .map_err(|e| match e {
+        AnyErr::EpubUrlNotFound(_) => http::StatusCode::NOT_FOUND,
+        AnyErr::EpubContent => http::StatusCode::BAD_REQUEST,
+        _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    opened.blocked_remote.extend(context.blocked_remote.take());
+
+    // converted for the reader to parse as XHTML
+    let media_type = if is_content_doc && convert::is_convertible(&media_type) {
+        String::from(MIMETYPE_XHTML)
+    } else {
+        media_type
+    };
+
+    Ok(BytesAndMediaType(body, media_type, sandboxed))
+}
+
+/// Serve the document to read the spine continuously, with the stylesheets of all content
+/// documents. The reader fills it with the documents served stitched.
+fn serve_continuous_request(app_handle: &AppHandle) -> Result<BytesAndMediaType, http::StatusCode> {
+    let state = app_handle.state::<AppState>();
+    let mut state_guard = state.lock().unwrap();
+
+    let settings = content_settings(app_handle, &mut state_guard)
+        .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let user_css = read_user_styles(app_handle, &state_guard);
+
+    let opened = state_guard.opened_pub.as_mut().unwrap();
+    let mut chapters = Vec::new();
+    for url in opened.pb.spine() {
+        let is_xhtml = opened
+            .pb
+            .resource(url)
+            .is_ok_and(|info| info.media_type == MIMETYPE_XHTML);
+        // converted documents have no stylesheets of their own
+        let head = match opened.archive.get_reader(url) {
+            Ok(reader) if is_xhtml => alter::HeadStyles::read(BufReader::new(reader), url)
+                .unwrap_or_else(|_| {
+                    log::warn!("could not read the head of {url}");
+                    Default::default()
+                }),
+            _ => Default::default(),
+        };
+        chapters.push((url, head));
+    }
+    let doc = alter::continuous_document(chapters, opened.pb.language());
+
+    let mut uri = Url::parse("epub:/").map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    uri.set_query(Some(CONTINUOUS_QUERY));
+    let context = alter::Context {
+        user_css,
+        ..content_context(opened, &uri, false)
+    };
+    let mut pipeline = Pipeline::new(&settings, &context);
+    let body = alter_xhtml(doc.as_bytes(), &mut pipeline)
+        .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    opened.blocked_remote.extend(context.blocked_remote.take());
+
+    Ok(BytesAndMediaType(body, String::from(MIMETYPE_XHTML), false))
+}
+
+/// Serve a resource on the web through the proxy, if its host is allowed for the book.
+fn serve_remote_request(
+    app_handle: &AppHandle,
+    url: &Url,
+) -> Result<BytesAndMediaType, http::StatusCode> {
+    let allowed = {
+        let state = app_handle.state::<AppState>();
+        let mut state_guard = state.lock().unwrap();
+        let settings = content_settings(app_handle, &mut state_guard)
+            .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        let allowed = alter::allowed_hosts(&settings);
+        if !alter::is_allowed(&allowed, url) {
+            let opened = state_guard.opened_pub.as_mut().unwrap();
+            opened.blocked_remote.insert(url.clone());
+            return Err(http::StatusCode::FORBIDDEN);
+        }
+        allowed
+    };
+
+    let cache_dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .join(REMOTE_CACHE_DIR);
+    let (body, media_type) = proxy::fetch(&cache_dir, url, &allowed).map_err(|e| {
+        log::warn!("could not fetch {url}: {e}");
+        http::StatusCode::BAD_GATEWAY
+    })?;
+    // whatever the site says it is, only what a book would load from it is served
+    let Some(media_type) = proxy::served_media_type(&media_type) else {
+        log::warn!("not serving {url} of type {media_type}");
+        return Err(http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    };
+    if media_type != MIMETYPE_CSS && media_type != MIMETYPE_SVG {
+        return Ok(BytesAndMediaType(body, media_type, false));
+    }
+
+    // altered as those in the book, with URLs resolved against the site
+    let state = app_handle.state::<AppState>();
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(app_handle, &mut state_guard)
+        .map_err(|_| http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let opened = state_guard
+        .opened_pub
+        .as_mut()
+        .ok_or(http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let context = content_context(opened, url, media_type == MIMETYPE_SVG);
+    let mut pipeline = Pipeline::new(&settings, &context);
+    let body = if media_type == MIMETYPE_CSS {
+        alter_css(body.as_slice(), &mut pipeline)
+    } else {
+        alter_xhtml(body.as_slice(), &mut pipeline)
+    }
+    .map_err(|_| http::StatusCode::BAD_GATEWAY)?;
+    opened.blocked_remote.extend(context.blocked_remote.take());
+
+    Ok(BytesAndMediaType(body, media_type, false))
+}
+
+fn epub_response(result: Result<BytesAndMediaType, http::StatusCode>) -> http::Response<Vec<u8>> {
+    match result {
+        Ok(BytesAndMediaType(body, mime, sandboxed)) => {
+            let mut builder = http::Response::builder()
+                .status(http::StatusCode::OK)
+                .header(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .header(http::header::CONTENT_TYPE, mime);
+            if sandboxed {
+                builder = builder
+                    .header(http::header::CONTENT_SECURITY_POLICY, SANDBOX_CSP)
+                    .header(
+                        http::header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                        SANDBOXED_HEADER,
+                    )
+                    .header(SANDBOXED_HEADER, "1");
+            }
+            builder.body(body).unwrap()
+        }
+        Err(code) => http::Response::builder()
+            .status(code)
+            .body(Vec::default())
+            .unwrap(),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Diagnostics {
+    /// Resources on the web listed in the manifest.
+    #[serde(rename(serialize = "remoteDeclared"))]
+    remote_declared: Vec<Url>,
+    #[serde(rename(serialize = "remoteBlocked"))]
+    remote_blocked: Vec<Url>,
+}
+
+/// Front-end invokes this to show problems found in the opened book so far.
+#[tauri::command]
+fn get_diagnostics(state: State<AppState>) -> Result<Diagnostics, AnyErr> {
+    let state_guard = state.lock().unwrap();
+    let opened = state_guard.opened_pub.as_ref().ok_or(AnyErr::Unknown)?;
+    let mut remote_declared: Vec<Url> = opened.pb.remote_resources().cloned().collect();
+    remote_declared.sort();
+    Ok(Diagnostics {
+        remote_declared,
+        remote_blocked: opened.blocked_remote.iter().cloned().collect(),
+    })
+}
+
+/// Front-end invokes this to let the opened book load resources from `host` through the
+/// proxy.
+#[tauri::command]
+fn allow_remote_host(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    host: String,
+) -> Result<(), AnyErr> {
+    log::debug!("command allow_remote_host[{host}]");
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(&app_handle, &mut state_guard)?;
+    let mut allowlist: Vec<String> = settings.get(prefs::REMOTE_ALLOWLIST).unwrap_or_default();
+    if !allowlist.iter().any(|h| h.eq_ignore_ascii_case(&host)) {
+        allowlist.push(host.clone());
+    }
+    set_filewise_value(
+        &app_handle,
+        &mut state_guard,
+        prefs::REMOTE_ALLOWLIST,
+        serde_json::json!(allowlist),
+    )?;
+    set_filewise_value(
+        &app_handle,
+        &mut state_guard,
+        prefs::REMOTE_RESOURCES,
+        serde_json::json!(prefs::RemotePolicy::Proxy),
+    )?;
+
+    if let Some(opened) = state_guard.opened_pub.as_mut() {
+        opened.blocked_remote.retain(|url| {
+            !url.host_str()
+                .is_some_and(|h| h.eq_ignore_ascii_case(&host))
+        });
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct PublisherFonts {
+    /// Font families in `font-family` declarations.
+    families: Vec<String>,
+    /// Fonts of `@font-face` rules.
+    embedded: Vec<alter::EmbeddedFont>,
+}
+
+/// Front-end invokes this to list the font families used, and the fonts embedded, by the
+/// publisher across the stylesheets and content documents of the opened book.
+#[tauri::command]
+fn list_publisher_fonts(
+    app_handle: AppHandle,
+    state: State<AppState>,
+) -> Result<PublisherFonts, AnyErr> {
+    log::debug!("command list_publisher_fonts");
+    let mut state_guard = state.lock().unwrap();
+    let disabled: Vec<Url> = content_settings(&app_handle, &mut state_guard)?
+        .get(prefs::DISABLED_FONTS)
+        .unwrap_or_default();
+    let opened = state_guard.opened_pub.as_mut().ok_or(AnyErr::Unknown)?;
+    let mut fonts = alter::PublisherFonts::default();
+    for url in opened.pb.resources_of_type(MIMETYPE_CSS) {
+        let Ok(reader) = opened.archive.get_reader(url) else {
+            log::warn!("could not read {url} to list fonts");
+            continue;
+        };
+        if let Err(e) = fonts.add_css(reader, url) {
+            log::warn!("could not list fonts of {url}: {e}");
+        }
+    }
+    for url in opened.pb.spine() {
+        let Ok(resource) = opened.pb.resource(url) else {
+            log::warn!("could not find {url} to list fonts");
+            continue;
+        };
+        if resource.media_type != MIMETYPE_XHTML {
+            continue;
+        }
+        let Ok(reader) = opened.archive.get_reader(url) else {
+            log::warn!("could not read {url} to list fonts");
+            continue;
+        };
+        if let Err(e) = fonts.add_xhtml(reader, url) {
+            log::warn!("could not list fonts of {url}: {e}");
+        }
+    }
+
+    let mut embedded = fonts.embedded();
+    for font in embedded.iter_mut() {
+        font.size = opened.archive.get_reader(&font.url).ok().map(|r| r.size());
+        font.disabled = disabled.contains(&font.url);
+    }
+    Ok(PublisherFonts {
+        families: fonts.families(),
+        embedded,
+    })
+}
+
+/// Front-end invokes this to drop, or restore, the `@font-face` rules of the font at `url`
+/// in the opened book, see `prefs::DISABLED_FONTS`.
+#[tauri::command]
+fn set_font_disabled(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    url: Url,
+    disabled: bool,
+) -> Result<(), AnyErr> {
+    log::debug!("command set_font_disabled[{url}] {disabled}");
+    let mut state_guard = state.lock().unwrap();
+    let path = filewise_styles_path(&app_handle, &state_guard)?;
+    let mut urls: Vec<Url> = read_filewise_values(&path)
+        .remove(prefs::DISABLED_FONTS)
+        .and_then(|urls| serde_json::from_value(urls).ok())
+        .unwrap_or_default();
+    urls.retain(|u| *u != url);
+    if disabled {
+        urls.push(url);
+    }
+    set_filewise_value(
+        &app_handle,
+        &mut state_guard,
+        prefs::DISABLED_FONTS,
+        serde_json::json!(urls),
+    )
+}
+
+/// Front-end invokes this to list the font families installed, to substitute for
+/// publisher fonts.
+#[tauri::command]
+fn list_system_fonts() -> Result<Vec<String>, AnyErr> {
+    log::debug!("command list_system_fonts");
+    fonts::system_families()
+}
+
+/// Front-end invokes this to get the local font families to use for publisher font
+/// families, see `prefs::FONT_SUBSTITUTE`. Per-book entries apply if a book is opened.
+#[tauri::command]
+fn get_font_substitutes(
+    app_handle: AppHandle,
+    state: State<AppState>,
+) -> Result<BTreeMap<String, String>, AnyErr> {
+    let state_guard = state.lock().unwrap();
+    let filewise = filewise_styles_path(&app_handle, &state_guard)
+        .map(|path| read_filewise_values(&path))
+        .unwrap_or_default();
+    let app = app_handle
+        .store(PREFS_STORE)?
+        .entries()
+        .into_iter()
+        .collect();
+    Ok(prefs::Settings::new(filewise, app).get_merged(prefs::FONT_SUBSTITUTE))
+}
+
+/// Front-end invokes this to use the local font family `local` for the publisher font
+/// `family`, for the opened book if `filewise`, or else for all books. `None` removes the
+/// substitute.
+#[tauri::command]
+fn set_font_substitute(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    filewise: bool,
+    family: String,
+    local: Option<String>,
+) -> Result<(), AnyErr> {
+    log::debug!("command set_font_substitute[{family}]");
+    let family = family.to_lowercase();
+    let update = |substitutes: Option<serde_json::Value>| {
+        let mut substitutes = match substitutes {
+            Some(serde_json::Value::Object(substitutes)) => substitutes,
+            _ => serde_json::Map::new(),
+        };
+        match &local {
+            Some(local) => substitutes.insert(family.clone(), serde_json::json!(local)),
+            None => substitutes.remove(&family),
+        };
+        serde_json::Value::Object(substitutes)
+    };
+
+    if !filewise {
+        let prefs_store = app_handle.store(PREFS_STORE)?;
+        prefs_store.set(
+            prefs::FONT_SUBSTITUTE,
+            update(prefs_store.get(prefs::FONT_SUBSTITUTE)),
+        );
+        return Ok(());
+    }
+    let mut state_guard = state.lock().unwrap();
+    let path = filewise_styles_path(&app_handle, &state_guard)?;
+    let substitutes = read_filewise_values(&path).remove(prefs::FONT_SUBSTITUTE);
+    set_filewise_value(
+        &app_handle,
+        &mut state_guard,
+        prefs::FONT_SUBSTITUTE,
+        update(substitutes),
+    )
+}
+
+/// Front-end invokes this to find the part of the document that `url` points to, to read
+/// there, see `alter::DocumentSplit`.
+#[tauri::command]
+fn get_document_part(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    url: Url,
+) -> Result<DocumentPart, AnyErr> {
+    log::debug!("command get_document_part[{url}]");
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(&app_handle, &mut state_guard)?;
+    let opened = state_guard.opened_pub.as_mut().ok_or(AnyErr::Unknown)?;
+    locate_document_part(opened, &url, &settings)
+}
+
+/// Front-end invokes this to know whether to read the spine as one scrolling document.
+#[tauri::command]
+fn get_continuous_scroll(app_handle: AppHandle, state: State<AppState>) -> Result<bool, AnyErr> {
+    log::debug!("command get_continuous_scroll");
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(&app_handle, &mut state_guard)?;
+    let opened = state_guard.opened_pub.as_ref().ok_or(AnyErr::Unknown)?;
+    Ok(continuous_scroll(&settings, &opened.pb))
+}
+
+/// Front-end invokes this to show the note at `url` in a pop-up.
+///
+/// Returns the note as an XHTML fragment, altered like the content served, or `None` if
+/// `url` points to no element. The document may be anywhere in the book, even off the spine.
+#[tauri::command]
+fn get_note(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    url: Url,
+) -> Result<Option<String>, AnyErr> {
+    log::debug!("command get_note[{url}]");
+    let Some(id) = url.fragment() else {
+        return Ok(None);
+    };
+    let mut doc_url = url.clone();
+    doc_url.set_fragment(None);
+    doc_url.set_query(None);
+
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(&app_handle, &mut state_guard)?;
+    let opened = state_guard.opened_pub.as_mut().ok_or(AnyErr::Unknown)?;
+    if opened.pb.resource(&doc_url)?.media_type != MIMETYPE_XHTML {
+        return Ok(None);
+    }
+
+    let context = content_context(opened, &doc_url, false);
+    let mut pipeline = Pipeline::new(&settings, &context);
+    let reader = opened.archive.get_reader(&doc_url)?;
+    let xhtml = alter_xhtml(reader, &mut pipeline)?;
+    extract_note(&xhtml, id)
+}
+
+/// Front-end invokes this to show how many times each replace rule matches in the document
+/// at `url`, see `prefs::REPLACE_RULES`.
+#[tauri::command]
+fn preview_rules(
+    app_handle: AppHandle,
+    state: State<AppState>,
+    url: Url,
+) -> Result<Vec<alter::RuleMatches>, AnyErr> {
+    log::debug!("command preview_rules[{url}]");
+    let mut doc_url = url;
+    doc_url.set_fragment(None);
+    doc_url.set_query(None);
+
+    let mut state_guard = state.lock().unwrap();
+    let settings = content_settings(&app_handle, &mut state_guard)?;
+    let opened = state_guard.opened_pub.as_mut().ok_or(AnyErr::Unknown)?;
+    let media_type = opened.pb.resource(&doc_url)?.media_type.clone();
+    let reader = opened.archive.get_reader(&doc_url)?;
+    if convert::is_convertible(&media_type) {
+        let xhtml = convert::to_xhtml(reader, &media_type)?;
+        return alter::preview_rules(xhtml.as_slice(), &settings);
+    }
+    alter::preview_rules(reader, &settings)
+}
 
-    Ok(BytesAndMediaType(body, media_type))
+/// Front-end invokes this to open a link to an external site in the system browser, after
+/// asking the user unless turned off by `prefs::CONFIRM_EXTERNAL_LINKS`.
+#[tauri::command]
+fn open_external_link(app_handle: AppHandle, url: Url) -> Result<(), AnyErr> {
+    log::debug!("command open_external_link[{url}]");
+    if !matches!(url.scheme(), "http" | "https" | "mailto" | "tel" | "ftp") {
+        return Err(AnyErr::Unknown);
+    }
+
+    let confirm = app_handle
+        .store(PREFS_STORE)?
+        .get(prefs::CONFIRM_EXTERNAL_LINKS)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(true);
+    if !confirm {
+        app_handle.opener().open_url(url.as_str(), None::<&str>)?;
+        return Ok(());
+    }
+
+    let app = app_handle.clone();
+    app_handle
+        .dialog()
+        .message(format!("Open {url} using system default application"))
+        .title("Confirm")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            String::from("Open"),
+            String::from("Cancel"),
+        ))
+        .show(move |confirmed| {
+            if confirmed && let Err(e) = app.opener().open_url(url.as_str(), None::<&str>) {
+                log::error!("Could not open {url}: {e}");
+            }
+        });
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -397,6 +1348,27 @@ pub fn run(filepath: Option<PathBuf>) {
         .on_menu_event(|handle, event| menus::handle_menu_event(handle, event.id().0.as_str()))
         .setup(move |app| {
             log::debug!("setup");
+
+            // hyphenation patterns added by users take priority over bundled ones
+            let path = app.path();
+            let pattern_dirs = [path.app_data_dir(), path.resource_dir()]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join(HYPHENATION_DIR))
+                .collect();
+            alter::set_pattern_dirs(pattern_dirs);
+
+            // the front-end writes preferences too
+            app.listen_any("store://change", move |event| {
+                let is_prefs = serde_json::from_str::<serde_json::Value>(event.payload())
+                    .ok()
+                    .and_then(|payload| payload.get("path")?.as_str().map(PathBuf::from))
+                    .is_some_and(|path| path.ends_with(PREFS_STORE));
+                if is_prefs {
+                    PREFS_CHANGED.store(true, Ordering::Relaxed);
+                }
+            });
+
             if let Some(filepath) = filepath {
                 log::debug!(" with {}", filepath.to_string_lossy());
                 let state = app.state::<AppState>();
@@ -407,42 +1379,84 @@ pub fn run(filepath: Option<PathBuf>) {
             }
             Ok(())
         })
-        .register_uri_scheme_protocol("epub", |ctx, request| {
+        .register_asynchronous_uri_scheme_protocol("epub", |ctx, request, responder| {
             let Ok(uri) = url_from_epub_request(request.uri()) else {
-                return http::Response::builder()
-                    .status(http::StatusCode::BAD_REQUEST)
-                    .body(Vec::new())
-                    .unwrap();
+                responder.respond(epub_response(Err(http::StatusCode::BAD_REQUEST)));
+                return;
             };
 
             log::debug!("handling request {}", uri);
 
+            // fetching from the web shouldn't hold up the rest
+            if let Some(remote) = alter::remote_url(&uri) {
+                let app_handle = ctx.app_handle().clone();
+                tauri::async_runtime::spawn_blocking(move || {
+                    responder.respond(epub_response(serve_remote_request(&app_handle, &remote)));
+                });
+                return;
+            }
+
             let is_content_doc = request
                 .headers()
                 .get("Ogier-Epub-Content-Document")
                 .is_some_and(|v| !v.is_empty());
 
-            match serve_epub_request(ctx.app_handle(), &uri, is_content_doc) {
-                Ok(BytesAndMediaType(body, mime)) => http::Response::builder()
-                    .status(http::StatusCode::OK)
-                    .header(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                    .header(http::header::CONTENT_TYPE, mime)
-                    .body(body)
-                    .unwrap(),
-                Err(code) => http::Response::builder()
-                    .status(code)
-                    .body(Vec::default())
-                    .unwrap(),
+            let original = request.uri().query() == Some(ORIGINAL_IMAGE_QUERY);
+            let part = request.uri().query().and_then(alter::part_index);
+            let stitched = request.uri().query() == Some(CONTINUOUS_QUERY);
+
+            if stitched && uri.path() == "/" {
+                responder.respond(epub_response(serve_continuous_request(ctx.app_handle())));
+                return;
+            }
+
+            // so is decoding images
+            if !is_content_doc {
+                let app_handle = ctx.app_handle().clone();
+                tauri::async_runtime::spawn_blocking(move || {
+                    responder.respond(epub_response(serve_epub_request(
+                        &app_handle,
+                        &uri,
+                        false,
+                        original,
+                        None,
+                        false,
+                    )));
+                });
+                return;
             }
+
+            responder.respond(epub_response(serve_epub_request(
+                ctx.app_handle(),
+                &uri,
+                is_content_doc,
+                original,
+                part,
+                stitched,
+            )));
         })
         .invoke_handler(tauri::generate_handler![
+            allow_remote_host,
+            get_continuous_scroll,
+            get_diagnostics,
+            get_document_part,
             get_filewise_styles,
+            get_font_substitutes,
+            get_note,
             get_reading_position,
+            get_user_styles,
+            list_publisher_fonts,
+            list_system_fonts,
             open_epub,
             open_epub_if_loaded,
+            open_external_link,
+            preview_rules,
             reload_book,
             set_filewise_styles,
+            set_font_substitute,
+            set_font_disabled,
             set_reading_position,
+            set_user_styles,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -3,9 +3,12 @@
 // - Put ID and Text in resource file that can be imported here and frontend.
 // - Tidy the `use` or qualifiers
 
-use tauri::{Emitter, menu::Menu};
+use tauri::{Emitter, Manager, menu::Menu};
 
-use crate::prefs::FontPrefer;
+use crate::prefs::{
+    self, ChineseScript, EmphasisStrength, PublisherStyles, RubyMode, ScriptPolicy, Theme,
+    VerticalLayout,
+};
 
 fn handle_by_frontend<R>(app: &tauri::AppHandle<R>, id: &str)
 where
@@ -16,6 +19,22 @@ where
     }
 }
 
+/// Saves a per-book option of the opened book, then lets the front-end reload.
+fn save_book_option<T>(app: &tauri::AppHandle, key: &str, value: T, id: &str)
+where
+    T: serde::Serialize,
+{
+    let state = app.state::<crate::AppState>();
+    let json_value = serde_json::to_value(value).unwrap_or_default();
+    if let Err(e) = crate::set_filewise_value(app, &mut state.lock().unwrap(), key, json_value) {
+        log::error!("Could not save {}: {}", key, e);
+        return;
+    }
+
+    // notify the front-end
+    handle_by_frontend(app, id);
+}
+
 pub mod file {
     use tauri::menu::{MenuItemBuilder, PredefinedMenuItem, Submenu, SubmenuBuilder};
 
@@ -112,17 +131,96 @@ pub mod file {
 pub mod view {
     use tauri::menu::{Submenu, SubmenuBuilder};
 
+    use crate::menus::save_book_option;
+
     pub const ID: &str = "v";
     const TEXT: &str = "View";
 
+    /// Opens the font preference dialog of the front-end.
     pub mod font_preference {
+        pub const ID: &str = "v_fp";
+        pub(super) const TEXT: &str = "Font preference";
+    }
+
+    /// An option saved per book, as check items of View or of one of its submenus, each
+    /// standing for a value. Unchecking the checked item goes back to `unchecked`.
+    pub struct BookOption<T: 'static> {
+        /// Key of the option in `prefs`.
+        key: &'static str,
+        /// ID of the submenu with the items, or `None` if they are in View itself.
+        submenu: Option<&'static str>,
+        /// IDs of the items, with the values they stand for.
+        items: &'static [(&'static str, T)],
+        unchecked: T,
+        /// ID the front-end is notified of once the value is saved.
+        notify: &'static str,
+    }
+
+    impl<T> BookOption<T>
+    where
+        T: Copy + PartialEq + serde::Serialize,
+    {
+        fn checks<R>(&self, view: &Submenu<R>) -> Submenu<R>
+        where
+            R: tauri::Runtime,
+        {
+            match self.submenu {
+                Some(id) => view.get(id).unwrap().as_submenu_unchecked().clone(),
+                None => view.clone(),
+            }
+        }
+
+        /// Saves the value of the item `id` as just toggled, and checks it alone.
+        pub fn handle(&self, app: &tauri::AppHandle, id: &str) {
+            let menu = app.menu().unwrap().get(ID).unwrap();
+            let checks = self.checks(menu.as_submenu_unchecked());
+            let Ok(is_checked) = checks
+                .get(id)
+                .unwrap()
+                .as_check_menuitem_unchecked()
+                .is_checked()
+            else {
+                return;
+            };
+            let value = match self.items.iter().find(|(item, _)| *item == id) {
+                Some(&(_, value)) if is_checked => value,
+                _ => self.unchecked,
+            };
+            if self.set(menu.as_submenu_unchecked(), value).is_err() {
+                return;
+            }
+
+            save_book_option(app, self.key, value, self.notify);
+        }
+
+        /// Only updates the checks. The value is saved per book by the caller.
+        pub fn set<R>(&self, view: &Submenu<R>, value: T) -> Result<(), tauri::Error>
+        where
+            R: tauri::Runtime,
+        {
+            let checks = self.checks(view);
+            for &(id, item_value) in self.items {
+                checks
+                    .get(id)
+                    .unwrap()
+                    .as_check_menuitem_unchecked()
+                    .set_checked(value == item_value)?;
+            }
+            Ok(())
+        }
+    }
+
+    pub mod theme {
         use tauri::menu::{Submenu, SubmenuBuilder};
         use tauri_plugin_store::StoreExt;
 
-        use crate::{menus::handle_by_frontend, prefs::FontPrefer};
+        use crate::{
+            menus::handle_by_frontend,
+            prefs::{self, Theme},
+        };
 
-        pub const ID: &str = "v_fp";
-        const TEXT: &str = "Font preference";
+        pub const ID: &str = "v_t";
+        const TEXT: &str = "Theme";
 
         pub fn handle(app: &tauri::AppHandle, id: &str) {
             let Ok(prefs_store) = app.store(crate::PREFS_STORE) else {
@@ -145,25 +243,34 @@ pub mod view {
             let Ok(is_checked) = menu_item.is_checked() else {
                 return;
             };
-            let font_pref = if is_checked {
-                Some(if id == serif::ID {
-                    FontPrefer::Serif
+            let theme = if is_checked {
+                Some(if id == dark::ID {
+                    Theme::Dark
                 } else {
-                    FontPrefer::SansSerif
+                    Theme::Sepia
                 })
             } else {
                 None
             };
-            let _ = set(&menu, font_pref, &prefs_store);
+            if set(&menu, theme).is_err() {
+                return;
+            }
+
+            // save prefs
+            let json_value = serde_json::to_value(theme).unwrap_or_default();
+            prefs_store.set(prefs::THEME, json_value);
+
+            // notify the front-end
+            handle_by_frontend(app, ID);
         }
 
-        pub mod sans_serif {
-            pub const ID: &str = "v_fp_ss";
-            pub(super) const TEXT: &str = "Sans-serif";
+        pub mod sepia {
+            pub const ID: &str = "v_t_s";
+            pub(super) const TEXT: &str = "Sepia";
         }
-        pub mod serif {
-            pub const ID: &str = "v_fp_s";
-            pub(super) const TEXT: &str = "Serif";
+        pub mod dark {
+            pub const ID: &str = "v_t_d";
+            pub(super) const TEXT: &str = "Dark";
         }
 
         pub fn make<R, M>(manager: &M) -> tauri::Result<Submenu<R>>
@@ -173,48 +280,326 @@ pub mod view {
         {
             SubmenuBuilder::new(manager, TEXT)
                 .id(ID)
-                .check(sans_serif::ID, sans_serif::TEXT)
-                .check(serif::ID, serif::TEXT)
+                .check(sepia::ID, sepia::TEXT)
+                .check(dark::ID, dark::TEXT)
                 .build()
         }
 
-        pub fn set<R>(
-            submenu: &Submenu<R>,
-            value: Option<FontPrefer>,
-            prefs_store: &tauri_plugin_store::Store<R>,
-        ) -> Result<(), tauri::Error>
+        /// Only updates the checks. The value is saved by the caller.
+        pub fn set<R>(submenu: &Submenu<R>, value: Option<Theme>) -> Result<(), tauri::Error>
         where
             R: tauri::Runtime,
         {
-            let (sans_checked, serif_checked) = match value {
-                Some(FontPrefer::SansSerif) => (true, false),
-                Some(FontPrefer::Serif) => (false, true),
-                None => (false, false),
-            };
             submenu
-                .get(sans_serif::ID)
+                .get(sepia::ID)
                 .unwrap()
                 .as_check_menuitem_unchecked()
-                .set_checked(sans_checked)?;
+                .set_checked(value == Some(Theme::Sepia))?;
             submenu
-                .get(serif::ID)
+                .get(dark::ID)
                 .unwrap()
                 .as_check_menuitem_unchecked()
-                .set_checked(serif_checked)?;
+                .set_checked(value == Some(Theme::Dark))?;
+            Ok(())
+        }
+    }
+
+    pub mod publisher_styles {
+        use tauri::menu::{Submenu, SubmenuBuilder};
+
+        use super::BookOption;
+        use crate::prefs::{self, PublisherStyles};
+
+        pub const ID: &str = "v_ps";
+        const TEXT: &str = "Publisher styles";
+
+        pub const OPTION: BookOption<PublisherStyles> = BookOption {
+            key: prefs::PUBLISHER_STYLES,
+            submenu: Some(ID),
+            items: &[
+                (keep::ID, PublisherStyles::Keep),
+                (normalize::ID, PublisherStyles::Normalize),
+                (ignore::ID, PublisherStyles::Ignore),
+            ],
+            // unchecking one goes back to keep
+            unchecked: PublisherStyles::Keep,
+            notify: ID,
+        };
+
+        pub mod keep {
+            pub const ID: &str = "v_ps_k";
+            pub(super) const TEXT: &str = "Keep";
+        }
+        pub mod normalize {
+            pub const ID: &str = "v_ps_n";
+            pub(super) const TEXT: &str = "Normalize";
+        }
+        pub mod ignore {
+            pub const ID: &str = "v_ps_i";
+            pub(super) const TEXT: &str = "Ignore (text only)";
+        }
+
+        pub fn make<R, M>(manager: &M) -> tauri::Result<Submenu<R>>
+        where
+            R: tauri::Runtime,
+            M: tauri::Manager<R>,
+        {
+            SubmenuBuilder::new(manager, TEXT)
+                .id(ID)
+                .check(keep::ID, keep::TEXT)
+                .check(normalize::ID, normalize::TEXT)
+                .check(ignore::ID, ignore::TEXT)
+                .build()
+        }
+    }
 
-            let json_value = match value {
-                Some(FontPrefer::SansSerif) => serde_json::json!("sans-serif"),
-                Some(FontPrefer::Serif) => serde_json::json!("serif"),
-                None => serde_json::Value::Null,
+    pub mod horizontal_layout {
+        use super::BookOption;
+        use crate::prefs::{self, VerticalLayout};
+
+        pub const ID: &str = "v_hl";
+        pub(super) const TEXT: &str = "Horizontal layout for vertical text";
+
+        pub const OPTION: BookOption<VerticalLayout> = BookOption {
+            key: prefs::VERTICAL_LAYOUT,
+            submenu: None,
+            items: &[(ID, VerticalLayout::Horizontal)],
+            unchecked: VerticalLayout::Keep,
+            notify: ID,
+        };
+    }
+
+    pub mod ruby {
+        use tauri::menu::{Submenu, SubmenuBuilder};
+
+        use super::BookOption;
+        use crate::prefs::{self, RubyMode};
+
+        pub const ID: &str = "v_r";
+        const TEXT: &str = "Ruby";
+
+        pub const OPTION: BookOption<RubyMode> = BookOption {
+            key: prefs::RUBY,
+            submenu: Some(ID),
+            items: &[
+                (show::ID, RubyMode::Show),
+                (hide::ID, RubyMode::Hide),
+                (inline::ID, RubyMode::Inline),
+            ],
+            // unchecking one goes back to show
+            unchecked: RubyMode::Show,
+            notify: ID,
+        };
+
+        pub mod show {
+            pub const ID: &str = "v_r_s";
+            pub(super) const TEXT: &str = "Show";
+        }
+        pub mod hide {
+            pub const ID: &str = "v_r_h";
+            pub(super) const TEXT: &str = "Hide";
+        }
+        pub mod inline {
+            pub const ID: &str = "v_r_i";
+            pub(super) const TEXT: &str = "Inline in parentheses";
+        }
+        pub mod add_readings {
+            use crate::{menus::view::BookOption, prefs};
+
+            pub const ID: &str = "v_r_ar";
+            pub(super) const TEXT: &str = "Add readings";
+
+            pub const OPTION: BookOption<bool> = BookOption {
+                key: prefs::RUBY_READINGS,
+                submenu: Some(super::ID),
+                items: &[(ID, true)],
+                unchecked: false,
+                notify: super::ID,
             };
-            // save prefs
-            prefs_store.set("font.prefer", json_value.clone());
+        }
 
-            // notify the front-end
-            handle_by_frontend(submenu.app_handle(), ID);
+        pub fn make<R, M>(manager: &M) -> tauri::Result<Submenu<R>>
+        where
+            R: tauri::Runtime,
+            M: tauri::Manager<R>,
+        {
+            SubmenuBuilder::new(manager, TEXT)
+                .id(ID)
+                .check(show::ID, show::TEXT)
+                .check(hide::ID, hide::TEXT)
+                .check(inline::ID, inline::TEXT)
+                .separator()
+                .check(add_readings::ID, add_readings::TEXT)
+                .build()
+        }
+    }
 
-            Ok(())
+    pub mod chinese_script {
+        use tauri::menu::{Submenu, SubmenuBuilder};
+
+        use super::BookOption;
+        use crate::prefs::{self, ChineseScript};
+
+        pub const ID: &str = "v_cs";
+        const TEXT: &str = "Chinese script";
+
+        pub const OPTION: BookOption<Option<ChineseScript>> = BookOption {
+            key: prefs::CHINESE_SCRIPT,
+            submenu: Some(ID),
+            items: &[
+                (simplified::ID, Some(ChineseScript::Simplified)),
+                (traditional::ID, Some(ChineseScript::Traditional)),
+                (taiwan::ID, Some(ChineseScript::Taiwan)),
+                (hong_kong::ID, Some(ChineseScript::HongKong)),
+            ],
+            // unchecking one goes back to the script of the book
+            unchecked: None,
+            notify: ID,
+        };
+
+        pub mod simplified {
+            pub const ID: &str = "v_cs_s";
+            pub(super) const TEXT: &str = "Simplified";
+        }
+        pub mod traditional {
+            pub const ID: &str = "v_cs_t";
+            pub(super) const TEXT: &str = "Traditional";
+        }
+        pub mod taiwan {
+            pub const ID: &str = "v_cs_tw";
+            pub(super) const TEXT: &str = "Traditional (Taiwan)";
+        }
+        pub mod hong_kong {
+            pub const ID: &str = "v_cs_hk";
+            pub(super) const TEXT: &str = "Traditional (Hong Kong)";
+        }
+
+        pub fn make<R, M>(manager: &M) -> tauri::Result<Submenu<R>>
+        where
+            R: tauri::Runtime,
+            M: tauri::Manager<R>,
+        {
+            SubmenuBuilder::new(manager, TEXT)
+                .id(ID)
+                .check(simplified::ID, simplified::TEXT)
+                .check(traditional::ID, traditional::TEXT)
+                .check(taiwan::ID, taiwan::TEXT)
+                .check(hong_kong::ID, hong_kong::TEXT)
+                .build()
+        }
+    }
+
+    pub mod reading_emphasis {
+        use tauri::menu::{Submenu, SubmenuBuilder};
+
+        use super::BookOption;
+        use crate::prefs::{self, EmphasisStrength};
+
+        pub const ID: &str = "v_re";
+        const TEXT: &str = "Reading emphasis";
+
+        pub const OPTION: BookOption<Option<EmphasisStrength>> = BookOption {
+            key: prefs::READING_EMPHASIS,
+            submenu: Some(ID),
+            items: &[
+                (light::ID, Some(EmphasisStrength::Light)),
+                (medium::ID, Some(EmphasisStrength::Medium)),
+                (strong::ID, Some(EmphasisStrength::Strong)),
+            ],
+            // unchecking one turns emphasis off
+            unchecked: None,
+            notify: ID,
+        };
+
+        pub mod light {
+            pub const ID: &str = "v_re_l";
+            pub(super) const TEXT: &str = "Light";
+        }
+        pub mod medium {
+            pub const ID: &str = "v_re_m";
+            pub(super) const TEXT: &str = "Medium";
+        }
+        pub mod strong {
+            pub const ID: &str = "v_re_s";
+            pub(super) const TEXT: &str = "Strong";
         }
+
+        pub fn make<R, M>(manager: &M) -> tauri::Result<Submenu<R>>
+        where
+            R: tauri::Runtime,
+            M: tauri::Manager<R>,
+        {
+            SubmenuBuilder::new(manager, TEXT)
+                .id(ID)
+                .check(light::ID, light::TEXT)
+                .check(medium::ID, medium::TEXT)
+                .check(strong::ID, strong::TEXT)
+                .build()
+        }
+    }
+
+    pub mod scripts_in_sandbox {
+        use super::BookOption;
+        use crate::prefs::{self, ScriptPolicy};
+
+        pub const ID: &str = "v_sis";
+        pub(super) const TEXT: &str = "Run scripts in sandbox";
+
+        pub const OPTION: BookOption<ScriptPolicy> = BookOption {
+            key: prefs::SCRIPTS,
+            submenu: None,
+            items: &[(ID, ScriptPolicy::Sandbox)],
+            unchecked: ScriptPolicy::Strip,
+            notify: ID,
+        };
+    }
+
+    pub mod downscale_images {
+        use super::BookOption;
+        use crate::prefs;
+
+        pub const ID: &str = "v_di";
+        pub(super) const TEXT: &str = "Downscale large images";
+
+        pub const OPTION: BookOption<bool> = BookOption {
+            key: prefs::DOWNSCALE_IMAGES,
+            submenu: None,
+            items: &[(ID, true)],
+            unchecked: false,
+            notify: ID,
+        };
+    }
+
+    pub mod hyphenation {
+        use super::BookOption;
+        use crate::prefs;
+
+        pub const ID: &str = "v_h";
+        pub(super) const TEXT: &str = "Hyphenate text";
+
+        pub const OPTION: BookOption<bool> = BookOption {
+            key: prefs::HYPHENATION,
+            submenu: None,
+            items: &[(ID, true)],
+            unchecked: false,
+            notify: ID,
+        };
+    }
+
+    pub mod continuous_scroll {
+        use super::BookOption;
+        use crate::prefs;
+
+        pub const ID: &str = "v_csc";
+        pub(super) const TEXT: &str = "Continuous scroll";
+
+        pub const OPTION: BookOption<bool> = BookOption {
+            key: prefs::CONTINUOUS_SCROLL,
+            submenu: None,
+            items: &[(ID, true)],
+            unchecked: false,
+            notify: ID,
+        };
     }
 
     pub mod open_filewise_styles {
@@ -235,6 +620,36 @@ pub mod view {
         }
     }
 
+    pub mod open_user_styles {
+        use tauri::Manager;
+        use tauri_plugin_opener::OpenerExt;
+
+        pub const ID: &str = "v_ous";
+        pub(super) const TEXT: &str = "Open user styles";
+        pub const FILEWISE_ID: &str = "v_ous_f";
+        pub(super) const FILEWISE_TEXT: &str = "Open filewise user styles";
+
+        /// Opens the user stylesheet of all books, or of the opened one, creating it empty
+        /// for the editor if there is none.
+        pub fn handle(app: &tauri::AppHandle, id: &str) {
+            let state = app.state::<crate::AppState>();
+            let filewise = id == FILEWISE_ID;
+            let Ok(css_path) = crate::user_styles_path(app, &state.lock().unwrap(), filewise)
+            else {
+                return;
+            };
+            if !css_path.exists() {
+                if let Some(dir) = css_path.parent() {
+                    let _ = std::fs::create_dir_all(dir);
+                }
+                let _ = std::fs::write(&css_path, "");
+            }
+            let _ = app
+                .opener()
+                .open_path(css_path.to_string_lossy(), None::<&str>);
+        }
+    }
+
     pub fn make<R>(window: &tauri::Window<R>) -> tauri::Result<Submenu<R>>
     where
         R: tauri::Runtime,
@@ -242,8 +657,25 @@ pub mod view {
         SubmenuBuilder::new(window, TEXT)
             .id(ID)
             .text(open_filewise_styles::ID, open_filewise_styles::TEXT)
+            .text(open_user_styles::ID, open_user_styles::TEXT)
+            .text(
+                open_user_styles::FILEWISE_ID,
+                open_user_styles::FILEWISE_TEXT,
+            )
+            .separator()
+            .text(font_preference::ID, font_preference::TEXT)
+            .item(&theme::make(window)?)
+            .item(&publisher_styles::make(window)?)
             .separator()
-            .item(&font_preference::make(window)?)
+            .check(horizontal_layout::ID, horizontal_layout::TEXT)
+            .check(continuous_scroll::ID, continuous_scroll::TEXT)
+            .item(&ruby::make(window)?)
+            .item(&chinese_script::make(window)?)
+            .item(&reading_emphasis::make(window)?)
+            .check(hyphenation::ID, hyphenation::TEXT)
+            .separator()
+            .check(scripts_in_sandbox::ID, scripts_in_sandbox::TEXT)
+            .check(downscale_images::ID, downscale_images::TEXT)
             .build()
     }
 }
@@ -330,10 +762,31 @@ pub fn handle_menu_event(app: &tauri::AppHandle, id: &str) {
 
         file::open_preference_file::ID => file::open_preference_file::handle(app),
 
-        view::font_preference::sans_serif::ID | view::font_preference::serif::ID => {
-            view::font_preference::handle(app, id)
+        view::font_preference::ID => handle_by_frontend(app, id),
+        view::theme::sepia::ID | view::theme::dark::ID => view::theme::handle(app, id),
+        view::publisher_styles::keep::ID
+        | view::publisher_styles::normalize::ID
+        | view::publisher_styles::ignore::ID => view::publisher_styles::OPTION.handle(app, id),
+        view::horizontal_layout::ID => view::horizontal_layout::OPTION.handle(app, id),
+        view::ruby::show::ID | view::ruby::hide::ID | view::ruby::inline::ID => {
+            view::ruby::OPTION.handle(app, id)
         }
+        view::ruby::add_readings::ID => view::ruby::add_readings::OPTION.handle(app, id),
+        view::chinese_script::simplified::ID
+        | view::chinese_script::traditional::ID
+        | view::chinese_script::taiwan::ID
+        | view::chinese_script::hong_kong::ID => view::chinese_script::OPTION.handle(app, id),
+        view::reading_emphasis::light::ID
+        | view::reading_emphasis::medium::ID
+        | view::reading_emphasis::strong::ID => view::reading_emphasis::OPTION.handle(app, id),
+        view::scripts_in_sandbox::ID => view::scripts_in_sandbox::OPTION.handle(app, id),
+        view::downscale_images::ID => view::downscale_images::OPTION.handle(app, id),
+        view::hyphenation::ID => view::hyphenation::OPTION.handle(app, id),
+        view::continuous_scroll::ID => view::continuous_scroll::OPTION.handle(app, id),
         view::open_filewise_styles::ID => view::open_filewise_styles::handle(app),
+        view::open_user_styles::ID | view::open_user_styles::FILEWISE_ID => {
+            view::open_user_styles::handle(app, id)
+        }
 
         help::open_dev_tools::ID => help::open_dev_tools::handle(app),
         help::version::ID => (),
@@ -377,27 +830,47 @@ where
     let view_submenu = view::make(window)?;
     menu.insert(&view_submenu, 1)?;
 
-    // font prefer init value
-    let font_prefer_value = prefs_store.get("font.prefer");
-    set_font_preference(
-        &window,
-        match font_prefer_value {
-            Some(serde_json::Value::String(value)) if value == "sans-serif" => {
-                Some(FontPrefer::SansSerif)
-            }
-            Some(serde_json::Value::String(value)) if value == "serif" => Some(FontPrefer::Serif),
-            _ => None,
-        },
-        prefs_store,
-    )?;
+    // theme init value
+    let theme = prefs_store
+        .get(prefs::THEME)
+        .and_then(|value| serde_json::from_value::<Theme>(value).ok());
+    set_theme(&window, theme)?;
 
     Ok(true)
 }
 
-fn set_font_preference<R>(
+fn set_theme<R>(window: &tauri::Window<R>, value: Option<Theme>) -> Result<(), tauri::Error>
+where
+    R: tauri::Runtime,
+{
+    let menu = window.menu().unwrap();
+    let view = menu.get(view::ID).unwrap();
+    let view = view.as_submenu_unchecked();
+    let theme = view.get(view::theme::ID).unwrap();
+    let theme = theme.as_submenu_unchecked();
+
+    view::theme::set(theme, value)?;
+    Ok(())
+}
+
+/// Per-book options, as checked in the View menu.
+pub struct BookOptions {
+    pub publisher_styles: PublisherStyles,
+    pub vertical_layout: VerticalLayout,
+    pub ruby: RubyMode,
+    pub ruby_readings: bool,
+    pub chinese_script: Option<ChineseScript>,
+    pub reading_emphasis: Option<EmphasisStrength>,
+    pub scripts: ScriptPolicy,
+    pub downscale_images: bool,
+    pub hyphenation: bool,
+    pub continuous_scroll: bool,
+}
+
+/// Updates the checks of per-book options to the values of the opened book.
+pub fn set_book_options<R>(
     window: &tauri::Window<R>,
-    value: Option<FontPrefer>,
-    prefs_store: &tauri_plugin_store::Store<R>,
+    options: &BookOptions,
 ) -> Result<(), tauri::Error>
 where
     R: tauri::Runtime,
@@ -405,9 +878,16 @@ where
     let menu = window.menu().unwrap();
     let view = menu.get(view::ID).unwrap();
     let view = view.as_submenu_unchecked();
-    let font_preference = view.get(view::font_preference::ID).unwrap();
-    let font_preference = font_preference.as_submenu_unchecked();
 
-    view::font_preference::set(font_preference, value, prefs_store)?;
+    view::publisher_styles::OPTION.set(view, options.publisher_styles)?;
+    view::horizontal_layout::OPTION.set(view, options.vertical_layout)?;
+    view::ruby::OPTION.set(view, options.ruby)?;
+    view::ruby::add_readings::OPTION.set(view, options.ruby_readings)?;
+    view::chinese_script::OPTION.set(view, options.chinese_script)?;
+    view::reading_emphasis::OPTION.set(view, options.reading_emphasis)?;
+    view::scripts_in_sandbox::OPTION.set(view, options.scripts)?;
+    view::downscale_images::OPTION.set(view, options.downscale_images)?;
+    view::hyphenation::OPTION.set(view, options.hyphenation)?;
+    view::continuous_scroll::OPTION.set(view, options.continuous_scroll)?;
     Ok(())
 }
@@ -1,4 +1,232 @@
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Generic family that the reader, and font stacks, fall back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum FontPrefer {
     SansSerif,
     Serif,
 }
+
+/// A `FontPrefer`. The reader uses the browser default if unset.
+pub const FONT_PREFER: &str = "font.prefer";
+
+/// Font families by BCP 47 language tag or script subtag, app-wide and per book, e.g.,
+/// `{"ja": ["Noto Serif JP"], "Hant": ["Noto Sans TC", "PingFang TC"]}`. Per-book entries
+/// win. Text in a language uses the stack of its longest matching tag, see
+/// `alter::Pass::FontStacks`. A stack replaces the generic family it lists, `serif` or
+/// `sans-serif`, or else the `FONT_PREFER` one.
+pub const FONT_STACKS: &str = "font.stacks";
+
+/// Color theme of the reader. Publisher colors are kept if unset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Sepia,
+    Dark,
+}
+
+/// A `Theme`.
+pub const THEME: &str = "content.theme";
+
+/// How much of the publisher styles to apply, set per book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PublisherStyles {
+    Keep,
+    /// Neutralize justification, fixed widths and forced fonts.
+    Normalize,
+    /// Strip all publisher CSS, leaving the structure.
+    Ignore,
+}
+
+/// A `PublisherStyles`.
+pub const PUBLISHER_STYLES: &str = "content.publisher-styles";
+
+/// Layout of vertically written books, set per book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerticalLayout {
+    Keep,
+    /// Rewrite vertical writing modes to `horizontal-tb`.
+    Horizontal,
+}
+
+/// A `VerticalLayout`.
+pub const VERTICAL_LAYOUT: &str = "content.vertical-layout";
+
+/// How ruby annotations (`<rt>`) are shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RubyMode {
+    Show,
+    Hide,
+    /// In parentheses after the base text.
+    Inline,
+}
+
+/// A `RubyMode`.
+pub const RUBY: &str = "ruby.mode";
+
+/// Whether to add readings as ruby over Han characters, pinyin for Chinese and kana for
+/// Japanese, set per book. Off if unset.
+pub const RUBY_READINGS: &str = "ruby.readings";
+
+/// Script to convert Chinese text to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChineseScript {
+    Simplified,
+    Traditional,
+    /// Traditional, with the character forms used in Taiwan.
+    Taiwan,
+    /// Traditional, with the character forms used in Hong Kong.
+    HongKong,
+}
+
+/// A `ChineseScript`, set per book. Text is not converted if unset.
+pub const CHINESE_SCRIPT: &str = "content.chinese-script";
+
+/// How much of each word reading emphasis makes bold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmphasisStrength {
+    Light,
+    Medium,
+    Strong,
+}
+
+/// An `EmphasisStrength`, set per book. Words are not emphasized if unset.
+pub const READING_EMPHASIS: &str = "content.reading-emphasis";
+
+/// What to do with scripts in content documents, set per book or app-wide.
+///
+/// Scripts never run with the privileges of the reader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScriptPolicy {
+    /// Remove `<script>`, event handlers, `javascript:` URLs, and frames and plugins showing
+    /// anything but the book.
+    Strip,
+    /// Show documents marked `scripted` in a sandboxed frame, with their scripts.
+    Sandbox,
+}
+
+/// A `ScriptPolicy`.
+pub const SCRIPTS: &str = "content.scripts";
+
+/// What to do with resources that books load from the web, set per book or app-wide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemotePolicy {
+    Block,
+    /// Fetch resources on hosts in `REMOTE_ALLOWLIST` through a caching proxy, and block
+    /// others. Only stylesheets, fonts and images are served.
+    Proxy,
+}
+
+/// A `RemotePolicy`. `Block` if unset.
+pub const REMOTE_RESOURCES: &str = "remote.policy";
+
+/// Hosts whose resources are fetched, set per book, e.g., `["fonts.example.com"]`.
+pub const REMOTE_ALLOWLIST: &str = "remote.allowlist";
+
+/// Whether to downscale large PNG and JPEG images, set per book. Off if unset.
+pub const DOWNSCALE_IMAGES: &str = "image.downscale";
+
+/// Largest width or height of downscaled images, in pixels, e.g., `2048`.
+pub const IMAGE_MAX_DIMENSION: &str = "image.max-dimension";
+
+/// Whether to insert soft hyphens into text, set per book. Off if unset, since copied and
+/// searched text gets them too.
+pub const HYPHENATION: &str = "content.hyphenation";
+
+/// Whether to ask before opening external links in the system browser. On if unset.
+pub const CONFIRM_EXTERNAL_LINKS: &str = "links.confirm-external";
+
+/// A find and replace rule applied to the text of books, never to markup.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReplaceRule {
+    pub find: String,
+    pub replace: String,
+    /// Whether `find` is a regular expression, whose groups `replace` may refer to as `$1`.
+    #[serde(default)]
+    pub regex: bool,
+}
+
+/// `ReplaceRule`s, app-wide and per book, e.g., `[{"find": "tbe", "replace": "the"}]`.
+/// Both lists apply, app-wide first.
+pub const REPLACE_RULES: &str = "content.replace-rules";
+
+/// Local font families by publisher font family in lowercase, app-wide and per book, e.g.,
+/// `{"georgia": "Noto Serif"}`. The reader sets them as the `--og-font-*` custom properties
+/// that `alter::Pass::FontFamily` puts before each family. Per-book entries win.
+pub const FONT_SUBSTITUTE: &str = "font.substitute";
+
+/// URLs of the embedded fonts whose `@font-face` rules are dropped, set per book, e.g.,
+/// `["epub:/OEBPS/fonts/minion.otf"]`.
+pub const DISABLED_FONTS: &str = "font.disabled";
+
+/// Whether to read the spine as one scrolling document, set per book. If unset, on if the
+/// book asks for `rendition:flow` `scrolled-continuous`.
+pub const CONTINUOUS_SCROLL: &str = "view.continuous-scroll";
+
+/// List of `alter::Pass` to run on served content, in order, set per book or app-wide, e.g.,
+/// `["block-anchors", "colors", "units"]`. `alter::Pass::DEFAULT` if unset. Passes that strip
+/// scripts and block remote resources run anyway, last if left out.
+pub const TRANSFORMS: &str = "content.transforms";
+
+/// Settings that apply to the opened book.
+///
+/// A value can be set in the preferences store (app-wide) or in the filewise styles file
+/// (per book). The per-book value wins.
+#[derive(Clone, Default)]
+pub struct Settings {
+    filewise: Map<String, Value>,
+    app: Map<String, Value>,
+}
+
+impl Settings {
+    pub fn new(filewise: Map<String, Value>, app: Map<String, Value>) -> Self {
+        Self { filewise, app }
+    }
+
+    /// Get the value at `key`. Values of the wrong type are ignored.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let parse = |values: &Map<String, Value>| {
+            values
+                .get(key)
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+        };
+        parse(&self.filewise).or_else(|| parse(&self.app))
+    }
+
+    /// Get the lists at `key` app-wide and per book, joined in that order.
+    pub fn get_joined<T: DeserializeOwned>(&self, key: &str) -> Vec<T> {
+        let parse = |values: &Map<String, Value>| -> Vec<T> {
+            values
+                .get(key)
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default()
+        };
+        let mut joined = parse(&self.app);
+        joined.extend(parse(&self.filewise));
+        joined
+    }
+
+    /// Get the maps at `key` app-wide and per book, merged. Per-book entries win.
+    pub fn get_merged<T: DeserializeOwned>(&self, key: &str) -> BTreeMap<String, T> {
+        let parse = |values: &Map<String, Value>| -> BTreeMap<String, T> {
+            values
+                .get(key)
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default()
+        };
+        let mut merged = parse(&self.app);
+        merged.extend(parse(&self.filewise));
+        merged
+    }
+}
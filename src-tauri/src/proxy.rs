@@ -0,0 +1,104 @@
+//! Fetches resources that books load from the web, see `prefs::RemotePolicy`.
+
+use std::fs;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::Path;
+
+use twox_hash::XxHash64;
+use url::Url;
+
+use crate::alter::is_allowed;
+use crate::errors::AnyErr;
+
+const DEFAULT_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// Largest body fetched, in bytes, well above that of fonts and images in books.
+const MAX_BODY_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Most redirects followed for a resource.
+const MAX_REDIRECTS: usize = 10;
+
+/// Media types of fonts not under `font/`, as sites still serve them.
+const LEGACY_FONT_TYPES: &[&str] = &[
+    "application/font-sfnt",
+    "application/font-woff",
+    "application/vnd.ms-fontobject",
+    "application/vnd.ms-opentype",
+    "application/x-font-otf",
+    "application/x-font-ttf",
+    "application/x-font-woff",
+];
+
+/// The media type to serve a fetched resource as, without parameters, if it is one that
+/// books load from the web: a stylesheet, a font or an image. Others are not served.
+pub fn served_media_type(media_type: &str) -> Option<String> {
+    let essence = media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let served = essence == "text/css"
+        || essence.starts_with("font/")
+        || essence.starts_with("image/")
+        || LEGACY_FONT_TYPES.contains(&essence.as_str());
+    served.then_some(essence)
+}
+
+/// Returns the body and media type of the resource at `url`, from the cache in `cache_dir`
+/// if there, or else from the web, caching it. Redirects are followed only to `allowed`
+/// hosts, and bodies over `MAX_BODY_SIZE` are refused.
+pub fn fetch(cache_dir: &Path, url: &Url, allowed: &[String]) -> Result<(Vec<u8>, String), AnyErr> {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(url.as_str().as_bytes());
+    let key = format!("{:016x}", hasher.finish());
+    let body_path = cache_dir.join(&key);
+    let media_type_path = cache_dir.join(format!("{key}.type"));
+
+    if let (Ok(body), Ok(media_type)) = (fs::read(&body_path), fs::read_to_string(&media_type_path))
+    {
+        return Ok((body, media_type));
+    }
+
+    let allowed = allowed.to_vec();
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if is_allowed(&allowed, attempt.url()) {
+            attempt.follow()
+        } else {
+            let message = format!("redirected to {}, which is not allowed", attempt.url());
+            attempt.error(message)
+        }
+    });
+    let client = reqwest::blocking::Client::builder()
+        .redirect(redirect)
+        .build()?;
+
+    log::info!("fetching remote resource {url}");
+    let response = client.get(url.as_str()).send()?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_BODY_SIZE)
+    {
+        return Err(AnyErr::RemoteTooLarge);
+    }
+    let media_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(DEFAULT_MEDIA_TYPE)
+        .to_owned();
+    // the length may be missing or wrong
+    let mut body = Vec::new();
+    response.take(MAX_BODY_SIZE + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(AnyErr::RemoteTooLarge);
+    }
+
+    fs::create_dir_all(cache_dir)?;
+    fs::write(&body_path, &body)?;
+    fs::write(&media_type_path, &media_type)?;
+    Ok((body, media_type))
+}
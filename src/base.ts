@@ -8,6 +8,79 @@ export const APP_NAME = "OgierEPUB";
 
 export type UrlAndPercentage = [URL, number | null];
 
+/**
+ * A place in the text of a page that doesn't move with styles:
+ * `offset` chars into the text of the element with `data-og-id` of `anchor`.
+ * Soft hyphens are not counted.
+ */
+export interface TextPosition {
+	anchor: string;
+	offset: number;
+}
+
+const TEXT_POSITION_HASH_PREFIX = "#og-pos=";
+
+/**
+ * Copy of `url` holding `position` in its hash, as read by `textPositionFromUrl`.
+ */
+export function urlWithTextPosition(url: URL, position: TextPosition): URL {
+	const result = new URL(url);
+	result.hash = `${TEXT_POSITION_HASH_PREFIX}${position.anchor}:${position.offset}`;
+	return result;
+}
+
+export function textPositionFromUrl(url: URL): TextPosition | null {
+	if (!url.hash.startsWith(TEXT_POSITION_HASH_PREFIX)) return null;
+	const match = /^([0-9]+(?:\.[0-9]+)*):([0-9]+)$/.exec(
+		url.hash.substring(TEXT_POSITION_HASH_PREFIX.length),
+	);
+	if (!match) return null;
+	return { anchor: match[1], offset: Number(match[2]) };
+}
+
+const DOCUMENT_PART_QUERY = "og-part";
+
+/**
+ * URL of part `index` of the document at `url`, when a very large document is split.
+ */
+export function documentPartUrl(url: URL, index: number): URL {
+	const result = new URL(url);
+	result.search = `${DOCUMENT_PART_QUERY}=${index}`;
+	return result;
+}
+
+const CONTINUOUS_QUERY = "og-continuous";
+
+/**
+ * The document to read the spine continuously, with a section for each document of the spine.
+ */
+export const CONTINUOUS_DOCUMENT_URL = new URL(`epub:/?${CONTINUOUS_QUERY}`);
+
+/**
+ * URL of the document at `url` served to be stitched into the continuous document.
+ */
+export function stitchedDocumentUrl(url: URL): URL {
+	const result = new URL(url);
+	result.search = CONTINUOUS_QUERY;
+	result.hash = "";
+	return result;
+}
+
+/**
+ * ID of the element of `id` in the document at `index` in the spine, once stitched.
+ * Same as `alter::id_prefix` in the backend.
+ */
+export function stitchedId(index: number, id: string): string {
+	return `og-${index}-${id}`;
+}
+
+/**
+ * Length of `text` as counted in a `TextPosition`.
+ */
+export function textPositionLength(text: string): number {
+	return text.replace(/\u00AD/g, "").length;
+}
+
 export interface AboutPubJson {
 	// file
 	filePath: string;
@@ -21,6 +94,7 @@ export interface AboutPubJson {
 	pubTocUrl: string | null;
 	pubTocIsLegacy: boolean;
 	pubLandingPage: string;
+	pubVertical: boolean;
 }
 export interface AboutPub {
 	// file
@@ -35,6 +109,7 @@ export interface AboutPub {
 	pubTocUrl: URL | null;
 	pubTocIsLegacy: boolean;
 	pubLandingPage: URL;
+	pubVertical: boolean;
 }
 
 export function aboutPubFromJson(json: AboutPubJson): AboutPub {
@@ -49,6 +124,7 @@ export function aboutPubFromJson(json: AboutPubJson): AboutPub {
 		pubTocUrl,
 		pubTocIsLegacy,
 		pubLandingPage,
+		pubVertical,
 	} = json;
 
 	const dateFromMs = (ms: number) => {
@@ -69,6 +145,7 @@ export function aboutPubFromJson(json: AboutPubJson): AboutPub {
 		pubTocUrl: pubTocUrl == null ? null : URL.parse(pubTocUrl)!,
 		pubTocIsLegacy,
 		pubLandingPage: URL.parse(pubLandingPage)!,
+		pubVertical,
 	};
 }
 
@@ -177,6 +254,8 @@ export function clamp(value: number, min: number, max: number): number {
 
 export type FontPrefer = "sans-serif" | "serif" | null;
 
+export type Theme = "sepia" | "dark" | null;
+
 export class TaskRepeater {
 	#intervalMs: number;
 	#handle: number | null = null;
@@ -204,11 +283,19 @@ function toResourceUri(uri: URL): string {
 	// which means '/' in s will be escaped and the path structured is broken.
 	// So "" is used to make use of the platform-specific part,
 	// and append the pathname manually.
+	// The query is kept for the backend, e.g., to serve a part of a split document.
 	let tauriUrl = convertFileSrc("", "epub");
-	tauriUrl += uri.pathname.slice(1);
+	tauriUrl += uri.pathname.slice(1) + uri.search;
 	return tauriUrl;
 }
 
+/**
+ * URI of the image as it is in the book, not downscaled, e.g., for zooming in.
+ */
+export function originalImageUri(url: URL): string {
+	return toResourceUri(url) + "?og-original";
+}
+
 export function setElementUrl(
 	element: HTMLAnchorElement | HTMLImageElement | SVGImageElement | HTMLLinkElement,
 	url: URL,
@@ -228,7 +315,7 @@ export function setElementUrl(
 	}
 }
 
-export function fetchXml(url: URL, isContentDoc: boolean): Promise<Document> {
+function requestXml(url: URL, isContentDoc: boolean): Promise<XMLHttpRequest> {
 	const tauriUrl = toResourceUri(url);
 	return new Promise((resolve, reject) => {
 		const xhr = new XMLHttpRequest();
@@ -240,13 +327,37 @@ export function fetchXml(url: URL, isContentDoc: boolean): Promise<Document> {
 		}
 		xhr.onerror = reject;
 		xhr.onload = () => {
-			// TODO: confirm svg works
-			const doc = xhr.responseXML;
-			if (doc == null) {
+			if (xhr.responseXML == null) {
 				throw new Error("null XML in response");
 			}
-			resolve(doc);
+			resolve(xhr);
 		};
 		xhr.send();
 	});
 }
+
+export function fetchXml(url: URL, isContentDoc: boolean): Promise<Document> {
+	return requestXml(url, isContentDoc).then(xhr => xhr.responseXML!);
+}
+
+/**
+ * Fetch a content document, and whether it is to be shown in a sandboxed frame because of
+ * its scripts.
+ */
+export function fetchContentDocument(url: URL): Promise<[Document, boolean]> {
+	return requestXml(url, true).then(xhr => [
+		xhr.responseXML!,
+		!!xhr.getResponseHeader("Ogier-Epub-Sandboxed"),
+	]);
+}
+
+/**
+ * Make an `<iframe>` showing `url` with scripts enabled, isolated from the app.
+ */
+export function createSandboxedFrame(url: URL): HTMLIFrameElement {
+	const frame = document.createElement("iframe");
+	frame.sandbox.add("allow-scripts");
+	frame.style.cssText = "display: block; width: 100%; height: 100%; border: none;";
+	frame.src = toResourceUri(url);
+	return frame;
+}
@@ -10,7 +10,7 @@ import {
 	AboutPub,
 	AboutPubJson,
 	FilewiseStyles,
-	FontPrefer,
+	Theme,
 	UrlAndPercentage,
 	aboutPubFromJson,
 } from "./base";
@@ -33,6 +33,22 @@ export function setFilewiseStyles(styles: FilewiseStyles): Promise<void> {
 	return invoke("set_filewise_styles", args);
 }
 
+/**
+ * Returns the user stylesheet of the opened book if `filewise`, or else of all books.
+ */
+export function getUserStyles(filewise: boolean): Promise<string> {
+	const args = { filewise };
+	return invoke<string>("get_user_styles", args);
+}
+
+/**
+ * Saves the user stylesheet, applied from the next page loaded.
+ */
+export function setUserStyles(filewise: boolean, content: string): Promise<void> {
+	const args = { filewise, content };
+	return invoke("set_user_styles", args);
+}
+
 export function openEpub(path: string): Promise<AboutPub> {
 	const args = { path };
 	return invoke<AboutPubJson>("open_epub", args).then(aboutPubFromJson);
@@ -48,6 +64,152 @@ export function reloadBook(): Promise<AboutPub> {
 	return invoke<AboutPubJson>("reload_book").then(aboutPubFromJson);
 }
 
+/**
+ * Returns the note at `url` as an XHTML fragment, or null if there is none.
+ */
+export function getNote(url: URL): Promise<string | null> {
+	const args = { url };
+	return invoke<string | null>("get_note", args);
+}
+
+/**
+ * Open a link to an external site in the system browser, after confirming if so set.
+ */
+export function openExternalLink(url: URL): Promise<void> {
+	const args = { url };
+	return invoke<void>("open_external_link", args);
+}
+
+export type Diagnostics = {
+	remoteDeclared: URL[];
+	remoteBlocked: URL[];
+};
+
+export function getDiagnostics(): Promise<Diagnostics> {
+	return invoke<{ remoteDeclared: string[]; remoteBlocked: string[] }>("get_diagnostics").then(
+		json => ({
+			remoteDeclared: json.remoteDeclared.map(url => new URL(url)),
+			remoteBlocked: json.remoteBlocked.map(url => new URL(url)),
+		}),
+	);
+}
+
+export type RuleMatches = {
+	find: string;
+	replace: string;
+	regex: boolean;
+	/** null if the rule can't match, e.g., an invalid regular expression. */
+	count: number | null;
+};
+
+/**
+ * Returns how many times each replace rule matches in the document at `url`.
+ */
+export function previewRules(url: URL): Promise<RuleMatches[]> {
+	const args = { url };
+	return invoke<RuleMatches[]>("preview_rules", args);
+}
+
+/**
+ * Let the opened book load resources on `host` through the proxy.
+ */
+export function allowRemoteHost(host: string): Promise<void> {
+	const args = { host };
+	return invoke<void>("allow_remote_host", args);
+}
+
+export type EmbeddedFont = {
+	family: string;
+	weight: string | null;
+	style: string | null;
+	url: URL;
+	/** Size of the font file in bytes, null if not in the book. */
+	size: number | null;
+	disabled: boolean;
+};
+
+export type PublisherFonts = {
+	families: string[];
+	embedded: EmbeddedFont[];
+};
+
+/**
+ * Returns the font families used, and the fonts embedded, by the publisher in the opened
+ * book.
+ */
+export function listPublisherFonts(): Promise<PublisherFonts> {
+	type EmbeddedFontJson = Omit<EmbeddedFont, "url"> & { url: string };
+	type Json = { families: string[]; embedded: EmbeddedFontJson[] };
+	return invoke<Json>("list_publisher_fonts").then(json => ({
+		families: json.families,
+		embedded: json.embedded.map(font => ({ ...font, url: new URL(font.url) })),
+	}));
+}
+
+/**
+ * Drops the `@font-face` rules of the font at `url` in the opened book if `disabled`, or
+ * else restores them.
+ */
+export function setFontDisabled(url: URL, disabled: boolean): Promise<void> {
+	const args = { url, disabled };
+	return invoke("set_font_disabled", args);
+}
+
+/**
+ * Returns the font families installed.
+ */
+export function listSystemFonts(): Promise<string[]> {
+	return invoke<string[]>("list_system_fonts");
+}
+
+/**
+ * Returns the local font families by lowercase publisher font family, with those of the
+ * opened book over those of all books.
+ */
+export function getFontSubstitutes(): Promise<Record<string, string>> {
+	return invoke<Record<string, string>>("get_font_substitutes");
+}
+
+/**
+ * Substitutes `local` for the publisher font `family` in the opened book if `filewise`, or
+ * else in all books. A null `local` removes the substitute.
+ */
+export function setFontSubstitute(
+	filewise: boolean,
+	family: string,
+	local: string | null,
+): Promise<void> {
+	const args = { filewise, family, local };
+	return invoke("set_font_substitute", args);
+}
+
+/**
+ * Whether to read the spine as one scrolling document, as set for the book or as it asks.
+ */
+export function getContinuousScroll(): Promise<boolean> {
+	return invoke("get_continuous_scroll");
+}
+
+export type DocumentPart = {
+	/** URL of the part, with the hash of the URL asked for. */
+	url: URL;
+	index: number;
+	count: number;
+};
+
+/**
+ * Returns the part of the document that `url` points to, when a very large document is
+ * split. Documents served whole are their only part.
+ */
+export function getDocumentPart(url: URL): Promise<DocumentPart> {
+	const args = { url };
+	type Json = Omit<DocumentPart, "url"> & { url: string };
+	return invoke<Json>("get_document_part", args).then(json => ({
+		...json,
+		url: new URL(json.url),
+	}));
+}
+
 export function setReadingPosition(url: URL, percentage: number): Promise<void> {
 	const args = { url, percentage };
 	return invoke<void>("set_reading_position", args);
@@ -79,8 +241,46 @@ export function setMenuHandlerFotFileNavigate(handler: () => any): void {
 	getCurrentWebviewWindow().listen("menu/f_n", handler);
 }
 
-export function setMenuHandlerForViewFontPrefers(
-	handler: (fontPrefer: FontPrefer) => any,
-): void {
-	getCurrentWebviewWindow().listen<FontPrefer>("menu/v_fp", event => handler(event.payload));
+export function setMenuHandlerForViewFontPrefers(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_fp", handler);
+}
+
+export function setMenuHandlerForViewTheme(handler: (theme: Theme) => any): void {
+	getCurrentWebviewWindow().listen<Theme>("menu/v_t", event => handler(event.payload));
+}
+
+export function setMenuHandlerForViewPublisherStyles(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_ps", handler);
+}
+
+export function setMenuHandlerForViewHorizontalLayout(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_hl", handler);
+}
+
+export function setMenuHandlerForViewRuby(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_r", handler);
+}
+
+export function setMenuHandlerForViewChineseScript(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_cs", handler);
+}
+
+export function setMenuHandlerForViewReadingEmphasis(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_re", handler);
+}
+
+export function setMenuHandlerForViewScriptsInSandbox(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_sis", handler);
+}
+
+export function setMenuHandlerForViewDownscaleImages(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_di", handler);
+}
+
+export function setMenuHandlerForViewHyphenation(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_h", handler);
+}
+
+export function setMenuHandlerForViewContinuousScroll(handler: () => any): void {
+	getCurrentWebviewWindow().listen("menu/v_csc", handler);
 }
@@ -1,12 +1,20 @@
 import { BaseModal, ModalCoordinator } from "./base";
 
 import { AboutPub, EpubMetadataItem, PubHelper, setElementUrl } from "../base";
+import { getGlobalContext } from "../context";
 import * as rs from "../invoke";
 
 export class DetailsModal extends BaseModal {
 	#bookDl: HTMLDListElement;
 	#fileDl: HTMLDListElement;
+	#diagnosticsDl: HTMLDListElement;
+	#fontsDl: HTMLDListElement;
+	#embeddedFontsDl: HTMLDListElement;
 	#coverImg: HTMLImageElement;
+	#onRemoteHostAllowed: () => any = () => {};
+	#getPageUrl: () => URL | null = () => null;
+	#onFontsChanged: () => any = () => {};
+	#onEmbeddedFontsChanged: () => any = () => {};
 
 	init(pub: AboutPub, pubHelper: PubHelper): void {
 		this.locked = false;
@@ -22,6 +30,9 @@ export class DetailsModal extends BaseModal {
 		this.#bookDl.replaceChildren(
 			...pub.pubMetadata.flatMap(item => createDetailsDlItemRich(item, pubHelper.lang)),
 		);
+		if (pub.pubVertical) {
+			this.#bookDl.append(...createDetailsDlItem("Writing mode", "Vertical"));
+		}
 
 		this.#fileDl.replaceChildren();
 		this.#fileDl.append(...createDetailsDlItem("Path", pub.filePath));
@@ -41,7 +52,183 @@ export class DetailsModal extends BaseModal {
 	}
 
 	show(): void {
-		ModalCoordinator.show(this);
+		if (ModalCoordinator.show(this)) {
+			this.refreshFonts();
+			this.refreshDiagnostics();
+		}
+	}
+
+	/**
+	 * Set what to do after the user lets the book load resources from a host.
+	 */
+	setupRemoteHostAllowed(fn: () => any): void {
+		this.#onRemoteHostAllowed = fn;
+	}
+
+	/**
+	 * Set how to get the URL of the page being read, to count replace rule matches in.
+	 */
+	setupPageUrl(fn: () => URL | null): void {
+		this.#getPageUrl = fn;
+	}
+
+	/**
+	 * Set what to do after the user substitutes a local font for a publisher font.
+	 */
+	setupFontsChanged(fn: () => any): void {
+		this.#onFontsChanged = fn;
+	}
+
+	/**
+	 * Set what to do after the user disables or enables an embedded font.
+	 */
+	setupEmbeddedFontsChanged(fn: () => any): void {
+		this.#onEmbeddedFontsChanged = fn;
+	}
+
+	async refreshFonts(): Promise<void> {
+		let fonts: rs.PublisherFonts, systemFonts: string[], substitutes: Record<string, string>;
+		let appSubstitutes: Record<string, string> | undefined;
+		try {
+			[fonts, systemFonts, substitutes, appSubstitutes] = await Promise.all([
+				rs.listPublisherFonts(),
+				rs.listSystemFonts().catch(err => {
+					console.error("Error listing system fonts:", err);
+					return [];
+				}),
+				rs.getFontSubstitutes(),
+				getGlobalContext().prefsStore?.get<Record<string, string>>("font.substitute"),
+			]);
+		} catch (err) {
+			console.error("Error loading fonts:", err);
+			return;
+		}
+
+		this.#fontsDl.replaceChildren();
+		for (const family of fonts.families) {
+			const [dt, dd] = createDetailsDlItem(family, "");
+			const select = document.createElement("select");
+			select.append(new Option("Publisher font", ""));
+			const key = family.toLowerCase();
+			const substitute = substitutes[key];
+			for (const local of systemFonts) {
+				select.append(new Option(local, local, false, local == substitute));
+			}
+			if (substitute && !systemFonts.includes(substitute)) {
+				select.append(new Option(substitute, substitute, false, true));
+			}
+
+			// where the substitute is set, the book or the app
+			const label = document.createElement("label");
+			const allBooks = document.createElement("input");
+			allBooks.type = "checkbox";
+			allBooks.checked = substitute != null && appSubstitutes?.[key] == substitute;
+			label.append(allBooks, " All books");
+
+			select.onchange = async () => {
+				if (allBooks.checked) {
+					// the one of the book would win
+					await rs.setFontSubstitute(true, family, null);
+				}
+				await rs.setFontSubstitute(!allBooks.checked, family, select.value || null);
+				this.#onFontsChanged();
+			};
+			allBooks.onchange = async () => {
+				if (select.value) {
+					await rs.setFontSubstitute(allBooks.checked, family, null);
+					await rs.setFontSubstitute(!allBooks.checked, family, select.value);
+					this.#onFontsChanged();
+				}
+			};
+			dd.append(select, " ", label);
+			this.#fontsDl.append(dt, dd);
+		}
+		if (!this.#fontsDl.hasChildNodes()) {
+			this.#fontsDl.append(...createDetailsDlItem("No fonts specified", ""));
+		}
+
+		this.#embeddedFontsDl.replaceChildren();
+		for (const font of fonts.embedded) {
+			const face = [font.weight, font.style].filter(value => value).join(" ");
+			const size = font.size != null ? ` (${font.size.toLocaleString()} bytes)` : "";
+			const [dt, dd] = createDetailsDlItem(
+				face ? `${font.family} ${face}` : font.family,
+				`${font.url.href}${size}`,
+			);
+			const label = document.createElement("label");
+			const enabled = document.createElement("input");
+			enabled.type = "checkbox";
+			enabled.checked = !font.disabled;
+			enabled.onchange = async () => {
+				await rs.setFontDisabled(font.url, !enabled.checked);
+				this.#onEmbeddedFontsChanged();
+			};
+			label.append(enabled, " Enabled");
+			dt.append(" ", label);
+			this.#embeddedFontsDl.append(dt, dd);
+		}
+		if (!this.#embeddedFontsDl.hasChildNodes()) {
+			this.#embeddedFontsDl.append(...createDetailsDlItem("No fonts embedded", ""));
+		}
+	}
+
+	async refreshDiagnostics(): Promise<void> {
+		let diagnostics: rs.Diagnostics;
+		try {
+			diagnostics = await rs.getDiagnostics();
+		} catch (err) {
+			console.error("Error loading diagnostics:", err);
+			return;
+		}
+
+		this.#diagnosticsDl.replaceChildren();
+		if (diagnostics.remoteDeclared.length > 0) {
+			const urls = diagnostics.remoteDeclared.map(url => url.href).join("\n");
+			this.#diagnosticsDl.append(...createDetailsDlItem("Remote resources", urls));
+		}
+
+		// blocked resources, by host
+		const blocked = new Map<string, URL[]>();
+		for (const url of diagnostics.remoteBlocked) {
+			blocked.set(url.host, [...(blocked.get(url.host) ?? []), url]);
+		}
+		for (const [host, urls] of blocked) {
+			const [dt, dd] = createDetailsDlItem(
+				`Blocked from ${host}`,
+				urls.map(url => url.href).join("\n"),
+			);
+			const allow = document.createElement("button");
+			allow.type = "button";
+			allow.textContent = "Allow";
+			allow.onclick = async () => {
+				await rs.allowRemoteHost(host);
+				this.#onRemoteHostAllowed();
+				this.refreshDiagnostics();
+			};
+			dt.append(" ", allow);
+			this.#diagnosticsDl.append(dt, dd);
+		}
+
+		if (!this.#diagnosticsDl.hasChildNodes()) {
+			this.#diagnosticsDl.append(...createDetailsDlItem("No problems found", ""));
+		}
+
+		const pageUrl = this.#getPageUrl();
+		let matches: rs.RuleMatches[] = [];
+		try {
+			matches = pageUrl ? await rs.previewRules(pageUrl) : [];
+		} catch (err) {
+			console.error("Error counting replace rule matches:", err);
+		}
+		for (const rule of matches) {
+			const count =
+				rule.count == null
+					? "Invalid rule"
+					: `${rule.count.toLocaleString()} matches in this chapter`;
+			this.#diagnosticsDl.append(
+				...createDetailsDlItem(`Replace ${rule.find} with ${rule.replace}`, count),
+			);
+		}
 	}
 
 	private constructor() {
@@ -49,6 +236,11 @@ export class DetailsModal extends BaseModal {
 		this.locked = true;
 		this.#bookDl = document.getElementById("og-details-book") as HTMLDListElement;
 		this.#fileDl = document.getElementById("og-details-file") as HTMLDListElement;
+		this.#diagnosticsDl = document.getElementById("og-details-diagnostics") as HTMLDListElement;
+		this.#fontsDl = document.getElementById("og-details-fonts") as HTMLDListElement;
+		this.#embeddedFontsDl = document.getElementById(
+			"og-details-embedded-fonts",
+		) as HTMLDListElement;
 		this.#coverImg = document.getElementById("og-details-cover") as HTMLImageElement;
 
 		ModalCoordinator.modals["details"] = this;
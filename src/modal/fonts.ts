@@ -0,0 +1,113 @@
+import { BaseModal, ModalCoordinator } from "./base";
+
+import { FontPrefer } from "../base";
+import { getGlobalContext } from "../context";
+import * as rs from "../invoke";
+
+/**
+ * Font stacks by BCP 47 language tag or script subtag. Keep in sync with
+ * `prefs::FONT_STACKS`.
+ */
+type FontStacks = Record<string, string[]>;
+
+export class FontsModal extends BaseModal {
+	#preferSelect: HTMLSelectElement;
+	#stacksBody: HTMLTableSectionElement;
+	#onChanged: () => any = () => {};
+
+	async show(): Promise<void> {
+		const prefs = getGlobalContext().prefsStore;
+		if (!prefs) return;
+
+		const [prefer, stacks] = await Promise.all([
+			prefs.get<FontPrefer>("font.prefer"),
+			prefs.get<FontStacks>("font.stacks"),
+		]);
+		this.#preferSelect.value = prefer ?? "";
+		this.#stacksBody.replaceChildren(
+			...Object.entries(stacks ?? {}).map(([lang, stack]) => createStackRow(lang, stack)),
+		);
+		ModalCoordinator.show(this);
+	}
+
+	/**
+	 * Set what to do after the user saves font preferences.
+	 */
+	setupChanged(fn: () => any): void {
+		this.#onChanged = fn;
+	}
+
+	async #save(): Promise<void> {
+		const prefs = getGlobalContext().prefsStore;
+		if (!prefs) return;
+
+		const stacks: FontStacks = {};
+		for (const row of this.#stacksBody.rows) {
+			const [langInput, stackInput] = row.querySelectorAll("input");
+			const lang = langInput.value.trim();
+			const stack = stackInput.value
+				.split(",")
+				.map(family => family.trim().replace(/^["']|["']$/g, ""))
+				.filter(family => family);
+			if (lang && stack.length > 0) {
+				stacks[lang] = stack;
+			}
+		}
+		await prefs.set("font.prefer", this.#preferSelect.value || null);
+		await prefs.set("font.stacks", stacks);
+		await prefs.save();
+		this.#onChanged();
+	}
+
+	private constructor() {
+		super(document.getElementById("og-fonts-modal") as HTMLDialogElement);
+		this.#preferSelect = document.getElementById("og-fonts-prefer") as HTMLSelectElement;
+		const table = document.getElementById("og-fonts-stacks") as HTMLTableElement;
+		this.#stacksBody = table.tBodies[0];
+
+		const addBtn = document.getElementById("og-fonts-add") as HTMLButtonElement;
+		addBtn.onclick = () => {
+			const row = createStackRow("", []);
+			this.#stacksBody.append(row);
+			row.querySelector("input")?.focus();
+		};
+		this.setOnClose(_ => this.#save());
+
+		ModalCoordinator.modals["fonts"] = this;
+
+		rs.setMenuHandlerForViewFontPrefers(() => this.show());
+	}
+
+	// Singleton
+	private static self?: FontsModal;
+	static get(): FontsModal {
+		if (!FontsModal.self) FontsModal.self = new FontsModal();
+		return FontsModal.self;
+	}
+}
+
+function createStackRow(lang: string, stack: string[]): HTMLTableRowElement {
+	const row = document.createElement("tr");
+
+	const langInput = document.createElement("input");
+	langInput.value = lang;
+	langInput.placeholder = "ja, zh-Hant, Hans";
+	langInput.spellcheck = false;
+
+	const stackInput = document.createElement("input");
+	stackInput.value = stack.join(", ");
+	stackInput.placeholder = "Noto Serif JP, Yu Mincho";
+	stackInput.spellcheck = false;
+
+	const removeBtn = document.createElement("button");
+	removeBtn.type = "button";
+	removeBtn.textContent = "Remove";
+	removeBtn.onclick = () => row.remove();
+
+	for (const elem of [langInput, stackInput, removeBtn]) {
+		const td = document.createElement("td");
+		td.append(elem);
+		row.append(td);
+	}
+	return row;
+}
@@ -1,3 +1,4 @@
 export { DetailsModal } from "./details";
+export { FontsModal } from "./fonts";
 export { NavModal } from "./nav";
 export { PreviewModal } from "./preview";
@@ -2,6 +2,7 @@ import { BaseModal, ModalCoordinator } from "./base";
 
 export class PreviewModal extends BaseModal {
 	#contentDiv: HTMLElement;
+	#goThereBtn: HTMLButtonElement;
 
 	show(floatingContentRoot: HTMLElement, resolvedLang: string): void {
 		if (ModalCoordinator.show(this)) {
@@ -10,13 +11,18 @@ export class PreviewModal extends BaseModal {
 		}
 	}
 
-	setupGoThere(navigate: () => any): void {
-		this.setOnClose(_ => navigate());
+	/**
+	 * Set where "Go there" leads, or hide it if null.
+	 */
+	setupGoThere(navigate: (() => any) | null): void {
+		this.#goThereBtn.hidden = navigate == null;
+		this.setOnClose(_ => navigate?.());
 	}
 
 	private constructor() {
 		super(document.getElementById("og-preview-modal") as HTMLDialogElement);
 		this.#contentDiv = document.getElementById("og-preview-div") as HTMLDivElement;
+		this.#goThereBtn = document.getElementById("og-preview-go-there") as HTMLButtonElement;
 
 		ModalCoordinator.modals["preview"] = this;
 	}
@@ -1,9 +1,16 @@
 import {
-	fetchXml,
+	CONTINUOUS_DOCUMENT_URL,
+	createSandboxedFrame,
+	fetchContentDocument,
 	FilewiseStyles,
 	markSessionInProgress,
 	setElementUrl,
+	stitchedDocumentUrl,
+	stitchedId,
 	TaskRepeater,
+	TextPosition,
+	textPositionLength,
+	urlWithTextPosition,
 } from "./base";
 import { FilewiseStylesEditor } from "./filewise";
 import * as rs from "./invoke";
@@ -14,6 +21,7 @@ class ReaderDomContext {
 	#host: HTMLElement;
 	readonly shadowRoot: ShadowRoot;
 	#clickEventHandler: ((event: Event) => any) | null = null;
+	#scrollEventHandler: ((event: Event) => any) | null = null;
 
 	constructor() {
 		this.#host = document.getElementById("og-reader-host") as HTMLDivElement;
@@ -46,6 +54,52 @@ class ReaderDomContext {
 		return percentage * content.height - box.height / 5;
 	}
 
+	/**
+	 * Like `getViewPercentage`, through `section` of the content.
+	 */
+	getViewPercentageIn(section: HTMLElement): number {
+		const box = this.#hostRect;
+		const rect = section.getBoundingClientRect();
+		return (box.top + box.height / 5 - rect.top) / rect.height;
+	}
+
+	/**
+	 * The last of `sections`, in document order, starting above the height of
+	 * `getViewOffsetPx`.
+	 */
+	findAtViewLine(sections: HTMLElement[]): HTMLElement | null {
+		const box = this.#hostRect;
+		const lineY = box.top + box.height / 5;
+		let found: HTMLElement | null = null;
+		for (const section of sections) {
+			if (section.getBoundingClientRect().top > lineY) break;
+			found = section;
+		}
+		return found;
+	}
+
+	/**
+	 * Whether `section` is within a screen of the view.
+	 */
+	isNearView(section: HTMLElement): boolean {
+		const box = this.#hostRect;
+		const rect = section.getBoundingClientRect();
+		return rect.bottom >= box.top - box.height && rect.top <= box.bottom + box.height;
+	}
+
+	/**
+	 * Fill `section` with `nodes`. If it is above the view, scrolls by how much it grows, for
+	 * the view to stay on the same text.
+	 */
+	fillKeepingView(section: HTMLElement, nodes: Iterable<Node>): void {
+		const before = section.getBoundingClientRect();
+		const isAbove = before.top < this.#hostRect.top;
+		section.replaceChildren(...nodes);
+		if (isAbove) {
+			this.#host.scrollTop += section.getBoundingClientRect().height - before.height;
+		}
+	}
+
 	getElement(id: string): HTMLElement | null {
 		return this.shadowRoot.getElementById(id);
 	}
@@ -65,6 +119,110 @@ class ReaderDomContext {
 		this.#host.scroll({ top, behavior: "instant" });
 	}
 
+	/**
+	 * Like `scrollToPercentage`, through `section` of the content. Doesn't scroll above it.
+	 */
+	scrollToPercentageIn(section: HTMLElement, percentage: number): void {
+		const box = this.#hostRect;
+		const rect = section.getBoundingClientRect();
+		const offset = Math.max(percentage * rect.height - box.height / 5, 0);
+		const top = this.#host.scrollTop + rect.top - box.top + offset;
+		this.#host.scroll({ top, behavior: "instant" });
+	}
+
+	/**
+	 * Walks the text of `root` as counted in a `TextPosition`, without generated readings.
+	 */
+	#textWalker(root: Node): TreeWalker {
+		return document.createTreeWalker(root, NodeFilter.SHOW_TEXT, node =>
+			node.parentElement?.closest("rt[data-og-ruby]")
+				? NodeFilter.FILTER_REJECT
+				: NodeFilter.FILTER_ACCEPT,
+		);
+	}
+
+	/**
+	 * The text at the same height as `getViewOffsetPx`, in the innermost anchored element there.
+	 */
+	getTextPosition(): TextPosition | null {
+		const box = this.#hostRect;
+		const lineY = box.top + box.height / 5;
+		let target: HTMLElement | null = null;
+		// descendants come after their ancestors
+		for (const elem of this.shadowRoot.querySelectorAll<HTMLElement>("[data-og-id]")) {
+			const rect = elem.getBoundingClientRect();
+			if (rect.top <= lineY && lineY < rect.bottom) {
+				target = elem;
+			}
+		}
+		if (target == null) return null;
+
+		let offset = 0;
+		const walker = this.#textWalker(target);
+		const range = document.createRange();
+		for (let node = walker.nextNode(); node; node = walker.nextNode()) {
+			const text = node.textContent ?? "";
+			range.selectNodeContents(node);
+			if (range.getBoundingClientRect().bottom <= lineY) {
+				offset += textPositionLength(text);
+				continue;
+			}
+			// first char of the text node on the line or below it
+			let [low, high] = [0, text.length];
+			while (low < high) {
+				const mid = Math.floor((low + high) / 2);
+				range.setStart(node, mid);
+				range.setEnd(node, mid + 1);
+				if (range.getBoundingClientRect().bottom <= lineY) {
+					low = mid + 1;
+				} else {
+					high = mid;
+				}
+			}
+			offset += textPositionLength(text.substring(0, low));
+			break;
+		}
+		return { anchor: target.dataset.ogId!, offset };
+	}
+
+	/**
+	 * Scrolls to `position`, in `scope` if anchors repeat, e.g., a section of the continuous
+	 * document.
+	 */
+	scrollToTextPosition(position: TextPosition, scope: ParentNode = this.shadowRoot): void {
+		const target = scope.querySelector<HTMLElement>(
+			`[data-og-id="${position.anchor}"]`,
+		);
+		if (target == null) return;
+
+		let remaining = position.offset;
+		const walker = this.#textWalker(target);
+		for (let node = walker.nextNode(); node; node = walker.nextNode()) {
+			const text = node.textContent ?? "";
+			const length = textPositionLength(text);
+			if (remaining >= length) {
+				remaining -= length;
+				continue;
+			}
+			// index in `text`, skipping soft hyphens
+			let index = 0;
+			for (let counted = 0; index < text.length; index++) {
+				if (text[index] == "\u00AD") continue;
+				if (counted == remaining) break;
+				counted++;
+			}
+			const range = document.createRange();
+			range.setStart(node, index);
+			range.setEnd(node, Math.min(index + 1, text.length));
+			const box = this.#hostRect;
+			const top =
+				this.#host.scrollTop + range.getBoundingClientRect().top - box.top - box.height / 5;
+			this.#host.scroll({ top, behavior: "instant" });
+			return;
+		}
+		target.scrollIntoView();
+	}
+
 	resetContent(): void {
 		this.shadowRoot.replaceChildren();
 	}
@@ -86,8 +244,24 @@ class ReaderDomContext {
 		}
 		this.#clickEventHandler = listener;
 	}
+
+	set handleScrollEvent(listener: ((event: Event) => any) | null) {
+		if (this.#scrollEventHandler != null) {
+			this.#host.removeEventListener("scroll", this.#scrollEventHandler);
+		}
+
+		if (listener != null) {
+			this.#host.addEventListener("scroll", listener);
+		}
+		this.#scrollEventHandler = listener;
+	}
 }
 
+/**
+ * Where to scroll a page to: a percentage, an element ID, or a text position.
+ */
+export type PageLocation = number | string | TextPosition | null;
+
 /**
  * Responsibility lies within host's shadow root.
  * In fact, this class doesn't need to know what EPUB is being read.
@@ -98,30 +272,61 @@ export class Reader {
 	saveReadingProgressTask: TaskRepeater;
 	styler: Styler;
 	pageLang: string = "";
+	/** Whether pages of the spine open in the continuous document, see `openContinuous`. */
+	continuous: boolean = false;
+	#opened: { url: URL; pubLang: string; continuous: boolean } | null = null;
+	/** Sections of the continuous document, one for each document of the spine, in order. */
+	#sections: HTMLElement[] = [];
+	#loadedSections = new Map<HTMLElement, Promise<void>>();
+	#pageChangedHandler: ((url: URL) => any) | null = null;
 
-	async open(url: URL, percentageOrId: number | string | null, pubLang: string): Promise<void> {
+	async open(url: URL, location: PageLocation, pubLang: string): Promise<void> {
 		this.domContext.resetContent();
+		this.domContext.handleScrollEvent = null;
 		NavModal.get().stopClosestNavPointTask();
+		this.#opened = { url, pubLang, continuous: false };
+		this.#sections = [];
 
-		const doc = await fetchXml(url, true);
-		this.pageLang = doc.documentElement.lang;
+		const [doc, sandboxed] = await fetchContentDocument(url);
+		this.pageLang = doc.documentElement.lang ?? "";
 		this.domContext.lang = this.pageLang || pubLang;
+		if (sandboxed) {
+			// the frame has the document to itself, out of reach of the reader
+			const body = document.createElement("body");
+			body.style.cssText = "margin: 0; height: 100%;";
+			body.append(createSandboxedFrame(url));
+			this.domContext.append(body);
+			markSessionInProgress();
+			return;
+		}
 
-		await this.processStyles(doc.head, url);
-		const body = doc.body;
+		let body = doc.body;
+		if (doc.documentElement instanceof SVGSVGElement) {
+			// SVG documents have no head, their styles are inside
+			body = document.createElement("body");
+			body.append(doc.documentElement);
+		} else {
+			await this.processStyles(doc.head, url);
+		}
 		this.processImages(body, url);
 		this.processAnchors(body, url);
 
 		this.domContext.append(body);
-		if (typeof percentageOrId == "string") {
-			this.domContext.scrollToElement(percentageOrId);
-		} else if (percentageOrId) {
-			this.domContext.scrollToPercentage(percentageOrId);
+		if (typeof location == "string") {
+			this.domContext.scrollToElement(location);
+		} else if (typeof location == "number") {
+			this.domContext.scrollToPercentage(location);
+		} else if (location) {
+			this.domContext.scrollToTextPosition(location);
 		}
 
 		this.saveReadingProgressTask.restart(() => {
 			const percentage = this.calculatePercentage();
-			return rs.setReadingPosition(url, percentage);
+			const position = this.domContext.getTextPosition();
+			return rs.setReadingPosition(
+				position ? urlWithTextPosition(url, position) : url,
+				percentage,
+			);
 		});
 
 		NavModal.get().restartClosestNavPointTask(
@@ -133,6 +338,195 @@ export class Reader {
 		markSessionInProgress();
 	}
 
+	/**
+	 * Show the page at `url` in the continuous document of the spine, opening that first if
+	 * not yet. Pages are loaded into their sections as they come near the view.
+	 * Pages off the spine are opened on their own.
+	 */
+	async openContinuous(url: URL, location: PageLocation, pubLang: string): Promise<void> {
+		// documents are stitched whole
+		url = new URL(url);
+		url.search = "";
+
+		if (!this.#opened?.continuous) {
+			await this.#openContinuousDocument(pubLang);
+		}
+		const index = this.#sections.findIndex(section => section.dataset.ogChapter == url.href);
+		const section = this.#sections[index];
+		if (section == undefined) {
+			await this.open(url, location, pubLang);
+			return;
+		}
+		this.#opened = { url, pubLang, continuous: true };
+		this.#pageChangedHandler?.(url);
+
+		await this.#loadSection(section);
+		if (typeof location == "string") {
+			this.domContext.scrollToElement(stitchedId(index, location));
+		} else if (typeof location == "number") {
+			this.domContext.scrollToPercentageIn(section, location);
+		} else if (location) {
+			this.domContext.scrollToTextPosition(location, section);
+		} else {
+			section.scrollIntoView();
+		}
+		this.#restartClosestNavPointTask(url, index);
+		await this.#loadSectionsNearView();
+	}
+
+	async #openContinuousDocument(pubLang: string): Promise<void> {
+		this.domContext.resetContent();
+		NavModal.get().stopClosestNavPointTask();
+		this.#sections = [];
+		this.#loadedSections = new Map();
+
+		const [doc] = await fetchContentDocument(CONTINUOUS_DOCUMENT_URL);
+		this.pageLang = doc.documentElement.lang ?? "";
+		this.domContext.lang = this.pageLang || pubLang;
+		await this.processStyles(doc.head, CONTINUOUS_DOCUMENT_URL);
+		const body = doc.body;
+		this.#sections = Array.from(body.querySelectorAll<HTMLElement>("section[data-og-chapter]"));
+		this.domContext.append(body);
+
+		this.domContext.handleScrollEvent = () => this.#loadSectionsNearView();
+		this.saveReadingProgressTask.restart(() => this.#saveContinuousReadingProgress());
+		markSessionInProgress();
+	}
+
+	/**
+	 * Fill `section` with the body of its page, once.
+	 */
+	#loadSection(section: HTMLElement): Promise<void> {
+		let loaded = this.#loadedSections.get(section);
+		if (loaded == undefined) {
+			loaded = this.#fillSection(section).catch(err => {
+				console.error(`Error loading ${section.dataset.ogChapter}:`, err);
+			});
+			this.#loadedSections.set(section, loaded);
+		}
+		return loaded;
+	}
+
+	async #fillSection(section: HTMLElement): Promise<void> {
+		const url = new URL(section.dataset.ogChapter!);
+		const [doc] = await fetchContentDocument(stitchedDocumentUrl(url));
+		let body = doc.body;
+		if (doc.documentElement instanceof SVGSVGElement) {
+			body = document.createElement("body");
+			body.append(doc.documentElement);
+		}
+		this.#linkSections(body, url);
+		this.processImages(body, url);
+		this.processAnchors(body, url);
+
+		// the section stands for the root and body of the page
+		if (doc.documentElement.lang) section.lang = doc.documentElement.lang;
+		section.classList.add(...body.classList);
+		this.domContext.fillKeepingView(section, Array.from(body.childNodes));
+	}
+
+	/**
+	 * Load the sections next to loaded ones, while they are near the view.
+	 */
+	async #loadSectionsNearView(): Promise<void> {
+		const sections = this.#sections;
+		for (;;) {
+			const next = sections.find(
+				(section, i) =>
+					!this.#loadedSections.has(section) &&
+					(this.#loadedSections.has(sections[i - 1]) ||
+						this.#loadedSections.has(sections[i + 1])) &&
+					this.domContext.isNearView(section),
+			);
+			if (next == undefined || sections != this.#sections) return;
+			await this.#loadSection(next);
+		}
+	}
+
+	/**
+	 * Point links to other pages of the spine to their elements in the continuous document, as
+	 * the backend does for links within a page.
+	 */
+	#linkSections(body: HTMLElement, pageUrl: URL): void {
+		for (const elem of body.querySelectorAll<HTMLAnchorElement>("a[href]")) {
+			const href = elem.getAttribute("href")!;
+			const url = URL.parse(href, pageUrl);
+			if (href.startsWith("#") || !url?.hash) continue;
+			const id = url.hash.slice(1);
+			url.hash = "";
+			const index = this.#sections.findIndex(section => section.dataset.ogChapter == url.href);
+			if (index >= 0) {
+				elem.setAttribute("href", `#${stitchedId(index, id)}`);
+			}
+		}
+	}
+
+	/**
+	 * Save the position in the page in view, under its own URL, noticing when the page changes.
+	 */
+	#saveContinuousReadingProgress(): Promise<void> | undefined {
+		const section = this.domContext.findAtViewLine(this.#sections);
+		if (section == null || this.#opened == null) return;
+		const url = new URL(section.dataset.ogChapter!);
+		if (url.href != this.#opened.url.href) {
+			this.#opened.url = url;
+			this.#restartClosestNavPointTask(url, this.#sections.indexOf(section));
+			this.#pageChangedHandler?.(url);
+		}
+
+		const percentage = this.domContext.getViewPercentageIn(section);
+		const position = this.domContext.getTextPosition();
+		return rs.setReadingPosition(
+			position ? urlWithTextPosition(url, position) : url,
+			percentage,
+		);
+	}
+
+	#restartClosestNavPointTask(url: URL, index: number): void {
+		NavModal.get().restartClosestNavPointTask(
+			url,
+			() => this.domContext.getViewOffsetPx(),
+			id => this.domContext.getElementOffsetPx(stitchedId(index, id)),
+		);
+	}
+
+	/**
+	 * Set what to do when another page comes into view in the continuous document.
+	 */
+	setupPageChanged(handler: (url: URL) => any): void {
+		this.#pageChangedHandler = handler;
+	}
+
+	/**
+	 * URL of the page in view, which changes as the continuous document scrolls.
+	 */
+	get viewUrl(): URL | null {
+		const section = this.#opened?.continuous
+			? this.domContext.findAtViewLine(this.#sections)
+			: null;
+		return section ? new URL(section.dataset.ogChapter!) : (this.#opened?.url ?? null);
+	}
+
+	/**
+	 * URL of the page that `elem` is in.
+	 */
+	pageUrlOf(elem: Element): URL | null {
+		const section = elem.closest<HTMLElement>("section[data-og-chapter]");
+		return section ? new URL(section.dataset.ogChapter!) : (this.#opened?.url ?? null);
+	}
+
+	/**
+	 * URL of the element of an ID made by `stitchedId`, in its own page.
+	 */
+	stitchedUrl(id: string): URL | null {
+		const match = /^og-([0-9]+)-(.*)$/.exec(id);
+		const section = match ? this.#sections[Number(match[1])] : undefined;
+		if (!match || section == undefined) return null;
+		const url = new URL(section.dataset.ogChapter!);
+		url.hash = match[2];
+		return url;
+	}
+
 	async processStyles(head: HTMLHeadElement, pageUrl: URL): Promise<void> {
 		// links are inserted to shadow DOM.
 		for (const elemLink of head.querySelectorAll<HTMLLinkElement>('link[rel="stylesheet"]')) {
@@ -176,6 +570,8 @@ export class Reader {
 		for (const elem of body.querySelectorAll<HTMLImageElement>("img")) {
 			const url = URL.parse(elem.src, pageUrl);
 			if (url) {
+				// keep where the image is, for zooming in on the original
+				elem.dataset.ogSrc = url.href;
 				setElementUrl(elem, url);
 			}
 		}
@@ -193,6 +589,13 @@ export class Reader {
 			if (!href) continue;
 			const url = URL.parse(href, pageUrl);
 			if (url) {
+				// keep where notes are, for showing them in pop-ups
+				if (elem.hasAttribute("data-og-noteref")) {
+					elem.dataset.ogNoteref = url.href;
+				}
+				if (elem.dataset.ogLink == "external") {
+					continue;
+				}
 				if (url.hash) {
 					elem.href = url.hash;
 				} else {
@@ -202,8 +605,30 @@ export class Reader {
 		}
 	}
 
+	/**
+	 * Open the current page again at the same position, e.g., after served content changes.
+	 */
+	async reload(): Promise<void> {
+		if (this.#opened == null) return;
+		const { pubLang } = this.#opened;
+		const url = this.viewUrl ?? this.#opened.url;
+		const location = this.domContext.getTextPosition() ?? this.calculatePercentage();
+		if (this.continuous) {
+			// the continuous document too
+			this.#opened = null;
+			await this.openContinuous(url, location, pubLang);
+		} else {
+			await this.open(url, location, pubLang);
+		}
+	}
+
 	calculatePercentage(): number {
-		return this.domContext.getViewPercentage();
+		const section = this.#opened?.continuous
+			? this.domContext.findAtViewLine(this.#sections)
+			: null;
+		return section
+			? this.domContext.getViewPercentageIn(section)
+			: this.domContext.getViewPercentage();
 	}
 
 	getElementById(id: string): HTMLElement | null {
@@ -215,8 +640,37 @@ export class Reader {
 		this.saveReadingProgressTask = new TaskRepeater(2000);
 		this.styler = new Styler(this.domContext.shadowRoot);
 
-		rs.setMenuHandlerForViewFontPrefers(() => {
-			this.styler.loadAppPrefs();
+		rs.setMenuHandlerForViewTheme(() => {
+			// publisher colors are remapped by the backend
+			this.reload();
+		});
+		rs.setMenuHandlerForViewPublisherStyles(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewHorizontalLayout(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewRuby(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewChineseScript(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewReadingEmphasis(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewScriptsInSandbox(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewDownscaleImages(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewHyphenation(() => {
+			this.reload();
+		});
+		rs.setMenuHandlerForViewContinuousScroll(async () => {
+			this.continuous = await rs.getContinuousScroll();
+			this.reload();
 		});
 	}
 
@@ -1,19 +1,20 @@
-import { confirm } from "@tauri-apps/plugin-dialog";
-import { openUrl } from "@tauri-apps/plugin-opener";
-
 import { end_of_spine_message, toc_unavailable_message } from "./strings.json";
 
 import {
 	AboutPub,
 	anchoredSamePageLocation,
+	documentPartUrl,
 	isLocationNear,
+	originalImageUri,
 	PubHelper,
+	setElementUrl,
 	TaskRepeater,
+	textPositionFromUrl,
 	UrlAndPercentage,
 } from "./base";
 import * as rs from "./invoke";
-import { DetailsModal, NavModal, PreviewModal } from "./modal";
-import { Reader } from "./reader";
+import { DetailsModal, FontsModal, NavModal, PreviewModal } from "./modal";
+import { PageLocation, Reader } from "./reader";
 import { FilewiseStylesEditor } from "./filewise";
 
 class ReadScreenDomContext {
@@ -84,6 +85,8 @@ export class ReadScreen {
 	pubHelper: PubHelper;
 	jumpHistory: UrlAndPercentage[];
 	pageUrl: URL;
+	/** Where the page is in its document, if split. */
+	pagePart: rs.DocumentPart | null = null;
 
 	get pageIndexInSpine(): number | undefined {
 		return this.pubHelper.indexInSpine(this.pageUrl);
@@ -105,16 +108,25 @@ export class ReadScreen {
 		this.reader.domContext.handleClickEvent = (event: Event) =>
 			this.handleReaderClickEvent(event);
 
+		// pages come into view as the continuous document scrolls
+		this.reader.setupPageChanged(url => {
+			this.pageUrl = url;
+			this.updateNavBtns();
+		});
+
 		// retrieve reading position
 		this.pageUrl = aboutPub.pubLandingPage; // expected to be updated at once
-		rs.getReadingPosition()
-			.then(result => {
-				let percentage = null;
+		Promise.all([rs.getReadingPosition(), rs.getContinuousScroll().catch(() => false)])
+			.then(([result, continuous]) => {
+				this.reader.continuous = continuous;
+				let location: PageLocation = null;
 				if (result) {
-					this.pageUrl = result[0];
-					percentage = result[1];
+					const [url, percentage] = result;
+					location = textPositionFromUrl(url) ?? percentage;
+					url.hash = "";
+					this.pageUrl = url;
 				}
-				return this.readPage(percentage);
+				return this.readPage(location);
 			})
 			.then(() => {
 				return this.initDetailsAndTocModals();
@@ -132,13 +144,33 @@ export class ReadScreen {
 		this.refreshTocBtnLabelTask.stop();
 	}
 
-	async readPage(percentageOrId: string | number | null): Promise<void> {
+	async readPage(location: PageLocation): Promise<void> {
+		if (this.reader.continuous) {
+			// documents are stitched whole
+			this.pagePart = null;
+			this.updateNavBtns();
+			await this.reader.openContinuous(this.pageUrl, location, this.pubHelper.lang);
+			return;
+		}
+
+		// pages off the manifest have no parts
+		this.pagePart = await rs.getDocumentPart(this.pageUrl).catch(() => null);
+		this.updateNavBtns();
+		await this.reader.open(this.pageUrl, location, this.pubHelper.lang);
+	}
+
+	updateNavBtns(): void {
 		const index = this.pageIndexInSpine;
+		const part = this.pagePart ?? { index: 0, count: 1 };
 		this.domContext.setNavBtnsUsability(
-			index != undefined ? [index > 0, index + 1 < this.pub.pubSpine.length] : null,
+			index != undefined
+				? [
+						index > 0 || part.index > 0,
+						index + 1 < this.pub.pubSpine.length || part.index + 1 < part.count,
+					]
+				: null,
 			this.jumpHistory.length > 0,
 		);
-		await this.reader.open(this.pageUrl, percentageOrId, this.pubHelper.lang);
 	}
 
 	handleKeyEvent(event: KeyboardEvent) {
@@ -178,49 +210,59 @@ export class ReadScreen {
 		}
 		const elemAnchor = event.target.closest("a");
 		if (!elemAnchor) {
+			// zoom in on the image
+			const elemImg = event.target.closest<HTMLImageElement>("img[data-og-src]");
+			const src = URL.parse(elemImg?.dataset.ogSrc ?? "");
+			if (elemImg && src) {
+				this.previewImage(src, elemImg.alt);
+			}
 			return;
 		}
 
 		event.preventDefault();
+		const pageUrl = this.reader.pageUrlOf(elemAnchor) ?? this.pageUrl;
+		const url = URL.parse(elemAnchor.getAttribute("href") ?? "", pageUrl);
+		const link = elemAnchor.dataset.ogLink;
+		if (url && link == "external") {
+			rs.openExternalLink(url).catch(err => {
+				window.alert(`Error opening ${url}: ${err}`);
+			});
+			return;
+		}
+		const noteUrl = URL.parse(elemAnchor.dataset.ogNoteref ?? "");
+		if (noteUrl && noteUrl.pathname != pageUrl.pathname) {
+			this.previewNote(noteUrl);
+			return;
+		}
 		const elemNoteId = anchoredSamePageLocation(elemAnchor);
 		if (elemNoteId) {
 			this.previewSamePageLocation(elemAnchor, elemNoteId);
-		} else {
-			const url = URL.parse(elemAnchor.getAttribute("href") ?? "", this.pageUrl);
-			if (url) {
-				if (
-					url.protocol == "http:" ||
-					url.protocol == "https:" ||
-					url.protocol == "mailto:" ||
-					url.protocol == "tel:"
-				) {
-					// open externally
-					confirm(`Open ${url} using system default application`, {
-						title: "Confirm",
-						kind: "warning",
-					})
-						.then(confirmed => {
-							if (confirmed) {
-								return openUrl(url);
-							}
-						})
-						.catch(err => {
-							window.alert(`Error opening ${url}: ${err}`);
-						});
-				} else {
-					this.jumpTo(url);
-				}
-			}
+		} else if (url && link == "image") {
+			this.previewImage(url, elemAnchor.textContent ?? "");
+		} else if (url) {
+			this.jumpTo(url);
 		}
 	}
 
 	jumpTo(url: URL): void {
-		const id = url.hash.slice(1) || null;
-		url.hash = "";
 		const percentage = this.reader.calculatePercentage();
-		this.jumpHistory.push([this.pageUrl, percentage]);
+		this.jumpHistory.push([this.reader.viewUrl ?? this.pageUrl, percentage]);
+		this.readLocation(url); // don't wait
+	}
+
+	/**
+	 * Read the page at `url`, in the part of the document that its hash points to if split.
+	 */
+	async readLocation(url: URL): Promise<void> {
+		try {
+			if (!this.reader.continuous) url = (await rs.getDocumentPart(url)).url;
+		} catch (err) {
+			console.error(`Error locating ${url}:`, err);
+		}
+		const location = textPositionFromUrl(url) ?? (url.hash.slice(1) || null);
+		url.hash = "";
 		this.pageUrl = url;
-		this.readPage(id); // don't wait
+		await this.readPage(location);
 	}
 
 	jumpBack(): void {
@@ -232,28 +274,54 @@ export class ReadScreen {
 		this.readPage(percentage); // don't wait
 	}
 
-	moveInSpine(forward: boolean): void {
+	async moveInSpine(forward: boolean): Promise<void> {
 		const spine = this.pub.pubSpine;
 		let index = this.pageIndexInSpine;
 		if (index == undefined) {
 			// If not in spine, do nothing.
 			return;
 		}
+		const percentage = forward ? 0.0 : 1.0;
+
+		// through the parts of a split document first
+		const part = this.pagePart;
+		const partIndex = part ? part.index + (forward ? +1 : -1) : -1;
+		if (part && partIndex >= 0 && partIndex < part.count) {
+			this.jumpHistory = [];
+			this.pageUrl = documentPartUrl(this.pageUrl, partIndex);
+			await this.readPage(percentage);
+			return;
+		}
 
 		index += forward ? +1 : -1;
 		if (index < 0 || index >= spine.length) {
 			window.alert(end_of_spine_message);
 			return;
 		}
-		const percentage = forward ? 0.0 : 1.0;
+		let url = spine[index];
+		if (!forward && !this.reader.continuous) {
+			// the last part, if split
+			try {
+				const { count } = await rs.getDocumentPart(url);
+				if (count > 1) url = documentPartUrl(url, count - 1);
+			} catch (err) {
+				console.error(`Error locating ${url}:`, err);
+			}
+		}
 		this.jumpHistory = [];
-		this.pageUrl = spine[index];
-		this.readPage(percentage); // don't wait
+		this.pageUrl = url;
+		await this.readPage(percentage);
 	}
 
 	async initDetailsAndTocModals(): Promise<void> {
 		const detailsModal = DetailsModal.get();
 		detailsModal.init(this.pub, this.pubHelper);
+		detailsModal.setupRemoteHostAllowed(() => this.reader.reload());
+		detailsModal.setupPageUrl(() => this.pageUrl);
+		detailsModal.setupFontsChanged(() => this.reader.styler.loadAppPrefs());
+		detailsModal.setupEmbeddedFontsChanged(() => this.reader.reload());
+		// font stacks are put in content documents by the backend
+		FontsModal.get().setupChanged(() => this.reader.reload());
 
 		const navModal = NavModal.get();
 		try {
@@ -313,9 +381,66 @@ export class ReadScreen {
 		return [clone, elemToPreview];
 	}
 
+	/**
+	 * Show the image at its original size, e.g., linked by an anchor off the spine.
+	 */
+	previewImage(url: URL, alt: string): void {
+		const root = document.createElement("div");
+		const img = document.createElement("img");
+		img.src = originalImageUri(url);
+		img.alt = alt;
+		root.append(img);
+		const modal = PreviewModal.get();
+		modal.show(root, this.reader.pageLang || this.pubHelper.lang);
+		modal.setupGoThere(null);
+	}
+
+	/**
+	 * Show the note in another document, or go there if it can't be found.
+	 */
+	async previewNote(url: URL): Promise<void> {
+		let note: string | null = null;
+		try {
+			note = await rs.getNote(url);
+		} catch (err) {
+			console.error(`Error loading note at ${url}:`, err);
+		}
+		if (!note) {
+			this.jumpTo(url);
+			return;
+		}
+
+		const doc = new DOMParser().parseFromString(note, "application/xhtml+xml");
+		const root = doc.documentElement as HTMLElement;
+		for (const elem of root.querySelectorAll<HTMLImageElement>("img")) {
+			const src = URL.parse(elem.getAttribute("src") ?? "", url);
+			if (src) {
+				setElementUrl(elem, src);
+			}
+		}
+		for (const elem of root.querySelectorAll<HTMLAnchorElement>("a")) {
+			elem.removeAttribute("href");
+		}
+		const lang = (root.firstElementChild as HTMLElement | null)?.lang;
+		const modal = PreviewModal.get();
+		modal.show(root, lang || this.pubHelper.lang);
+		modal.setupGoThere(() => this.jumpTo(url));
+	}
+
 	previewSamePageLocation(anchor: HTMLElement, elemNoteId: string): void {
 		const elemNote = this.reader.getElementById(elemNoteId);
 		if (!elemNote) {
+			// maybe in a page of the continuous document not loaded yet
+			const stitchedUrl = this.reader.stitchedUrl(elemNoteId);
+			if (stitchedUrl) {
+				this.previewNote(stitchedUrl);
+				return;
+			}
+			// maybe in another part of a split document
+			const url = URL.parse(`#${elemNoteId}`, this.pageUrl);
+			if (this.pagePart && this.pagePart.count > 1 && url) {
+				this.jumpTo(url);
+			}
 			return;
 		}
 		const result = this.createSamePageLocationPreviewContent(anchor, elemNote);
@@ -1,5 +1,14 @@
-import { clamp, FilewiseStylesKey, FilewiseStyles, FontPrefer } from "./base";
+import { clamp, FilewiseStylesKey, FilewiseStyles, FontPrefer, Theme } from "./base";
 import { getGlobalContext } from "./context";
+import * as rs from "./invoke";
+
+/**
+ * Background and foreground colors of themes. Keep in sync with `Palette` in `alter/color.rs`.
+ */
+const THEME_PALETTES: Record<NonNullable<Theme>, [string, string]> = {
+	sepia: ["#f4ecd8", "#5b4636"],
+	dark: ["#1e1e1e", "#d8d8d8"],
+};
 
 export class Styler {
 	#readerRoot: ShadowRoot;
@@ -29,8 +38,14 @@ export class Styler {
 		const prefs = getGlobalContext().prefsStore;
 		if (!prefs) return;
 
-		const fontSubstitute = await prefs.get<Record<string, string>>("font.substitute");
+		let fontSubstitute: Record<string, string> | undefined;
+		try {
+			fontSubstitute = await rs.getFontSubstitutes();
+		} catch (err) {
+			console.error("Error loading font substitutes:", err);
+		}
 		const fontPrefer = await prefs.get<FontPrefer>("font.prefer");
+		const theme = await prefs.get<Theme>("content.theme");
 
 		let css = ":host {";
 
@@ -64,11 +79,20 @@ export class Styler {
 			css += `font-family: initial;\n`;
 		}
 
+		// theme: the backend remaps publisher colors to the same palette
+		if (theme) {
+			const [background, foreground] = THEME_PALETTES[theme];
+			css += `background-color: ${background};\n`;
+			css += `color: ${foreground};\n`;
+		}
+
 		// builtin
 		css += `
       img { max-width: 100%; }
 			a { text-decoration: none; }
 			.og-attention { background-color: #fbe54e44; }
+			rt[data-og-ruby] { user-select: none; }
+			b.og-fix { font-weight: bold; }
 		`;
 
 		css += "}";
@@ -0,0 +1,144 @@
+#!/usr/bin/env python3
+"""Writes the Simplified and Traditional dictionaries of src-tauri/src/alter/opencc from
+the CLDR transform between them, common/transforms/Simplified-Traditional.xml.
+
+Usage: cldr-chinese.py Simplified-Traditional.xml
+
+Traditional forms are as in OpenCC: those of TWVariants.txt are taken back from the forms
+of Taiwan that CLDR uses, e.g., 為 to 爲, unless the OpenCC form is another word, e.g., 纔
+for 才. Entries already in the files that CLDR lacks, e.g., 乾隆, are kept.
+"""
+
+import html
+import re
+import sys
+from pathlib import Path
+
+OPENCC_DIR = Path(__file__).parent.parent / "src-tauri" / "src" / "alter" / "opencc"
+
+# OpenCC forms in TWVariants.txt that are words of their own, not other glyphs
+OTHER_WORDS = {"纔", "覈", "喫", "鍼"}
+
+ARROWS = ("↔", "→", "←")
+
+
+def read_rules(path):
+    text = Path(path).read_text(encoding="utf-8")
+    rules = re.findall(r"<tRule>(.*?)</tRule>", text, re.S)
+    text = html.unescape("\n".join(rules)) if rules else text
+    text = "\n".join(line.split("#")[0] for line in text.splitlines())
+    return [rule.strip() for rule in text.split(";") if rule.strip()]
+
+
+def expand(pattern, variables):
+    """Strings a context matches: literal text, a `$variable` or a `[set]`."""
+    pattern = pattern.strip()
+    if not pattern:
+        return [""]
+    if pattern.startswith("$"):
+        pattern = variables[pattern[1:].strip()]
+    if pattern.startswith("[") and pattern.endswith("]"):
+        return list(pattern[1:-1])
+    return [pattern]
+
+
+def split_context(side):
+    """`before { key } after` as its three parts."""
+    before, _, rest = side.rpartition("{") if "{" in side else ("", "", side)
+    key, _, after = rest.partition("}")
+    return before, key.strip(), after
+
+
+def parse(rules):
+    """Entries Simplified to Traditional and back, each as (before, key, after, value)."""
+    variables = {}
+    forward, backward = [], []
+    for rule in rules:
+        if rule.startswith("::"):
+            continue
+        if rule.startswith("$") and "=" in rule:
+            name, _, value = rule.partition("=")
+            variables[name[1:].strip()] = value.strip()
+            continue
+        arrow = next(a for a in ARROWS if a in rule)
+        left, right = (side.strip() for side in rule.split(arrow))
+        if arrow in "↔→":
+            forward.append((*split_context(left), right))
+        if arrow in "↔←":
+            backward.append((*split_context(right), left))
+    return variables, forward, backward
+
+
+def build(entries, variables, convert=None):
+    """Key to values, the first being the default, with contexts spelled out."""
+    table = {}
+    for before, key, after, value in entries:
+        for b in expand(before, variables):
+            for a in expand(after, variables):
+                k = b + key + a
+                v = value
+                if b or a:
+                    v = convert(b) + value + convert(a)
+                values = table.setdefault(k, [])
+                if v not in values:
+                    values.append(v)
+    return table
+
+
+def char_converter(table):
+    return lambda text: "".join(table.get(c, [c])[0] for c in text)
+
+
+def read_variants(name):
+    pairs = []
+    for line in (OPENCC_DIR / name).read_text(encoding="utf-8").splitlines():
+        if "\t" in line:
+            key, values = line.split("\t")
+            pairs.append((key, values.split(" ")[0]))
+    return pairs
+
+
+def write(name, table):
+    path = OPENCC_DIR / name
+    if path.exists():
+        for line in path.read_text(encoding="utf-8").splitlines():
+            if "\t" in line:
+                key, values = line.split("\t")
+                table.setdefault(key, values.split(" "))
+    lines = [f"{k}\t{' '.join(v)}" for k, v in sorted(table.items())]
+    path.write_text("\n".join(lines) + "\n", encoding="utf-8")
+
+
+def main():
+    variables, forward, backward = parse(read_rules(sys.argv[1]))
+
+    # Taiwan forms back to the OpenCC standard, unless the Simplified text has them too
+    standard = {
+        tw: std
+        for std, tw in read_variants("TWVariants.txt")
+        if len(std) == 1 and std not in OTHER_WORDS
+    }
+
+    def to_standard(key, value):
+        return "".join(
+            standard.get(c, c) if c not in key else c for c in value
+        )
+
+    chars = build([e for e in forward if not e[0] and not e[2] and len(e[1]) == 1], variables)
+    st = build(forward, variables, char_converter(chars))
+    st = {k: list(dict.fromkeys(to_standard(k, v) for v in vs)) for k, vs in st.items()}
+
+    ts_chars = build([e for e in backward if not e[0] and not e[2] and len(e[1]) == 1], variables)
+    ts = build(backward, variables, char_converter(ts_chars))
+    for tw, std in standard.items():
+        if tw in ts and std not in ts:
+            ts[std] = ts[tw]
+
+    write("STCharacters.txt", {k: v for k, v in st.items() if len(k) == 1})
+    write("STPhrases.txt", {k: v for k, v in st.items() if len(k) > 1})
+    write("TSCharacters.txt", {k: v for k, v in ts.items() if len(k) == 1})
+    write("TSPhrases.txt", {k: v for k, v in ts.items() if len(k) > 1})
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,68 @@
+#!/usr/bin/env python3
+"""Writes src-tauri/src/alter/readings/pinyin.txt from the CLDR transform from Han
+characters to pinyin, common/transforms/Han-Latin.xml.
+
+Usage: cldr-pinyin.py Han-Latin.xml
+
+Each character gets the first reading that the transform gives it, and words of its context
+rules, e.g., 重庆, get theirs. Entries already in the file win, so that words CLDR lacks,
+e.g., 银行, and characters whose first reading is not the most common, e.g., 长, keep
+theirs.
+"""
+
+import html
+import re
+import sys
+from pathlib import Path
+
+PINYIN = Path(__file__).parent.parent / "src-tauri" / "src" / "alter" / "readings" / "pinyin.txt"
+
+
+def read_rules(path):
+    text = Path(path).read_text(encoding="utf-8")
+    rules = re.findall(r"<tRule>(.*?)</tRule>", text, re.S)
+    text = html.unescape("\n".join(rules)) if rules else text
+    text = "\n".join(line.split("#")[0] for line in text.splitlines())
+    return [rule.strip() for rule in text.split(";") if rule.strip()]
+
+
+def parse(rules):
+    """Readings of characters, and of the words of context rules as (word, first reading)."""
+    chars, words = {}, []
+    for rule in rules:
+        if rule.startswith("::") or "→" not in rule:
+            continue
+        left, _, reading = (side.strip() for side in rule.partition("→"))
+        if "}" in left:
+            key, _, after = left.partition("}")
+            after = after.replace("\\u0020?", "").strip()
+            words.append((key.strip() + after, reading))
+        elif left.startswith("[") and left.endswith("]"):
+            for c in left[1:-1]:
+                chars.setdefault(c, reading)
+        else:
+            chars.setdefault(left, reading)
+    return chars, words
+
+
+def main():
+    chars, words = parse(read_rules(sys.argv[1]))
+
+    table = dict(chars)
+    for word, first in words:
+        rest = [chars.get(c) for c in word[1:]]
+        if None not in rest:
+            table[word] = " ".join([first, *rest])
+
+    if PINYIN.exists():
+        for line in PINYIN.read_text(encoding="utf-8").splitlines():
+            if "\t" in line:
+                key, reading = line.split("\t")
+                table[key] = reading
+
+    lines = [f"{k}\t{v}" for k, v in sorted(table.items())]
+    PINYIN.write_text("\n".join(lines) + "\n", encoding="utf-8")
+
+
+if __name__ == "__main__":
+    main()